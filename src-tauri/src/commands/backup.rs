@@ -0,0 +1,242 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+
+use crate::db;
+use crate::rag;
+
+const STORE_PATH: &str = "settings.json";
+
+/// Bump when the archive layout changes; newer archives are refused on restore
+const BACKUP_SCHEMA_VERSION: u32 = 2;
+const BACKUP_MANIFEST: &str = "manifest.json";
+const BACKUP_DATABASE: &str = "database.json";
+const BACKUP_SETTINGS: &str = "settings.json";
+const BACKUP_BUCKETS_DIR: &str = "buckets";
+/// Message images, added in schema version 2
+const BACKUP_IMAGES_DIR: &str = "images";
+const API_KEY_PREFIX: &str = "api_key_";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub schema_version: u32,
+    pub app_version: String,
+    pub created_at: String,
+    pub includes_api_keys: bool,
+}
+
+fn read_settings(app: &AppHandle, include_api_keys: bool) -> Result<Map<String, Value>> {
+    let store = app.store(STORE_PATH)?;
+    Ok(store
+        .entries()
+        .into_iter()
+        .filter(|(key, _)| include_api_keys || !key.starts_with(API_KEY_PREFIX))
+        .collect())
+}
+
+/// Every file under `dir`, paired with its `/`-separated path relative to `dir`
+fn collect_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                let name = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((name, path));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+async fn write_backup(app: &AppHandle, file_path: &Path, include_api_keys: bool) -> Result<BackupManifest> {
+    let manifest = BackupManifest {
+        schema_version: BACKUP_SCHEMA_VERSION,
+        app_version: app.package_info().version.to_string(),
+        created_at: Utc::now().to_rfc3339(),
+        includes_api_keys: include_api_keys,
+    };
+    let database = db::export_database(app).await?;
+    let settings = read_settings(app, include_api_keys)?;
+
+    let file = fs::File::create(file_path)?;
+    let mut archive = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default();
+
+    archive.start_file(BACKUP_MANIFEST, options)?;
+    archive.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    archive.start_file(BACKUP_DATABASE, options)?;
+    archive.write_all(&database)?;
+    archive.start_file(BACKUP_SETTINGS, options)?;
+    archive.write_all(&serde_json::to_vec_pretty(&settings)?)?;
+
+    for (name, path) in collect_files(&rag::buckets_dir(app))? {
+        archive.start_file(format!("{}/{}", BACKUP_BUCKETS_DIR, name), options)?;
+        std::io::copy(&mut fs::File::open(&path)?, &mut archive)?;
+    }
+    for (name, path) in collect_files(&db::images_dir(app))? {
+        archive.start_file(format!("{}/{}", BACKUP_IMAGES_DIR, name), options)?;
+        std::io::copy(&mut fs::File::open(&path)?, &mut archive)?;
+    }
+
+    archive.finish()?;
+    Ok(manifest)
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Drop the cached search index of every bucket directory under `buckets_dir`
+fn invalidate_bucket_indexes(buckets_dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(buckets_dir)? {
+        rag::invalidate_search_index(&entry?.path())?;
+    }
+    Ok(())
+}
+
+/// Write every archive entry under `prefix` to the same relative path in `dir`
+fn extract_dir(archive: &mut zip::ZipArchive<fs::File>, prefix: &str, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        // enclosed_name rejects absolute paths and `..` so entries can't escape the data dir
+        let Some(relative) = entry.enclosed_name()
+            .and_then(|name| name.strip_prefix(prefix).ok().map(Path::to_path_buf))
+        else {
+            continue;
+        };
+
+        let destination = dir.join(relative);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut fs::File::create(&destination)?)?;
+    }
+
+    Ok(())
+}
+
+/// Replace every bucket chunk store with the ones in the archive
+fn restore_buckets(archive: &mut zip::ZipArchive<fs::File>, buckets_dir: &Path) -> Result<()> {
+    if buckets_dir.exists() {
+        invalidate_bucket_indexes(buckets_dir)?;
+        fs::remove_dir_all(buckets_dir)?;
+    }
+    extract_dir(archive, BACKUP_BUCKETS_DIR, buckets_dir)?;
+
+    // A search during the restore may have started indexing a half-written store
+    invalidate_bucket_indexes(buckets_dir)?;
+
+    Ok(())
+}
+
+/// Swap in the archived settings, keeping the current API keys if the backup left them out
+fn restore_settings(app: &AppHandle, settings: Map<String, Value>, keep_api_keys: bool) -> Result<()> {
+    let store = app.store(STORE_PATH)?;
+    let kept: Vec<(String, Value)> = if keep_api_keys {
+        store.entries()
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(API_KEY_PREFIX))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    store.clear();
+    for (key, value) in settings.into_iter().chain(kept) {
+        store.set(key, value);
+    }
+    store.save()?;
+    Ok(())
+}
+
+/// Bundle the database, message images, every bucket chunk store and the settings into
+/// one zip archive.
+/// API keys are left out unless `include_api_keys` is set.
+#[tauri::command]
+pub async fn backup_all(
+    app: AppHandle,
+    file_path: String,
+    include_api_keys: Option<bool>,
+) -> Result<BackupManifest, String> {
+    write_backup(&app, Path::new(&file_path), include_api_keys.unwrap_or(false)).await
+        .map_err(|e| format!("Failed to create backup: {}", e))
+}
+
+/// Restore everything from a `backup_all` archive, replacing the current data.
+/// A full copy of the current data is saved under `backups/` in the app data dir first.
+#[tauri::command]
+pub async fn restore_all(app: AppHandle, file_path: String) -> Result<BackupManifest, String> {
+    let file = fs::File::open(&file_path)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("Invalid backup archive: {}", e))?;
+
+    let manifest: BackupManifest = read_entry(&mut archive, BACKUP_MANIFEST)
+        .and_then(|content| Ok(serde_json::from_slice(&content)?))
+        .map_err(|e| format!("Invalid backup manifest: {}", e))?;
+
+    if manifest.schema_version > BACKUP_SCHEMA_VERSION {
+        return Err(format!(
+            "This backup was made by a newer version of OmniChat (format {}); please update to restore it",
+            manifest.schema_version
+        ));
+    }
+
+    // Read and validate everything before touching the current data
+    let database = read_entry(&mut archive, BACKUP_DATABASE)
+        .map_err(|e| format!("Backup is missing the database: {}", e))?;
+    let settings: Map<String, Value> = read_entry(&mut archive, BACKUP_SETTINGS)
+        .and_then(|content| Ok(serde_json::from_slice(&content)?))
+        .map_err(|e| format!("Invalid settings in backup: {}", e))?;
+
+    let app_dir = app.path().app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    let safety_dir = app_dir.join("backups");
+    fs::create_dir_all(&safety_dir)
+        .map_err(|e| format!("Failed to create backups directory: {}", e))?;
+    let safety_path = safety_dir.join(format!("pre-restore-{}.zip", Utc::now().format("%Y%m%d-%H%M%S")));
+    write_backup(&app, &safety_path, true).await
+        .map_err(|e| format!("Failed to back up current data before restoring: {}", e))?;
+    println!("[BACKUP] Saved current data to {}", safety_path.display());
+
+    // Images are added alongside the current ones rather than replacing them, since the
+    // import may move inline images from an older backup into the same directory.
+    // Ones the restored database doesn't use are removed on the next startup.
+    extract_dir(&mut archive, BACKUP_IMAGES_DIR, &db::images_dir(&app))
+        .map_err(|e| format!("Failed to restore images: {}", e))?;
+    db::import_database(&app, &database).await
+        .map_err(|e| format!("Failed to restore database: {}", e))?;
+    restore_buckets(&mut archive, &rag::buckets_dir(&app))
+        .map_err(|e| format!("Failed to restore knowledge bases: {}", e))?;
+    restore_settings(&app, settings, !manifest.includes_api_keys)
+        .map_err(|e| format!("Failed to restore settings: {}", e))?;
+
+    Ok(manifest)
+}
@@ -2,18 +2,50 @@ use serde::{Deserialize, Serialize};
 use tauri::AppHandle;
 use uuid::Uuid;
 use chrono::Utc;
+use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
 use crate::db;
 use crate::rag;
+use crate::rag::{ChunkStrategy, EmbeddingModelChoice, EmbeddingModelInfo, TextSource};
+
+const DEFAULT_CHUNK_SIZE: usize = 500;
+const DEFAULT_CHUNK_OVERLAP: usize = 50;
+/// Number of trailing sentences repeated at the start of the next chunk
+const SENTENCE_OVERLAP: usize = 1;
+/// Limits for pages fetched by `ingest_url`
+const MAX_URL_BYTES: usize = 10 * 1024 * 1024;
+const MAX_URL_REDIRECTS: usize = 10;
+const URL_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn default_chunk_size() -> usize {
+    DEFAULT_CHUNK_SIZE
+}
+
+fn default_chunk_overlap() -> usize {
+    DEFAULT_CHUNK_OVERLAP
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Bucket {
     pub id: String,
     pub name: String,
     pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
     pub created_at: String,
     pub file_count: i32,
+    /// In words, or in tokens when `chunk_strategy` is `Tokens`
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+    #[serde(default = "default_chunk_overlap")]
+    pub chunk_overlap: usize,
+    #[serde(default)]
+    pub chunk_strategy: ChunkStrategy,
+    /// Model the bucket's chunks were embedded with; queries must use the same one
+    #[serde(default)]
+    pub embedding_model: EmbeddingModelChoice,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,13 +57,60 @@ pub struct BucketFile {
     pub file_size: i64,
     pub chunk_count: i32,
     pub created_at: String,
+    /// Chunks skipped at upload because the same content was already stored for this file
+    #[serde(default)]
+    pub duplicate_chunks: i32,
+    /// Whether the text was read from the document or recovered with OCR
+    #[serde(default)]
+    pub text_source: TextSource,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResult {
     pub content: String,
     pub filename: String,
+    pub bucket_id: String,
     pub score: f32,
+    /// Cross-encoder relevance score, set when results were reranked
+    #[serde(default)]
+    pub rerank_score: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileChunkStats {
+    pub filename: String,
+    /// Chunks found in the store for this file
+    pub chunk_count: usize,
+    /// Chunk count recorded in the file's metadata
+    pub recorded_chunk_count: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BucketStats {
+    pub bucket_id: String,
+    pub file_count: usize,
+    pub chunk_count: usize,
+    pub store_bytes: u64,
+    pub average_chunk_words: f32,
+    pub embedding_model: EmbeddingModelChoice,
+    /// Dimension of the stored vectors, or the model's dimension when the store is empty
+    pub embedding_dimensions: usize,
+    pub files: Vec<FileChunkStats>,
+}
+
+/// Bucket export archive layout version
+const BUCKET_EXPORT_VERSION: u32 = 1;
+const BUCKET_EXPORT_MANIFEST: &str = "bucket.json";
+const BUCKET_EXPORT_CHUNKS: &str = "chunks.bin";
+
+/// Bucket metadata stored alongside the chunk store in an export archive
+#[derive(Debug, Serialize, Deserialize)]
+struct BucketExport {
+    version: u32,
+    exported_at: String,
+    bucket: Bucket,
+    files: Vec<BucketFile>,
+    embedding_dimensions: usize,
 }
 
 #[tauri::command]
@@ -39,7 +118,24 @@ pub async fn create_bucket(
     app: AppHandle,
     name: String,
     description: String,
+    chunk_size: Option<usize>,
+    chunk_overlap: Option<usize>,
+    chunk_strategy: Option<ChunkStrategy>,
+    embedding_model: Option<EmbeddingModelChoice>,
 ) -> Result<Bucket, String> {
+    let chunk_size = chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+    let chunk_overlap = chunk_overlap.unwrap_or(DEFAULT_CHUNK_OVERLAP);
+
+    if chunk_size == 0 {
+        return Err("Chunk size must be greater than zero".to_string());
+    }
+    if chunk_overlap >= chunk_size {
+        return Err(format!(
+            "Chunk overlap ({}) must be less than chunk size ({})",
+            chunk_overlap, chunk_size
+        ));
+    }
+
     let id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
     
@@ -47,8 +143,14 @@ pub async fn create_bucket(
         id,
         name,
         description,
+        tags: Vec::new(),
         created_at: now,
         file_count: 0,
+        chunk_size,
+        chunk_overlap,
+        // Existing buckets without a strategy keep word chunking; new ones count tokens
+        chunk_strategy: chunk_strategy.unwrap_or(ChunkStrategy::Tokens),
+        embedding_model: embedding_model.unwrap_or_default(),
     };
     
     db::create_bucket(&app, &bucket).await
@@ -61,6 +163,12 @@ pub async fn create_bucket(
     Ok(bucket)
 }
 
+/// List the local embedding models a bucket can use
+#[tauri::command]
+pub async fn get_embedding_models() -> Result<Vec<EmbeddingModelInfo>, String> {
+    Ok(EmbeddingModelChoice::ALL.iter().map(|model| model.info()).collect())
+}
+
 #[tauri::command]
 pub async fn delete_bucket(app: AppHandle, bucket_id: String) -> Result<(), String> {
     // Delete from database
@@ -80,12 +188,41 @@ pub async fn get_buckets(app: AppHandle) -> Result<Vec<Bucket>, String> {
         .map_err(|e| format!("Failed to get buckets: {}", e))
 }
 
+#[tauri::command]
+pub async fn update_bucket(
+    app: AppHandle,
+    bucket_id: String,
+    name: String,
+    description: String,
+) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Bucket name cannot be empty".to_string());
+    }
+
+    db::update_bucket(&app, &bucket_id, name, description.trim()).await
+        .map_err(|e| format!("Failed to update bucket: {}", e))
+}
+
+#[tauri::command]
+pub async fn update_bucket_tags(app: AppHandle, bucket_id: String, tags: Vec<String>) -> Result<(), String> {
+    db::update_bucket_tags(&app, &bucket_id, &tags).await
+        .map_err(|e| format!("Failed to update bucket tags: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_buckets_by_tag(app: AppHandle, tag: String) -> Result<Vec<Bucket>, String> {
+    db::get_buckets_by_tag(&app, tag.trim()).await
+        .map_err(|e| format!("Failed to get buckets: {}", e))
+}
+
 #[tauri::command]
 pub async fn upload_file(
     app: AppHandle,
     bucket_id: String,
     file_path: String,
     api_key: String,
+    ocr: Option<bool>,
 ) -> Result<BucketFile, String> {
     println!("[RAG] Starting file upload: {}", file_path);
     
@@ -111,28 +248,85 @@ pub async fn upload_file(
     let file_type = match extension.as_str() {
         "pdf" => "pdf",
         "docx" | "doc" => "docx",
+        "pptx" => "pptx",
+        "xlsx" => "xlsx",
         "txt" | "md" => "txt",
+        "csv" => "csv",
+        "html" | "htm" => "html",
         _ => return Err(format!("Unsupported file type: {}", extension)),
     };
     
     println!("[RAG] File type detected: {}", file_type);
     
     // Read and parse file
-    let content = rag::parse_file(&path, file_type)
-        .map_err(|e| format!("Failed to parse file: {}", e))?;
+    let parsed = rag::parse_file(&path, file_type);
+    let scanned_pdf = file_type == "pdf" && parsed.as_ref().map_or(true, |text| rag::needs_ocr(text));
+
+    // OCR is slow, so it only runs when asked for and the PDF has no usable text layer
+    let (content, text_source) = if scanned_pdf && ocr.unwrap_or(false) {
+        match &parsed {
+            Ok(_) => println!("[RAG] PDF has little or no text layer, falling back to OCR"),
+            Err(e) => println!("[RAG] PDF text extraction failed ({}), falling back to OCR", e),
+        }
+        let ocr_path = path.clone();
+        let text = tauri::async_runtime::spawn_blocking(move || rag::ocr_pdf(&ocr_path))
+            .await
+            .map_err(|e| format!("OCR task failed: {}", e))?
+            .map_err(|e| format!("OCR failed: {}", e))?;
+        (text, TextSource::Ocr)
+    } else {
+        let text = parsed.map_err(|e| format!("Failed to parse file: {}", e))?;
+        (text, TextSource::Native)
+    };
     
-    println!("[RAG] Parsed content length: {} characters", content.len());
+    println!("[RAG] Parsed content length: {} characters ({:?})", content.len(), text_source);
     
     if content.trim().is_empty() {
-        return Err("File appears to be empty or could not extract text. For PDFs, ensure the file contains actual text (not just images).".to_string());
+        return Err(if scanned_pdf && text_source == TextSource::Native {
+            "Could not extract text from this PDF. It appears to be scanned; retry with OCR enabled.".to_string()
+        } else {
+            "File appears to be empty or could not extract text.".to_string()
+        });
     }
     
     // Get file size
     let metadata = std::fs::metadata(&path)
         .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-    
-    // Chunk the content
-    let chunks = rag::chunk_text(&content, 500, 50);
+
+    index_content(&app, &bucket_id, filename, file_type, metadata.len() as i64, &content, text_source, &api_key).await
+}
+
+/// Chunk, embed and record extracted text as a file in the bucket
+#[allow(clippy::too_many_arguments)]
+async fn index_content(
+    app: &AppHandle,
+    bucket_id: &str,
+    filename: String,
+    file_type: &str,
+    file_size: i64,
+    content: &str,
+    text_source: TextSource,
+    api_key: &str,
+) -> Result<BucketFile, String> {
+    // Chunk the content using the bucket's settings
+    let bucket = db::get_bucket(app, bucket_id).await
+        .map_err(|e| format!("Failed to get bucket: {}", e))?;
+
+    if bucket.chunk_overlap >= bucket.chunk_size {
+        return Err(format!(
+            "Bucket chunk overlap ({}) must be less than chunk size ({})",
+            bucket.chunk_overlap, bucket.chunk_size
+        ));
+    }
+
+    let chunks = match bucket.chunk_strategy {
+        ChunkStrategy::Words => rag::chunk_text(content, bucket.chunk_size, bucket.chunk_overlap),
+        ChunkStrategy::Sentences => rag::chunk_text_sentences(content, bucket.chunk_size, SENTENCE_OVERLAP),
+        ChunkStrategy::Tokens => {
+            rag::chunk_text_tokens(content, bucket.embedding_model, bucket.chunk_size, bucket.chunk_overlap)
+                .map_err(|e| format!("Failed to chunk content: {}", e))?
+        }
+    };
     
     println!("[RAG] Created {} chunks", chunks.len());
     
@@ -141,11 +335,14 @@ pub async fn upload_file(
     }
     
     // Generate embeddings and store
-    let chunk_count = chunks.len() as i32;
-    
-    println!("[RAG] Generating embeddings via OpenAI...");
-    rag::store_chunks(&app, &bucket_id, &filename, &chunks, &api_key).await
+    println!("[RAG] Generating embeddings locally with {}...", bucket.embedding_model.name());
+    let skipped = rag::store_chunks(app, bucket_id, &filename, &chunks, bucket.embedding_model, api_key).await
         .map_err(|e| format!("Failed to generate embeddings: {}", e))?;
+
+    let chunk_count = (chunks.len() - skipped) as i32;
+    if chunk_count == 0 {
+        return Err(format!("{} is already in this bucket.", filename));
+    }
     
     println!("[RAG] Embeddings stored successfully");
     
@@ -155,26 +352,97 @@ pub async fn upload_file(
     
     let bucket_file = BucketFile {
         id: file_id,
-        bucket_id: bucket_id.clone(),
+        bucket_id: bucket_id.to_string(),
         filename,
         file_type: file_type.to_string(),
-        file_size: metadata.len() as i64,
+        file_size,
         chunk_count,
         created_at: now,
+        duplicate_chunks: skipped as i32,
+        text_source,
     };
     
-    db::create_bucket_file(&app, &bucket_file).await
+    db::create_bucket_file(app, &bucket_file).await
         .map_err(|e| format!("Failed to save file metadata: {}", e))?;
     
     // Update bucket file count
-    db::update_bucket_file_count(&app, &bucket_id).await
+    db::update_bucket_file_count(app, bucket_id).await
         .map_err(|e| format!("Failed to update bucket: {}", e))?;
     
-    println!("[RAG] File upload complete: {} chunks indexed", chunk_count);
+    println!("[RAG] Indexing complete: {} chunks indexed", chunk_count);
     
     Ok(bucket_file)
 }
 
+/// Fetch a web page and index its readable text into the bucket, recording the URL as the filename
+#[tauri::command]
+pub async fn ingest_url(
+    app: AppHandle,
+    bucket_id: String,
+    url: String,
+) -> Result<BucketFile, String> {
+    let url = url.trim().to_string();
+    println!("[RAG] Fetching URL: {}", url);
+
+    let parsed = reqwest::Url::parse(&url)
+        .map_err(|e| format!("Invalid URL: {}", e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(format!("Unsupported URL scheme: {}", parsed.scheme()));
+    }
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::limited(MAX_URL_REDIRECTS))
+        .timeout(URL_FETCH_TIMEOUT)
+        .user_agent("OmniChat")
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let mut response = client.get(parsed).send().await
+        .map_err(|e| format!("Failed to fetch URL: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch URL: HTTP {}", response.status()));
+    }
+
+    if response.content_length().is_some_and(|len| len > MAX_URL_BYTES as u64) {
+        return Err(format!("Page is too large (limit is {} MB)", MAX_URL_BYTES / (1024 * 1024)));
+    }
+
+    if response.url().as_str() != url {
+        println!("[RAG] Redirected to: {}", response.url());
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    // Content-Length can be missing or wrong, so enforce the cap while reading
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await
+        .map_err(|e| format!("Failed to read response: {}", e))?
+    {
+        if bytes.len() + chunk.len() > MAX_URL_BYTES {
+            return Err(format!("Page is too large (limit is {} MB)", MAX_URL_BYTES / (1024 * 1024)));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let content = rag::parse_web_content(&bytes, &content_type)
+        .map_err(|e| format!("Failed to parse page: {}", e))?;
+
+    println!("[RAG] Parsed content length: {} characters", content.len());
+
+    if content.trim().is_empty() {
+        return Err("Page appears to be empty or no readable text could be extracted.".to_string());
+    }
+
+    // Embeddings are generated locally, so no API key is needed
+    index_content(&app, &bucket_id, url, "url", bytes.len() as i64, &content, TextSource::Native, "").await
+}
+
 #[tauri::command]
 pub async fn delete_file(
     app: AppHandle,
@@ -197,6 +465,177 @@ pub async fn delete_file(
     Ok(())
 }
 
+/// Remove duplicate chunks stored before uploads were deduplicated, returning how many were removed
+#[tauri::command]
+pub async fn dedupe_bucket(app: AppHandle, bucket_id: String) -> Result<usize, String> {
+    let (removed, counts) = rag::dedupe_chunks(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to dedupe bucket: {}", e))?;
+
+    if removed > 0 {
+        db::update_bucket_file_chunk_counts(&app, &bucket_id, &counts).await
+            .map_err(|e| format!("Failed to update bucket files: {}", e))?;
+    }
+
+    Ok(removed)
+}
+
+/// Re-embed every chunk in the bucket with another local model, without re-uploading files
+#[tauri::command]
+pub async fn reembed_bucket(
+    app: AppHandle,
+    bucket_id: String,
+    model: EmbeddingModelChoice,
+) -> Result<Bucket, String> {
+    let mut bucket = db::get_bucket(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket: {}", e))?;
+
+    let count = rag::reembed_chunks(&app, &bucket_id, model).await
+        .map_err(|e| format!("Failed to re-embed bucket: {}", e))?;
+
+    println!("[RAG] Re-embedded {} chunks in bucket {}", count, bucket_id);
+
+    bucket.embedding_model = model;
+    Ok(bucket)
+}
+
+/// Summarize a bucket's files and chunk store, for spotting empty or mismatched indexes
+#[tauri::command]
+pub async fn get_bucket_stats(app: AppHandle, bucket_id: String) -> Result<BucketStats, String> {
+    let bucket = db::get_bucket(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket: {}", e))?;
+    let bucket_files = db::get_bucket_files(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket files: {}", e))?;
+    let store = rag::chunk_store_stats(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to read chunk store: {}", e))?;
+
+    let files = bucket_files
+        .iter()
+        .map(|file| FileChunkStats {
+            filename: file.filename.clone(),
+            chunk_count: store.chunks_per_file.get(&file.filename).copied().unwrap_or(0),
+            recorded_chunk_count: file.chunk_count,
+        })
+        .collect();
+
+    Ok(BucketStats {
+        bucket_id,
+        file_count: bucket_files.len(),
+        chunk_count: store.chunk_count,
+        store_bytes: store.store_bytes,
+        average_chunk_words: store.average_chunk_words,
+        embedding_model: bucket.embedding_model,
+        embedding_dimensions: store.dimensions.unwrap_or_else(|| bucket.embedding_model.dimensions()),
+        files,
+    })
+}
+
+/// Write the bucket, its file records and its embedded chunks to a single zip archive
+#[tauri::command]
+pub async fn export_bucket(app: AppHandle, bucket_id: String, file_path: String) -> Result<(), String> {
+    let bucket = db::get_bucket(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket: {}", e))?;
+    let files = db::get_bucket_files(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket files: {}", e))?;
+    let chunks = rag::export_chunk_store(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to read chunk store: {}", e))?;
+
+    let manifest = BucketExport {
+        version: BUCKET_EXPORT_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        embedding_dimensions: bucket.embedding_model.dimensions(),
+        bucket,
+        files,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize bucket: {}", e))?;
+
+    write_bucket_archive(&file_path, &manifest, &chunks)
+        .map_err(|e| format!("Failed to write archive: {}", e))
+}
+
+fn write_bucket_archive(file_path: &str, manifest: &[u8], chunks: &[u8]) -> zip::result::ZipResult<()> {
+    let file = std::fs::File::create(file_path)?;
+    let mut archive = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default();
+
+    archive.start_file(BUCKET_EXPORT_MANIFEST, options)?;
+    archive.write_all(manifest)?;
+    archive.start_file(BUCKET_EXPORT_CHUNKS, options)?;
+    archive.write_all(chunks)?;
+    archive.finish()?;
+
+    Ok(())
+}
+
+/// Recreate a bucket from an `export_bucket` archive under a new id, reusing its embeddings
+#[tauri::command]
+pub async fn import_bucket(app: AppHandle, file_path: String) -> Result<Bucket, String> {
+    let file = std::fs::File::open(&file_path)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("Invalid bucket archive: {}", e))?;
+
+    let mut manifest = Vec::new();
+    archive.by_name(BUCKET_EXPORT_MANIFEST)
+        .map_err(|e| format!("Invalid bucket archive: {}", e))?
+        .read_to_end(&mut manifest)
+        .map_err(|e| format!("Failed to read archive: {}", e))?;
+    let export: BucketExport = serde_json::from_slice(&manifest)
+        .map_err(|e| format!("Invalid bucket metadata: {}", e))?;
+
+    if export.version > BUCKET_EXPORT_VERSION {
+        return Err(format!("Unsupported bucket export version: {}", export.version));
+    }
+
+    let model = export.bucket.embedding_model;
+    if export.embedding_dimensions != model.dimensions() {
+        return Err(format!(
+            "Archive declares {}-dimension embeddings but {} produces {}",
+            export.embedding_dimensions, model.name(), model.dimensions()
+        ));
+    }
+
+    let mut chunks = Vec::new();
+    archive.by_name(BUCKET_EXPORT_CHUNKS)
+        .map_err(|e| format!("Invalid bucket archive: {}", e))?
+        .read_to_end(&mut chunks)
+        .map_err(|e| format!("Failed to read archive: {}", e))?;
+
+    let bucket = Bucket {
+        id: Uuid::new_v4().to_string(),
+        created_at: Utc::now().to_rfc3339(),
+        file_count: export.files.len() as i32,
+        ..export.bucket
+    };
+
+    let chunk_count = rag::import_chunk_store(&app, &bucket.id, &chunks, model).await
+        .map_err(|e| format!("Failed to import chunk store: {}", e))?;
+
+    let saved = async {
+        db::create_bucket(&app, &bucket).await?;
+        for file in export.files {
+            let file = BucketFile {
+                id: Uuid::new_v4().to_string(),
+                bucket_id: bucket.id.clone(),
+                ..file
+            };
+            db::create_bucket_file(&app, &file).await?;
+        }
+        anyhow::Ok(())
+    }.await;
+
+    if let Err(e) = saved {
+        // Don't leave an orphaned chunk store behind
+        let _ = rag::delete_bucket_store(&app, &bucket.id).await;
+        let _ = db::delete_bucket(&app, &bucket.id).await;
+        return Err(format!("Failed to save imported bucket: {}", e));
+    }
+
+    println!("[RAG] Imported bucket {} with {} chunks", bucket.name, chunk_count);
+
+    Ok(bucket)
+}
+
 #[tauri::command]
 pub async fn get_bucket_files(app: AppHandle, bucket_id: String) -> Result<Vec<BucketFile>, String> {
     db::get_bucket_files(&app, &bucket_id).await
@@ -210,9 +649,40 @@ pub async fn search_bucket(
     query: String,
     api_key: String,
     top_k: Option<usize>,
+    min_score: Option<f32>,
+    rerank: Option<bool>,
 ) -> Result<Vec<SearchResult>, String> {
     let k = top_k.unwrap_or(5);
+    let bucket = db::get_bucket(&app, &bucket_id).await
+        .map_err(|e| format!("Failed to get bucket: {}", e))?;
     
-    rag::search(&app, &bucket_id, &query, &api_key, k).await
+    rag::search(&app, &bucket, &query, &api_key, k, min_score, rerank.unwrap_or(false)).await
         .map_err(|e| format!("Failed to search bucket: {}", e))
 }
+
+#[tauri::command]
+pub async fn search_buckets(
+    app: AppHandle,
+    bucket_ids: Vec<String>,
+    query: String,
+    top_k: Option<usize>,
+    min_score: Option<f32>,
+    rerank: Option<bool>,
+    max_context_chars: Option<usize>,
+) -> Result<Vec<SearchResult>, String> {
+    let k = top_k.unwrap_or(5);
+    let mut buckets = Vec::with_capacity(bucket_ids.len());
+    for bucket_id in &bucket_ids {
+        buckets.push(db::get_bucket(&app, bucket_id).await
+            .map_err(|e| format!("Failed to get bucket: {}", e))?);
+    }
+    
+    let results = rag::search_multi(&app, &buckets, &query, k, min_score, rerank.unwrap_or(false)).await
+        .map_err(|e| format!("Failed to search buckets: {}", e))?;
+
+    // With a budget, keep only the results that fit in an assembled context
+    Ok(match max_context_chars {
+        Some(max_chars) => rag::build_context(results, max_chars).1,
+        None => results,
+    })
+}
@@ -1,9 +1,22 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
 
 const LEMON_SQUEEZY_ACTIVATE_URL: &str = "https://api.lemonsqueezy.com/v1/licenses/activate";
 const LEMON_SQUEEZY_DEACTIVATE_URL: &str = "https://api.lemonsqueezy.com/v1/licenses/deactivate";
+const LEMON_SQUEEZY_VALIDATE_URL: &str = "https://api.lemonsqueezy.com/v1/licenses/validate";
 const OMNICHAT_PRODUCT_ID: u64 = 795978;
 
+const STORE_PATH: &str = "settings.json";
+const ACTIVATION_CACHE_KEY: &str = "license_activation";
+/// Days a cached activation stays valid without reaching the license server
+const DEFAULT_GRACE_PERIOD_DAYS: i64 = 7;
+/// Longest grace period a hand-edited setting can ask for
+const MAX_GRACE_PERIOD_DAYS: i64 = 365;
+
 #[derive(Debug, Serialize)]
 struct ActivateRequest {
     license_key: String,
@@ -16,6 +29,12 @@ struct DeactivateRequest {
     instance_id: String,
 }
 
+#[derive(Debug, Serialize)]
+struct ValidateRequest {
+    license_key: String,
+    instance_id: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct LemonSqueezyMeta {
     product_id: Option<u64>,
@@ -37,6 +56,7 @@ struct LemonSqueezyInstance {
 struct LemonSqueezyResponse {
     activated: Option<bool>,
     deactivated: Option<bool>,
+    valid: Option<bool>,
     error: Option<String>,
     license_key: Option<LemonSqueezyLicenseKey>,
     instance: Option<LemonSqueezyInstance>,
@@ -50,8 +70,130 @@ pub struct LicenseResult {
     pub instance_id: Option<String>,
 }
 
+/// Last successful activation, kept so the app can start offline
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedActivation {
+    key_hash: String,
+    instance_id: Option<String>,
+    validated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LicenseStatus {
+    pub valid: bool,
+    /// True when validity came from the cached activation rather than the license server
+    pub cached: bool,
+    pub validated_at: Option<String>,
+    pub message: String,
+}
+
+fn hash_license_key(license_key: &str) -> String {
+    format!("{:x}", Sha256::digest(license_key.trim().as_bytes()))
+}
+
+fn read_cached_activation(app: &AppHandle) -> Result<Option<CachedActivation>, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .get(ACTIVATION_CACHE_KEY)
+        .and_then(|value| serde_json::from_value(value).ok()))
+}
+
+fn write_cached_activation(app: &AppHandle, activation: Option<&CachedActivation>) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match activation {
+        Some(activation) => store.set(ACTIVATION_CACHE_KEY, json!(activation)),
+        None => {
+            store.delete(ACTIVATION_CACHE_KEY);
+        }
+    }
+
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))
+}
+
+fn grace_period(app: &AppHandle) -> Result<chrono::Duration, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    let days = store.get("license_grace_days").and_then(|v| v.as_i64());
+    Ok(grace_period_from_days(days))
+}
+
+/// The grace period for a configured number of days, clamped so a hand-edited
+/// settings file can't ask for a duration chrono can't represent
+fn grace_period_from_days(days: Option<i64>) -> chrono::Duration {
+    let days = days
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_GRACE_PERIOD_DAYS)
+        .min(MAX_GRACE_PERIOD_DAYS);
+    chrono::Duration::days(days)
+}
+
+/// Whether an activation last validated at `validated_at` can still be trusted offline
+fn is_within_grace(validated_at: DateTime<Utc>, now: DateTime<Utc>, grace: chrono::Duration) -> bool {
+    now - validated_at < grace
+}
+
+/// Whether a failed validation response is the license server rejecting the key itself,
+/// such as an unknown key or instance, rather than a transient error
+fn is_invalid_key_response(status: reqwest::StatusCode, data: &LemonSqueezyResponse) -> bool {
+    let rejects_key = matches!(
+        status,
+        reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::UNPROCESSABLE_ENTITY
+    );
+    rejects_key && data.valid == Some(false)
+}
+
+/// Ask the license server whether the activation is still valid.
+/// `Err` means the server couldn't be reached or understood, not that the license is invalid.
+async fn validate_online(license_key: &str, instance_id: &str) -> Result<(bool, String), String> {
+    let client = reqwest::Client::new();
+
+    let request = ValidateRequest {
+        license_key: license_key.trim().to_string(),
+        instance_id: instance_id.to_string(),
+    };
+
+    let response = client
+        .post(LEMON_SQUEEZY_VALIDATE_URL)
+        .header("Accept", "application/json")
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| format!("Network error: {}", e))?;
+
+    let status = response.status();
+    let parsed = response.json::<LemonSqueezyResponse>().await;
+
+    // Only the server's explicit verdict on the key may revoke the cached activation.
+    // Rate limiting and other failures are transient, so the grace period applies.
+    let data = match parsed {
+        Ok(data) if status.is_success() || is_invalid_key_response(status, &data) => data,
+        Err(e) if status.is_success() => return Err(format!("Failed to parse response: {}", e)),
+        _ => return Err(format!("License server error: {}", status)),
+    };
+
+    let product_id = data.meta.as_ref().and_then(|m| m.product_id)
+        .or_else(|| data.license_key.as_ref().and_then(|k| k.product_id));
+    if product_id.is_some_and(|pid| pid != OMNICHAT_PRODUCT_ID) {
+        return Ok((false, "This license key is not valid for OmniChat.".to_string()));
+    }
+
+    if data.valid == Some(true) {
+        return Ok((true, "License is valid.".to_string()));
+    }
+
+    let message = data.error.unwrap_or_else(|| "This license is no longer valid.".to_string());
+    Ok((false, message))
+}
+
 #[tauri::command]
-pub async fn activate_license(license_key: String, instance_name: String) -> Result<LicenseResult, String> {
+pub async fn activate_license(app: AppHandle, license_key: String, instance_name: String) -> Result<LicenseResult, String> {
     let client = reqwest::Client::new();
     
     let request = ActivateRequest {
@@ -90,6 +232,12 @@ pub async fn activate_license(license_key: String, instance_name: String) -> Res
         }
         
         let instance_id = data.instance.and_then(|i| i.id);
+
+        write_cached_activation(&app, Some(&CachedActivation {
+            key_hash: hash_license_key(&license_key),
+            instance_id: instance_id.clone(),
+            validated_at: Utc::now(),
+        }))?;
         
         return Ok(LicenseResult {
             success: true,
@@ -122,7 +270,7 @@ pub async fn activate_license(license_key: String, instance_name: String) -> Res
 }
 
 #[tauri::command]
-pub async fn deactivate_license(license_key: String, instance_id: String) -> Result<LicenseResult, String> {
+pub async fn deactivate_license(app: AppHandle, license_key: String, instance_id: String) -> Result<LicenseResult, String> {
     let client = reqwest::Client::new();
     
     let request = DeactivateRequest {
@@ -145,6 +293,7 @@ pub async fn deactivate_license(license_key: String, instance_id: String) -> Res
         .map_err(|e| format!("Failed to parse response: {}", e))?;
     
     if data.deactivated == Some(true) {
+        write_cached_activation(&app, None)?;
         return Ok(LicenseResult {
             success: true,
             message: "License deactivated. You can activate on another device.".to_string(),
@@ -160,3 +309,126 @@ pub async fn deactivate_license(license_key: String, instance_id: String) -> Res
         instance_id: None,
     })
 }
+
+/// Check the license, trusting the cached activation within the grace period and only
+/// revalidating online once it has lapsed or when `force` is set. A failed online check
+/// keeps the license valid while the cached activation is still within grace.
+#[tauri::command]
+pub async fn check_license_status(
+    app: AppHandle,
+    license_key: String,
+    force: Option<bool>,
+) -> Result<LicenseStatus, String> {
+    let cached = read_cached_activation(&app)?
+        .filter(|activation| activation.key_hash == hash_license_key(&license_key));
+
+    let Some(activation) = cached else {
+        return Ok(LicenseStatus {
+            valid: false,
+            cached: false,
+            validated_at: None,
+            message: "License is not activated on this device.".to_string(),
+        });
+    };
+
+    let within_grace = is_within_grace(activation.validated_at, Utc::now(), grace_period(&app)?);
+
+    if within_grace && !force.unwrap_or(false) {
+        return Ok(LicenseStatus {
+            valid: true,
+            cached: true,
+            validated_at: Some(activation.validated_at.to_rfc3339()),
+            message: "License is valid.".to_string(),
+        });
+    }
+
+    let instance_id = activation.instance_id.clone().unwrap_or_default();
+    match validate_online(&license_key, &instance_id).await {
+        Ok((true, message)) => {
+            let validated_at = Utc::now();
+            write_cached_activation(&app, Some(&CachedActivation {
+                validated_at,
+                ..activation
+            }))?;
+            Ok(LicenseStatus {
+                valid: true,
+                cached: false,
+                validated_at: Some(validated_at.to_rfc3339()),
+                message,
+            })
+        }
+        Ok((false, message)) => {
+            write_cached_activation(&app, None)?;
+            Ok(LicenseStatus {
+                valid: false,
+                cached: false,
+                validated_at: None,
+                message,
+            })
+        }
+        Err(e) => {
+            println!("[LICENSE] Online re-check failed: {}", e);
+            Ok(LicenseStatus {
+                valid: within_grace,
+                cached: true,
+                validated_at: Some(activation.validated_at.to_rfc3339()),
+                message: if within_grace {
+                    "License is valid (offline).".to_string()
+                } else {
+                    format!("Couldn't reach the license server to renew your license: {}", e)
+                },
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    fn response(body: serde_json::Value) -> LemonSqueezyResponse {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn explicit_invalid_key_responses_are_rejections() {
+        let invalid = response(json!({ "valid": false, "error": "license_key not found." }));
+
+        for status in [StatusCode::BAD_REQUEST, StatusCode::NOT_FOUND, StatusCode::UNPROCESSABLE_ENTITY] {
+            assert!(is_invalid_key_response(status, &invalid), "{}", status);
+        }
+    }
+
+    #[test]
+    fn other_failures_are_transient() {
+        let invalid = response(json!({ "valid": false }));
+        let no_verdict = response(json!({ "error": "Too many requests." }));
+
+        assert!(!is_invalid_key_response(StatusCode::TOO_MANY_REQUESTS, &invalid));
+        assert!(!is_invalid_key_response(StatusCode::INTERNAL_SERVER_ERROR, &invalid));
+        assert!(!is_invalid_key_response(StatusCode::BAD_GATEWAY, &invalid));
+        assert!(!is_invalid_key_response(StatusCode::NOT_FOUND, &no_verdict));
+        assert!(!is_invalid_key_response(StatusCode::BAD_REQUEST, &response(json!({ "valid": true }))));
+    }
+
+    #[test]
+    fn grace_period_falls_back_and_clamps() {
+        assert_eq!(grace_period_from_days(None), chrono::Duration::days(DEFAULT_GRACE_PERIOD_DAYS));
+        assert_eq!(grace_period_from_days(Some(-3)), chrono::Duration::days(DEFAULT_GRACE_PERIOD_DAYS));
+        assert_eq!(grace_period_from_days(Some(0)), chrono::Duration::zero());
+        assert_eq!(grace_period_from_days(Some(30)), chrono::Duration::days(30));
+        assert_eq!(grace_period_from_days(Some(i64::MAX)), chrono::Duration::days(MAX_GRACE_PERIOD_DAYS));
+    }
+
+    #[test]
+    fn activation_is_trusted_only_within_grace() {
+        let now = Utc::now();
+        let grace = chrono::Duration::days(7);
+
+        assert!(is_within_grace(now - chrono::Duration::days(6), now, grace));
+        assert!(!is_within_grace(now - chrono::Duration::days(7), now, grace));
+        assert!(!is_within_grace(now - chrono::Duration::days(30), now, grace));
+        assert!(!is_within_grace(now, now, chrono::Duration::zero()));
+    }
+}
@@ -3,3 +3,6 @@ pub mod settings;
 pub mod knowledge;
 pub mod speech;
 pub mod license;
+pub mod pricing;
+pub mod templates;
+pub mod backup;
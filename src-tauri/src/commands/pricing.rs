@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::db;
+
+const STORE_PATH: &str = "settings.json";
+const PRICING_TABLE_KEY: &str = "pricing_table";
+
+/// Rough characters-per-token ratio used when a message has no stored usage
+const CHARS_PER_TOKEN: usize = 4;
+
+/// USD price per 1K tokens for one model
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+/// Provider name -> model id (or model id prefix) -> price
+pub type PricingTable = HashMap<String, HashMap<String, ModelPrice>>;
+
+#[derive(Debug, Serialize)]
+pub struct MessageCost {
+    pub message_id: String,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub input_cost: f64,
+    pub output_cost: f64,
+    pub total_cost: f64,
+    /// Token counts were estimated from message length instead of stored usage
+    pub estimated: bool,
+    /// A price was found for the message's provider/model
+    pub priced: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationCost {
+    pub conversation_id: String,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub input_cost: f64,
+    pub output_cost: f64,
+    pub total_cost: f64,
+    pub messages: Vec<MessageCost>,
+}
+
+fn price(input_per_1k: f64, output_per_1k: f64) -> ModelPrice {
+    ModelPrice { input_per_1k, output_per_1k }
+}
+
+/// Built-in list prices; keep in sync with the providers' pricing pages
+fn default_pricing_table() -> PricingTable {
+    let mut table = PricingTable::new();
+
+    table.insert("openai".to_string(), HashMap::from([
+        ("gpt-4o".to_string(), price(0.0025, 0.01)),
+        ("gpt-4o-mini".to_string(), price(0.00015, 0.0006)),
+        ("gpt-4.1".to_string(), price(0.002, 0.008)),
+        ("gpt-4.1-mini".to_string(), price(0.0004, 0.0016)),
+        ("gpt-4.1-nano".to_string(), price(0.0001, 0.0004)),
+        ("gpt-4-turbo".to_string(), price(0.01, 0.03)),
+        ("gpt-3.5-turbo".to_string(), price(0.0005, 0.0015)),
+        // Variants are listed explicitly; prefix matching would bill them at the base model's rate
+        ("o1".to_string(), price(0.015, 0.06)),
+        ("o1-mini".to_string(), price(0.0011, 0.0044)),
+        ("o1-pro".to_string(), price(0.15, 0.6)),
+        ("o3".to_string(), price(0.002, 0.008)),
+        ("o3-mini".to_string(), price(0.0011, 0.0044)),
+        ("o4-mini".to_string(), price(0.0011, 0.0044)),
+    ]));
+
+    table.insert("anthropic".to_string(), HashMap::from([
+        ("claude-opus-4".to_string(), price(0.015, 0.075)),
+        ("claude-sonnet-4".to_string(), price(0.003, 0.015)),
+        ("claude-3-7-sonnet".to_string(), price(0.003, 0.015)),
+        ("claude-3-5-sonnet".to_string(), price(0.003, 0.015)),
+        ("claude-3-5-haiku".to_string(), price(0.0008, 0.004)),
+        ("claude-3-opus".to_string(), price(0.015, 0.075)),
+        ("claude-3-haiku".to_string(), price(0.00025, 0.00125)),
+    ]));
+
+    table.insert("gemini".to_string(), HashMap::from([
+        ("gemini-2.0-flash".to_string(), price(0.0001, 0.0004)),
+        ("gemini-1.5-pro".to_string(), price(0.00125, 0.005)),
+        ("gemini-1.5-flash".to_string(), price(0.000075, 0.0003)),
+    ]));
+
+    table.insert("deepseek".to_string(), HashMap::from([
+        ("deepseek-chat".to_string(), price(0.00027, 0.0011)),
+        ("deepseek-reasoner".to_string(), price(0.00055, 0.00219)),
+    ]));
+
+    table.insert("groq".to_string(), HashMap::from([
+        ("llama-3.3-70b-versatile".to_string(), price(0.00059, 0.00079)),
+        ("llama-3.1-8b-instant".to_string(), price(0.00005, 0.00008)),
+        ("gemma2-9b-it".to_string(), price(0.0002, 0.0002)),
+    ]));
+
+    table
+}
+
+fn read_pricing_overrides(app: &AppHandle) -> Result<PricingTable, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match store.get(PRICING_TABLE_KEY) {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| format!("Invalid pricing table setting: {}", e)),
+        None => Ok(PricingTable::new()),
+    }
+}
+
+/// Built-in prices with any user overrides applied on top
+fn effective_pricing_table(app: &AppHandle) -> Result<PricingTable, String> {
+    Ok(with_overrides(default_pricing_table(), read_pricing_overrides(app)?))
+}
+
+/// `table` with each override replacing or adding its model's price
+fn with_overrides(mut table: PricingTable, overrides: PricingTable) -> PricingTable {
+    for (provider, models) in overrides {
+        table.entry(provider).or_default().extend(models);
+    }
+    table
+}
+
+/// Find the price for a model, falling back to the longest matching prefix
+/// so dated ids like `gpt-4o-2024-08-06` use the `gpt-4o` entry
+fn lookup_price(table: &PricingTable, provider: &str, model: &str) -> Option<ModelPrice> {
+    let models = table.get(provider)?;
+    if let Some(price) = models.get(model) {
+        return Some(*price);
+    }
+
+    models
+        .iter()
+        .filter(|(id, _)| model.starts_with(id.as_str()))
+        .max_by_key(|(id, _)| id.len())
+        .map(|(_, price)| *price)
+}
+
+fn estimate_tokens(text: &str) -> u64 {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+#[tauri::command]
+pub async fn get_pricing_table(app: AppHandle) -> Result<PricingTable, String> {
+    effective_pricing_table(&app)
+}
+
+/// Replace the user's price overrides; an empty table restores the built-in prices
+#[tauri::command]
+pub async fn set_pricing_table(app: AppHandle, table: PricingTable) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    if table.is_empty() {
+        store.delete(PRICING_TABLE_KEY);
+    } else {
+        let value = serde_json::to_value(&table)
+            .map_err(|e| format!("Failed to serialize pricing table: {}", e))?;
+        store.set(PRICING_TABLE_KEY, value);
+    }
+
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))?;
+
+    Ok(())
+}
+
+/// Estimate what a conversation has cost so far, per assistant message.
+/// Messages without stored usage are estimated at ~4 characters per token,
+/// counting everything before them as input.
+#[tauri::command]
+pub async fn estimate_conversation_cost(
+    app: AppHandle,
+    conversation_id: String,
+) -> Result<ConversationCost, String> {
+    let table = effective_pricing_table(&app)?;
+    let messages = db::get_messages(&app, &conversation_id).await
+        .map_err(|e| format!("Failed to get messages: {}", e))?;
+
+    let mut breakdown = Vec::new();
+    let mut history_tokens: u64 = 0;
+
+    for message in &messages {
+        let content_tokens = estimate_tokens(&message.content);
+
+        if message.role == "assistant" {
+            let (input_tokens, output_tokens, estimated) = match &message.usage {
+                Some(usage) => (usage.prompt_tokens as u64, usage.completion_tokens as u64, false),
+                None => (history_tokens, content_tokens, true),
+            };
+
+            let model_price = lookup_price(&table, &message.provider, &message.model);
+            let (input_cost, output_cost) = model_price
+                .map(|p| (
+                    input_tokens as f64 / 1000.0 * p.input_per_1k,
+                    output_tokens as f64 / 1000.0 * p.output_per_1k,
+                ))
+                .unwrap_or((0.0, 0.0));
+
+            breakdown.push(MessageCost {
+                message_id: message.id.clone(),
+                provider: message.provider.clone(),
+                model: message.model.clone(),
+                input_tokens,
+                output_tokens,
+                input_cost,
+                output_cost,
+                total_cost: input_cost + output_cost,
+                estimated,
+                priced: model_price.is_some(),
+            });
+        }
+
+        history_tokens += content_tokens;
+    }
+
+    let input_tokens = breakdown.iter().map(|m| m.input_tokens).sum();
+    let output_tokens = breakdown.iter().map(|m| m.output_tokens).sum();
+    let input_cost: f64 = breakdown.iter().map(|m| m.input_cost).sum();
+    let output_cost: f64 = breakdown.iter().map(|m| m.output_cost).sum();
+
+    Ok(ConversationCost {
+        conversation_id,
+        input_tokens,
+        output_tokens,
+        input_cost,
+        output_cost,
+        total_cost: input_cost + output_cost,
+        messages: breakdown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(model: &str) -> Option<(f64, f64)> {
+        lookup_price(&default_pricing_table(), "openai", model).map(|p| (p.input_per_1k, p.output_per_1k))
+    }
+
+    #[test]
+    fn exact_model_id_uses_its_own_price() {
+        assert_eq!(prices("gpt-4o"), Some((0.0025, 0.01)));
+        assert_eq!(prices("gpt-4o-mini"), Some((0.00015, 0.0006)));
+    }
+
+    #[test]
+    fn dated_snapshot_uses_its_model_price() {
+        assert_eq!(prices("gpt-4o-2024-08-06"), Some((0.0025, 0.01)));
+        assert_eq!(prices("gpt-4o-mini-2024-07-18"), Some((0.00015, 0.0006)));
+        assert_eq!(prices("o1-2024-12-17"), Some((0.015, 0.06)));
+    }
+
+    #[test]
+    fn cheaper_variants_are_not_billed_at_the_base_rate() {
+        assert_eq!(prices("o1-mini"), Some((0.0011, 0.0044)));
+        assert_eq!(prices("o1-mini-2024-09-12"), Some((0.0011, 0.0044)));
+    }
+
+    #[test]
+    fn user_override_takes_precedence() {
+        let overrides = PricingTable::from([(
+            "openai".to_string(),
+            HashMap::from([("gpt-4o".to_string(), price(0.001, 0.002))]),
+        )]);
+        let table = with_overrides(default_pricing_table(), overrides);
+
+        let price = lookup_price(&table, "openai", "gpt-4o-2024-08-06").unwrap();
+        assert_eq!((price.input_per_1k, price.output_per_1k), (0.001, 0.002));
+        // Models the override doesn't mention keep their built-in price
+        assert!(lookup_price(&table, "openai", "gpt-4o-mini").is_some_and(|p| p.input_per_1k == 0.00015));
+    }
+
+    #[test]
+    fn unknown_model_has_no_price() {
+        assert!(prices("text-davinci-003").is_none());
+        assert!(lookup_price(&default_pricing_table(), "mystery", "gpt-4o").is_none());
+    }
+}
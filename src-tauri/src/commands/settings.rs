@@ -2,34 +2,30 @@ use tauri::{AppHandle, Manager};
 use tauri_plugin_store::StoreExt;
 use serde_json::json;
 use serde::{Deserialize, Serialize};
-use futures::StreamExt;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+use crate::commands::speech::{download_model, is_english_only_model, max_whisper_threads, whisper_model};
+use crate::providers::{
+    read_azure_provider_config, read_custom_provider_config, save_azure_provider_config,
+    save_custom_provider_config, AzureProviderConfig, CustomProviderConfig,
+};
 
 const STORE_PATH: &str = "settings.json";
-const DEFAULT_MODEL_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.en.bin";
-const DEFAULT_MODEL_FILENAME: &str = "ggml-base.en.bin";
-
-fn model_filename(model_id: &str) -> Result<&'static str, String> {
-    match model_id {
-        "tiny.en" => Ok("ggml-tiny.en.bin"),
-        "tiny" => Ok("ggml-tiny.bin"),
-        "base.en" => Ok("ggml-base.en.bin"),
-        "base" => Ok("ggml-base.bin"),
-        "small.en" => Ok("ggml-small.en.bin"),
-        "small" => Ok("ggml-small.bin"),
-        _ => Err("Unknown model id".to_string()),
-    }
-}
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct WhisperConfig {
     pub binary_path: String,
     pub model_path: String,
+    /// Spoken language hint; with `translate` on, output is English regardless
     pub language: String,
+    #[serde(default)]
+    pub translate: bool,
+    /// Threads used for transcription, between 1 and the number of logical cores
+    #[serde(default = "max_whisper_threads")]
+    pub threads: usize,
 }
 
-fn read_whisper_config(app: &AppHandle) -> Result<WhisperConfig, String> {
+pub(crate) fn read_whisper_config(app: &AppHandle) -> Result<WhisperConfig, String> {
     let store = app
         .store(STORE_PATH)
         .map_err(|e| format!("Failed to open store: {}", e))?;
@@ -46,11 +42,23 @@ fn read_whisper_config(app: &AppHandle) -> Result<WhisperConfig, String> {
         .get("whisper_language")
         .and_then(|v| v.as_str().map(|s| s.to_string()))
         .unwrap_or_else(|| "en".to_string());
+    let translate = store
+        .get("whisper_translate")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    // Clamped on read too, in case the setting came from a machine with more cores
+    let threads = store
+        .get("whisper_threads")
+        .and_then(|v| v.as_u64())
+        .map(|threads| (threads as usize).clamp(1, max_whisper_threads()))
+        .unwrap_or_else(max_whisper_threads);
 
     Ok(WhisperConfig {
         binary_path,
         model_path,
         language,
+        translate,
+        threads,
     })
 }
 
@@ -67,16 +75,16 @@ fn read_whisper_model_id(app: &AppHandle) -> Result<String, String> {
     Ok(model_id)
 }
 
-fn model_url(model_id: &str) -> Result<&'static str, String> {
-    match model_id {
-        "tiny.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.en.bin"),
-        "tiny" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.bin"),
-        "base.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.en.bin"),
-        "base" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.bin"),
-        "small.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.en.bin"),
-        "small" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin"),
-        _ => Err("Unknown model id".to_string()),
-    }
+/// The language setting as stored, without `read_whisper_config`'s English default
+fn read_stored_whisper_language(app: &AppHandle) -> Result<Option<String>, String> {
+    let store = app
+        .store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .get("whisper_language")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .filter(|language| !language.trim().is_empty()))
 }
 
 fn find_whisper_binary() -> Option<String> {
@@ -148,99 +156,44 @@ fn model_path(app: &AppHandle, model_id: &str) -> Result<PathBuf, String> {
     let models_dir = app_dir.join("whisper_models");
     std::fs::create_dir_all(&models_dir)
         .map_err(|e| format!("Failed to create models directory: {}", e))?;
-    Ok(models_dir.join(model_filename(model_id)?))
+    Ok(models_dir.join(whisper_model(model_id)?.filename))
 }
 
-async fn ensure_default_model(app: &AppHandle) -> Result<String, String> {
-    let dest_path = model_path(app, "base.en")?;
-
-    if dest_path.is_file() {
-        return Ok(dest_path.to_string_lossy().to_string());
-    }
-
-    let client = reqwest::Client::new();
-    let response = client
-        .get(DEFAULT_MODEL_URL)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to download model: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("Download failed: {}", response.status()));
-    }
-
-    let mut file = std::fs::File::create(&dest_path)
-        .map_err(|e| format!("Failed to create model file: {}", e))?;
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        std::io::Write::write_all(&mut file, &chunk)
-            .map_err(|e| format!("Failed to write model file: {}", e))?;
-    }
-
-    Ok(dest_path.to_string_lossy().to_string())
+/// The API key stored for a provider, if one is set
+pub fn read_api_key(app: &AppHandle, provider: &str) -> Result<Option<String>, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    
+    let key = format!("api_key_{}", provider);
+    Ok(store.get(&key).and_then(|v| v.as_str().map(|s| s.to_string())))
 }
 
-async fn ensure_model_by_id(app: &AppHandle, model_id: &str) -> Result<String, String> {
-    let dest_path = model_path(app, model_id)?;
-
-    if dest_path.is_file() {
-        return Ok(dest_path.to_string_lossy().to_string());
-    }
-
-    let url = model_url(model_id)?;
-    let client = reqwest::Client::new();
-    let response = client
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to download model: {}", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("Download failed: {}", response.status()));
-    }
-
-    let mut file = std::fs::File::create(&dest_path)
-        .map_err(|e| format!("Failed to create model file: {}", e))?;
-    let mut stream = response.bytes_stream();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        std::io::Write::write_all(&mut file, &chunk)
-            .map_err(|e| format!("Failed to write model file: {}", e))?;
-    }
-
-    Ok(dest_path.to_string_lossy().to_string())
+#[tauri::command]
+pub async fn get_api_key(app: AppHandle, provider: String) -> Result<Option<String>, String> {
+    read_api_key(&app, &provider)
 }
 
 #[tauri::command]
-pub async fn get_api_key(app: AppHandle, provider: String) -> Result<Option<String>, String> {
+pub async fn set_api_key(app: AppHandle, provider: String, api_key: String) -> Result<(), String> {
     let store = app.store(STORE_PATH)
         .map_err(|e| format!("Failed to open store: {}", e))?;
     
     let key = format!("api_key_{}", provider);
-    let value = store.get(&key);
+    store.set(&key, json!(api_key));
     
-    match value {
-        Some(v) => {
-            if let Some(s) = v.as_str() {
-                Ok(Some(s.to_string()))
-            } else {
-                Ok(None)
-            }
-        }
-        None => Ok(None),
-    }
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))?;
+    
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn set_api_key(app: AppHandle, provider: String, api_key: String) -> Result<(), String> {
+pub async fn delete_api_key(app: AppHandle, provider: String) -> Result<(), String> {
     let store = app.store(STORE_PATH)
         .map_err(|e| format!("Failed to open store: {}", e))?;
     
     let key = format!("api_key_{}", provider);
-    store.set(&key, json!(api_key));
+    store.delete(&key);
     
     store.save()
         .map_err(|e| format!("Failed to save store: {}", e))?;
@@ -249,12 +202,27 @@ pub async fn set_api_key(app: AppHandle, provider: String, api_key: String) -> R
 }
 
 #[tauri::command]
-pub async fn delete_api_key(app: AppHandle, provider: String) -> Result<(), String> {
+pub async fn get_base_url(app: AppHandle, provider: String) -> Result<Option<String>, String> {
     let store = app.store(STORE_PATH)
         .map_err(|e| format!("Failed to open store: {}", e))?;
     
-    let key = format!("api_key_{}", provider);
-    store.delete(&key);
+    let key = format!("{}_base_url", provider);
+    Ok(store.get(&key).and_then(|v| v.as_str().map(|s| s.to_string())))
+}
+
+/// Set a custom base URL for an OpenAI-compatible provider; an empty URL restores the default
+#[tauri::command]
+pub async fn set_base_url(app: AppHandle, provider: String, base_url: String) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    
+    let key = format!("{}_base_url", provider);
+    let base_url = base_url.trim();
+    if base_url.is_empty() {
+        store.delete(&key);
+    } else {
+        store.set(&key, json!(base_url));
+    }
     
     store.save()
         .map_err(|e| format!("Failed to save store: {}", e))?;
@@ -262,6 +230,94 @@ pub async fn delete_api_key(app: AppHandle, provider: String) -> Result<(), Stri
     Ok(())
 }
 
+#[tauri::command]
+pub async fn get_custom_provider_config(app: AppHandle) -> Result<Option<CustomProviderConfig>, String> {
+    read_custom_provider_config(&app)
+        .map_err(|e| format!("Failed to read custom provider config: {}", e))
+}
+
+/// Configure the OpenAI-compatible endpoint used by the "custom" provider; `None`
+/// removes it. Returns the saved config with the URL and fields normalized.
+#[tauri::command]
+pub async fn set_custom_provider_config(
+    app: AppHandle,
+    config: Option<CustomProviderConfig>,
+) -> Result<Option<CustomProviderConfig>, String> {
+    save_custom_provider_config(&app, config)
+        .map_err(|e| format!("Failed to save custom provider config: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_azure_provider_config(app: AppHandle) -> Result<Option<AzureProviderConfig>, String> {
+    read_azure_provider_config(&app)
+        .map_err(|e| format!("Failed to read Azure OpenAI config: {}", e))
+}
+
+/// Configure the Azure OpenAI resource used by the "azure" provider; `None` removes it.
+/// Returns the saved config with the endpoint and deployments normalized.
+#[tauri::command]
+pub async fn set_azure_provider_config(
+    app: AppHandle,
+    config: Option<AzureProviderConfig>,
+) -> Result<Option<AzureProviderConfig>, String> {
+    save_azure_provider_config(&app, config)
+        .map_err(|e| format!("Failed to save Azure OpenAI config: {}", e))
+}
+
+/// Whether grounded replies keep a copy of the knowledge base context they were sent
+pub fn read_store_context_used(app: &AppHandle) -> Result<bool, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store.get("store_context_used").and_then(|v| v.as_bool()).unwrap_or(false))
+}
+
+#[tauri::command]
+pub async fn get_store_context_used(app: AppHandle) -> Result<bool, String> {
+    read_store_context_used(&app)
+}
+
+/// Keep the knowledge base context on each grounded reply, for debugging answers.
+/// Off by default since the context can be large.
+#[tauri::command]
+pub async fn set_store_context_used(app: AppHandle, enabled: bool) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    store.set("store_context_used", json!(enabled));
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))?;
+
+    Ok(())
+}
+
+/// Seconds after which a transcription is aborted; `None` means no limit
+#[tauri::command]
+pub async fn get_whisper_timeout(app: AppHandle) -> Result<Option<u64>, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store.get("whisper_timeout_secs").and_then(|v| v.as_u64()).filter(|secs| *secs > 0))
+}
+
+/// Set the transcription timeout in seconds; `None` or zero removes the limit
+#[tauri::command]
+pub async fn set_whisper_timeout(app: AppHandle, timeout_secs: Option<u64>) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    if let Some(secs) = timeout_secs.filter(|secs| *secs > 0) {
+        store.set("whisper_timeout_secs", json!(secs));
+    } else {
+        store.delete("whisper_timeout_secs");
+    }
+
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))?;
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_whisper_config(app: AppHandle) -> Result<WhisperConfig, String> {
     read_whisper_config(&app)
@@ -317,22 +373,18 @@ pub async fn ensure_default_whisper_config(app: AppHandle) -> Result<WhisperConf
     }
 
     if model_id != "custom" {
-        // Get the expected model path for the current model_id
-        let expected_path = model_path(&app, model_id.trim())?;
-        let expected_path_str = expected_path.to_string_lossy().to_string();
-        
-        // Update if path is empty, doesn't match expected, or file doesn't exist
-        if config.model_path.trim().is_empty() 
-            || config.model_path != expected_path_str
-            || !Path::new(&config.model_path).is_file() 
-        {
-            config.model_path = ensure_model_by_id(&app, model_id.trim()).await?;
+        // Only returns early for a file large enough to be complete; anything else is
+        // downloaded again and checksummed before it is used
+        let model_path = download_model(&app, whisper_model(model_id.trim())?).await?;
+        if config.model_path != model_path {
+            config.model_path = model_path;
             changed = true;
         }
     }
 
-    if config.language.trim().is_empty() {
-        config.language = "en".to_string();
+    if read_stored_whisper_language(&app)?.is_none() {
+        // English-only models can't detect the language, so only they default to English
+        config.language = if is_english_only_model(&config.model_path) { "en" } else { "auto" }.to_string();
         changed = true;
     }
 
@@ -358,7 +410,13 @@ pub async fn set_whisper_config(
     binary_path: String,
     model_path: String,
     language: String,
+    translate: Option<bool>,
+    threads: Option<usize>,
 ) -> Result<(), String> {
+    if threads == Some(0) {
+        return Err("Whisper needs at least one thread".to_string());
+    }
+
     let store = app
         .store(STORE_PATH)
         .map_err(|e| format!("Failed to open store: {}", e))?;
@@ -366,6 +424,12 @@ pub async fn set_whisper_config(
     store.set("whisper_binary_path", json!(binary_path));
     store.set("whisper_model_path", json!(model_path));
     store.set("whisper_language", json!(language));
+    if let Some(translate) = translate {
+        store.set("whisper_translate", json!(translate));
+    }
+    if let Some(threads) = threads {
+        store.set("whisper_threads", json!(threads.min(max_whisper_threads())));
+    }
 
     store.save()
         .map_err(|e| format!("Failed to save store: {}", e))?;
@@ -1,111 +1,225 @@
+use std::collections::HashMap;
 use std::io::Cursor;
-use tauri::{AppHandle, Manager};
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_store::StoreExt;
 use futures::StreamExt;
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use tokio_util::sync::CancellationToken;
+use crate::commands::settings::{read_whisper_config, WhisperConfig};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState};
 
 const STORE_PATH: &str = "settings.json";
 
-fn get_whisper_config(app: &AppHandle) -> Result<(String, String, String), String> {
-    let store = app
-        .store(STORE_PATH)
-        .map_err(|e| format!("Failed to open store: {}", e))?;
+/// Language setting that lets whisper detect the spoken language
+const AUTO_LANGUAGE: &str = "auto";
 
-    let binary_path = store
-        .get("whisper_binary_path")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .unwrap_or_default();
-    let model_path = store
-        .get("whisper_model_path")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .unwrap_or_default();
-    let language = store
-        .get("whisper_language")
-        .and_then(|v| v.as_str().map(|s| s.to_string()))
-        .unwrap_or_else(|| "en".to_string());
+fn get_whisper_config(app: &AppHandle) -> Result<WhisperConfig, String> {
+    let config = read_whisper_config(app)?;
 
-    if model_path.trim().is_empty() {
+    if config.model_path.trim().is_empty() {
         return Err("Whisper model path not configured.".to_string());
     }
 
-    Ok((binary_path, model_path, language))
+    if config.translate && is_english_only_model(&config.model_path) {
+        return Err(
+            "Translation to English needs a multilingual whisper model, but the configured model is English-only (.en). \
+            Choose a model without \".en\" or turn translation off."
+                .to_string(),
+        );
+    }
+
+    if config.language.trim() == AUTO_LANGUAGE && is_english_only_model(&config.model_path) {
+        return Err(
+            "Language detection needs a multilingual whisper model, but the configured model is English-only (.en). \
+            Choose a model without \".en\" or set the language to \"en\"."
+                .to_string(),
+        );
+    }
+
+    Ok(config)
 }
 
-#[tauri::command]
-pub async fn transcribe_audio(app: AppHandle, wav_base64: String) -> Result<String, String> {
-    let (_binary_path, model_path, language) = get_whisper_config(&app)?;
+/// English-only models (`ggml-*.en.bin`) can transcribe English but not translate
+pub(crate) fn is_english_only_model(model_path: &str) -> bool {
+    std::path::Path::new(model_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.ends_with(".en"))
+}
+
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+/// Length of each window processed by `transcribe_audio_stream`
+const STREAM_WINDOW_SAMPLES: usize = 10 * WHISPER_SAMPLE_RATE as usize;
+/// Audio shared between consecutive windows so words at the seams aren't lost
+const STREAM_OVERLAP_SAMPLES: usize = WHISPER_SAMPLE_RATE as usize / 2;
+/// Whisper needs at least a second of audio; shorter tails are merged into the previous window
+const MIN_WINDOW_SAMPLES: usize = WHISPER_SAMPLE_RATE as usize;
+
+#[derive(Clone, Serialize)]
+struct TranscriptionEvent {
+    text: String,
+}
+
+/// Minimum bytes between `whisper-download-progress` events
+const DOWNLOAD_PROGRESS_INTERVAL: u64 = 256 * 1024;
 
+#[derive(Clone, Serialize)]
+struct WhisperDownloadProgress {
+    model_id: String,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+/// Decode a base64 WAV recording into 16kHz mono f32 samples
+fn decode_wav_audio(wav_base64: String) -> Result<Vec<f32>, String> {
     let audio_bytes =
         base64::decode(wav_base64).map_err(|e| format!("Invalid audio data: {}", e))?;
 
-    let mut reader = hound::WavReader::new(Cursor::new(audio_bytes))
+    let reader = hound::WavReader::new(Cursor::new(audio_bytes))
         .map_err(|e| format!("Failed to read wav data: {}", e))?;
     let spec = reader.spec();
 
-    if spec.bits_per_sample != 16 {
-        return Err("Unsupported audio format. Please record again.".to_string());
+    if spec.channels == 0 || spec.sample_rate == 0 {
+        return Err("Unreadable audio. Please record again.".to_string());
     }
 
-    let samples: Vec<i16> = reader
-        .into_samples::<i16>()
-        .map(|sample| sample.map_err(|e| format!("Invalid audio sample: {}", e)))
-        .collect::<Result<Vec<_>, _>>()?;
-
-    let mut audio = vec![0.0f32; samples.len()];
-    whisper_rs::convert_integer_to_float_audio(&samples, &mut audio)
-        .map_err(|e| format!("Failed to convert audio: {}", e))?;
+    let mut audio: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .map(|sample| sample.map_err(|e| format!("Invalid audio sample: {}", e)))
+            .collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Int => {
+            // Scale 8/16/24/32-bit integers into [-1.0, 1.0]
+            let scale = (1i64 << (spec.bits_per_sample.clamp(1, 32) - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| {
+                    sample
+                        .map(|s| s as f32 / scale)
+                        .map_err(|e| format!("Invalid audio sample: {}", e))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
 
     if spec.channels == 2 {
         audio = whisper_rs::convert_stereo_to_mono_audio(&audio)
             .map_err(|e| format!("Failed to convert to mono: {}", e))?;
-    } else if spec.channels != 1 {
-        return Err("Unsupported audio channels. Please record again.".to_string());
+    } else if spec.channels > 2 {
+        audio = audio
+            .chunks(spec.channels as usize)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+            .collect();
     }
 
-    if spec.sample_rate != 16000 {
-        return Err("Audio must be 16KHz. Please record again.".to_string());
+    if spec.sample_rate != WHISPER_SAMPLE_RATE {
+        println!("[Whisper] Resampling audio from {}Hz to {}Hz", spec.sample_rate, WHISPER_SAMPLE_RATE);
+        audio = resample_linear(&audio, spec.sample_rate, WHISPER_SAMPLE_RATE);
     }
 
+    Ok(audio)
+}
+
+/// Resample mono audio with linear interpolation
+fn resample_linear(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if samples.is_empty() || from_rate == to_rate {
+        return samples.to_vec();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    let output_len = (samples.len() as f64 / ratio).floor() as usize;
+
+    (0..output_len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position.floor() as usize;
+            let fraction = (position - index as f64) as f32;
+            let current = samples[index.min(samples.len() - 1)];
+            let next = samples[(index + 1).min(samples.len() - 1)];
+            current + (next - current) * fraction
+        })
+        .collect()
+}
+
+fn load_whisper_context(model_path: &str) -> Result<WhisperContext, String> {
     // Log which model is being used for debugging
     println!("[Whisper] Loading model: {}", model_path);
     let start = std::time::Instant::now();
 
     let ctx = WhisperContext::new_with_params(
-        &model_path,
+        model_path,
         WhisperContextParameters::default(),
     )
     .map_err(|e| format!("Failed to load whisper model: {}", e))?;
     
     println!("[Whisper] Model loaded in {:?}", start.elapsed());
-    let mut state = ctx
-        .create_state()
-        .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+    Ok(ctx)
+}
 
+/// Logical cores available; the default and the upper bound for `whisper_threads`
+pub(crate) fn max_whisper_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|v| v.get())
+        .unwrap_or(4)
+}
+
+/// Build decoding parameters. With `translate`, `language` is only a hint for the
+/// spoken language and the output is always English.
+fn whisper_params<'a, 'b>(language: &'a str, translate: bool, threads: usize, cancel: &CancellationToken) -> FullParams<'a, 'b> {
     let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
     let language = language.trim();
-    if !language.is_empty() {
+    // An English hint would make translation a no-op, so let whisper detect the source
+    if language == AUTO_LANGUAGE || (translate && language == "en") {
+        params.set_language(None);
+    } else if !language.is_empty() {
         params.set_language(Some(language));
     }
-    let threads = std::thread::available_parallelism()
-        .map(|v| v.get() as i32)
-        .unwrap_or(4);
-    params.set_n_threads(threads);
+    params.set_translate(translate);
+    params.set_n_threads(threads as i32);
     params.set_print_special(false);
     params.set_print_progress(false);
     params.set_print_realtime(false);
     params.set_print_timestamps(false);
 
+    // Whisper polls this between decoding steps and stops early once it returns true
+    let cancel = cancel.clone();
+    params.set_abort_callback_safe(move || cancel.is_cancelled());
+    params
+}
+
+/// A transcribed stretch of audio with its position in milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptSegment {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+/// Run whisper over the samples and return the non-empty segments with their timings
+fn run_whisper_segments(
+    state: &mut WhisperState,
+    language: &str,
+    translate: bool,
+    threads: usize,
+    audio: &[f32],
+    cancel: &CancellationToken,
+) -> Result<Vec<TranscriptSegment>, String> {
     let infer_start = std::time::Instant::now();
     state
-        .full(params, &audio[..])
+        .full(whisper_params(language, translate, threads, cancel), audio)
         .map_err(|e| format!("Whisper failed: {}", e))?;
     println!("[Whisper] Transcription took {:?} for {} samples ({:.1}s audio)", 
              infer_start.elapsed(), 
              audio.len(),
-             audio.len() as f32 / 16000.0);
+             audio.len() as f32 / WHISPER_SAMPLE_RATE as f32);
 
     let num_segments = state.full_n_segments();
-    let mut transcript_parts = Vec::new();
+    let mut segments = Vec::new();
     for i in 0..num_segments {
         if let Some(segment) = state.get_segment(i) {
             let segment_text = segment
@@ -113,62 +227,605 @@ pub async fn transcribe_audio(app: AppHandle, wav_base64: String) -> Result<Stri
                 .map_err(|e| format!("Failed to read segment: {}", e))?;
             let cleaned = segment_text.trim();
             if !cleaned.is_empty() {
-                transcript_parts.push(cleaned.to_string());
+                // Whisper reports timestamps in centiseconds
+                segments.push(TranscriptSegment {
+                    text: cleaned.to_string(),
+                    start_ms: segment.start_timestamp() * 10,
+                    end_ms: segment.end_timestamp() * 10,
+                });
             }
         }
     }
-    let transcript = transcript_parts.join(" ");
+    Ok(segments)
+}
+
+/// Run whisper over the samples and join the non-empty segments
+fn run_whisper(
+    state: &mut WhisperState,
+    language: &str,
+    translate: bool,
+    threads: usize,
+    audio: &[f32],
+    cancel: &CancellationToken,
+) -> Result<String, String> {
+    let segments = run_whisper_segments(state, language, translate, threads, audio, cancel)?;
+    Ok(segments
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+/// Cancellation tokens for running transcriptions, keyed by caller-supplied job id
+#[derive(Default)]
+pub struct ActiveTranscriptions(pub Mutex<HashMap<String, CancellationToken>>);
+
+/// Why a transcription did not produce text, tagged by `kind` for the frontend
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum TranscriptionError {
+    Cancelled,
+    TimedOut(String),
+    Failed(String),
+}
+
+impl From<String> for TranscriptionError {
+    fn from(message: String) -> Self {
+        TranscriptionError::Failed(message)
+    }
+}
+
+fn read_transcription_timeout(app: &AppHandle) -> Result<Option<Duration>, String> {
+    let store = app
+        .store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .get("whisper_timeout_secs")
+        .and_then(|v| v.as_u64())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs))
+}
+
+fn unregister_transcription(app: &AppHandle, job_id: Option<&str>) {
+    if let Some(job_id) = job_id {
+        if let Ok(mut jobs) = app.state::<ActiveTranscriptions>().0.lock() {
+            jobs.remove(job_id);
+        }
+    }
+}
+
+/// Run a whisper job, on a blocking thread when it can be cancelled by `job_id` or
+/// limited by the configured timeout. Without either, it runs inline as before.
+async fn run_transcription_job<T, F>(
+    app: &AppHandle,
+    job_id: Option<String>,
+    job: F,
+) -> Result<T, TranscriptionError>
+where
+    T: Send + 'static,
+    F: FnOnce(CancellationToken) -> Result<T, String> + Send + 'static,
+{
+    let timeout = read_transcription_timeout(app)?;
+    if timeout.is_none() && job_id.is_none() {
+        return job(CancellationToken::new()).map_err(TranscriptionError::Failed);
+    }
+
+    let cancel = CancellationToken::new();
+    if let Some(job_id) = &job_id {
+        app.state::<ActiveTranscriptions>()
+            .0
+            .lock()
+            .map_err(|e| format!("Failed to access active transcriptions: {}", e))?
+            .insert(job_id.clone(), cancel.clone());
+    }
+
+    let handle = tokio::task::spawn_blocking({
+        let cancel = cancel.clone();
+        move || job(cancel)
+    });
+
+    let joined = match timeout {
+        Some(limit) => match tokio::time::timeout(limit, handle).await {
+            Ok(joined) => joined,
+            Err(_) => {
+                // Stop the whisper thread too instead of leaving it running in the background
+                cancel.cancel();
+                unregister_transcription(app, job_id.as_deref());
+                println!("[Whisper] Transcription timed out after {:?}", limit);
+                return Err(TranscriptionError::TimedOut(format!(
+                    "Transcription took longer than {} seconds",
+                    limit.as_secs()
+                )));
+            }
+        },
+        None => handle.await,
+    };
+
+    unregister_transcription(app, job_id.as_deref());
+
+    match joined.map_err(|e| format!("Transcription task failed: {}", e))? {
+        Err(_) if cancel.is_cancelled() => Err(TranscriptionError::Cancelled),
+        result => result.map_err(TranscriptionError::Failed),
+    }
+}
+
+#[tauri::command]
+pub async fn transcribe_audio(
+    app: AppHandle,
+    wav_base64: String,
+    job_id: Option<String>,
+) -> Result<String, TranscriptionError> {
+    let WhisperConfig { model_path, language, translate, threads, .. } = get_whisper_config(&app)?;
+    let audio = decode_wav_audio(wav_base64)?;
+
+    let transcript = run_transcription_job(&app, job_id, move |cancel| {
+        let ctx = load_whisper_context(&model_path)?;
+        let mut state = ctx
+            .create_state()
+            .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+        run_whisper(&mut state, &language, translate, threads, &audio, &cancel)
+    }).await?;
 
     if transcript.is_empty() {
-        return Err("No speech detected in audio.".to_string());
+        return Err("No speech detected in audio.".to_string().into());
     }
 
     Ok(transcript)
 }
 
-fn get_model_url(model_id: &str) -> Result<&'static str, String> {
-    match model_id {
-        "tiny.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.en.bin"),
-        "tiny" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-tiny.bin"),
-        "base.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.en.bin"),
-        "base" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-base.bin"),
-        "small.en" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.en.bin"),
-        "small" => Ok("https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin"),
-        _ => Err("Unknown model id".to_string()),
+/// Transcribe audio into timed segments, for captions
+#[tauri::command]
+pub async fn transcribe_audio_timestamped(
+    app: AppHandle,
+    wav_base64: String,
+    job_id: Option<String>,
+) -> Result<Vec<TranscriptSegment>, TranscriptionError> {
+    let WhisperConfig { model_path, language, translate, threads, .. } = get_whisper_config(&app)?;
+    let audio = decode_wav_audio(wav_base64)?;
+
+    let segments = run_transcription_job(&app, job_id, move |cancel| {
+        let ctx = load_whisper_context(&model_path)?;
+        let mut state = ctx
+            .create_state()
+            .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+        run_whisper_segments(&mut state, &language, translate, threads, &audio, &cancel)
+    }).await?;
+
+    if segments.is_empty() {
+        return Err("No speech detected in audio.".to_string().into());
     }
+
+    Ok(segments)
 }
 
-// Minimum expected sizes for each model (to detect corrupt/partial files)
-fn get_min_model_size(model_id: &str) -> u64 {
-    match model_id {
-        "tiny.en" | "tiny" => 70_000_000,      // ~75MB
-        "base.en" | "base" => 140_000_000,     // ~142MB
-        "small.en" | "small" => 460_000_000,   // ~466MB
-        _ => 0,
+/// Transcribe audio in overlapping windows, emitting `transcription-partial` with the
+/// transcript so far after each window and `transcription-final` once done
+#[tauri::command]
+pub async fn transcribe_audio_stream(
+    app: AppHandle,
+    wav_base64: String,
+    job_id: Option<String>,
+) -> Result<String, TranscriptionError> {
+    let WhisperConfig { model_path, language, translate, threads, .. } = get_whisper_config(&app)?;
+    let audio = decode_wav_audio(wav_base64)?;
+
+    let emitter = app.clone();
+    let transcript = run_transcription_job(&app, job_id, move |cancel| {
+        let ctx = load_whisper_context(&model_path)?;
+        let mut state = ctx
+            .create_state()
+            .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+        let overlap_ms = (STREAM_OVERLAP_SAMPLES * 1000 / WHISPER_SAMPLE_RATE as usize) as i64;
+        let mut transcript_parts: Vec<String> = Vec::new();
+        let mut start = 0;
+        while start < audio.len() {
+            let mut end = (start + STREAM_WINDOW_SAMPLES).min(audio.len());
+            if audio.len() - end < MIN_WINDOW_SAMPLES {
+                end = audio.len();
+            }
+
+            let segments = run_whisper_segments(&mut state, &language, translate, threads, &audio[start..end], &cancel)?;
+            // Segments that end inside the overlap were already transcribed by the previous window
+            let seam_ms = if start == 0 { 0 } else { overlap_ms };
+            let window_text = segments
+                .into_iter()
+                .filter(|segment| segment.end_ms > seam_ms)
+                .map(|segment| segment.text)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let window_text = match transcript_parts.last() {
+                Some(previous) => trim_repeated_words(previous, &window_text),
+                None => window_text,
+            };
+            if !window_text.is_empty() {
+                transcript_parts.push(window_text);
+                let _ = emitter.emit("transcription-partial", TranscriptionEvent {
+                    text: transcript_parts.join(" "),
+                });
+            }
+
+            if end >= audio.len() {
+                break;
+            }
+            start = end - STREAM_OVERLAP_SAMPLES;
+        }
+
+        Ok(transcript_parts.join(" "))
+    }).await?;
+
+    if transcript.is_empty() {
+        return Err("No speech detected in audio.".to_string().into());
+    }
+
+    let _ = app.emit("transcription-final", TranscriptionEvent {
+        text: transcript.clone(),
+    });
+
+    Ok(transcript)
+}
+
+/// Most words a segment straddling a window seam can repeat from the previous window
+const MAX_SEAM_WORDS: usize = 8;
+
+/// Drop the leading words of `next` that repeat the end of `previous`. Words are
+/// compared without case or punctuation since whisper may format them differently
+/// in each window.
+fn trim_repeated_words(previous: &str, next: &str) -> String {
+    fn normalize(word: &str) -> String {
+        word.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
     }
+
+    let previous: Vec<String> = previous.split_whitespace().map(normalize).collect();
+    let next_words: Vec<&str> = next.split_whitespace().collect();
+    let normalized: Vec<String> = next_words.iter().map(|w| normalize(w)).collect();
+
+    let longest = MAX_SEAM_WORDS.min(previous.len()).min(next_words.len());
+    let repeated = (1..=longest)
+        .rev()
+        .find(|&n| previous[previous.len() - n..] == normalized[..n])
+        .unwrap_or(0);
+
+    next_words[repeated..].join(" ")
 }
 
+/// Whisper detects the language from the first 30 seconds of audio
+const LANGUAGE_DETECTION_SAMPLES: usize = 30 * WHISPER_SAMPLE_RATE as usize;
+
+/// Spoken language detected in a recording, with whisper's probability for it
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectedLanguage {
+    pub language: String,
+    pub confidence: f32,
+}
+
+/// Run whisper's language detection pass over a recording
 #[tauri::command]
-pub async fn download_whisper_model(app: AppHandle, model_id: String) -> Result<String, String> {
-    let model_id = model_id.trim();
-    let url = get_model_url(model_id)?;
+pub async fn detect_language(app: AppHandle, wav_base64: String) -> Result<DetectedLanguage, String> {
+    let WhisperConfig { model_path, threads, .. } = get_whisper_config(&app)?;
+    if is_english_only_model(&model_path) {
+        return Err(
+            "Language detection needs a multilingual whisper model, but the configured model is English-only (.en)."
+                .to_string(),
+        );
+    }
+    let mut audio = decode_wav_audio(wav_base64)?;
+    audio.truncate(LANGUAGE_DETECTION_SAMPLES);
+
+    let detected = tokio::task::spawn_blocking(move || {
+        let ctx = load_whisper_context(&model_path)?;
+        let mut state = ctx
+            .create_state()
+            .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+        state
+            .pcm_to_mel(&audio, threads)
+            .map_err(|e| format!("Failed to process audio: {}", e))?;
+        let (lang_id, probabilities) = state
+            .lang_detect(0, threads)
+            .map_err(|e| format!("Language detection failed: {}", e))?;
+
+        let language = whisper_rs::get_lang_str(lang_id)
+            .ok_or_else(|| format!("Unknown language id: {}", lang_id))?;
+        Ok::<_, String>(DetectedLanguage {
+            language: language.to_string(),
+            confidence: probabilities.get(lang_id as usize).copied().unwrap_or(0.0),
+        })
+    })
+    .await
+    .map_err(|e| format!("Language detection task failed: {}", e))??;
+
+    println!("[Whisper] Detected language {} ({:.0}%)", detected.language, detected.confidence * 100.0);
+    Ok(detected)
+}
+
+/// Abort a running transcription started with the given job id
+#[tauri::command]
+pub async fn cancel_transcription(app: AppHandle, job_id: String) -> Result<bool, String> {
+    let token = app
+        .state::<ActiveTranscriptions>()
+        .0
+        .lock()
+        .map_err(|e| format!("Failed to access active transcriptions: {}", e))?
+        .remove(&job_id);
+
+    match token {
+        Some(token) => {
+            println!("[Whisper] Cancelling transcription {}", job_id);
+            token.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+const MODEL_BASE_URL: &str = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main";
+
+/// A whisper model `download_whisper_model` knows how to fetch
+pub(crate) struct WhisperModel {
+    pub id: &'static str,
+    /// File name on Hugging Face and in the models directory
+    pub filename: &'static str,
+    /// Smaller files are partial or corrupt downloads
+    pub min_size: u64,
+    /// SHA-256 of the file, as published on Hugging Face
+    pub sha256: &'static str,
+}
+
+impl WhisperModel {
+    pub fn url(&self) -> String {
+        format!("{}/{}", MODEL_BASE_URL, self.filename)
+    }
+}
+
+/// Every known model, in size order
+pub(crate) static WHISPER_MODELS: &[WhisperModel] = &[
+    WhisperModel {
+        id: "tiny.en",
+        filename: "ggml-tiny.en.bin",
+        min_size: 70_000_000, // ~75MB
+        sha256: "921e4cf8686fdd993dcd081a5da5b6c365bfde1162e72b08d75ac75289920b1f",
+    },
+    WhisperModel {
+        id: "tiny",
+        filename: "ggml-tiny.bin",
+        min_size: 70_000_000,
+        sha256: "be07e048e1e599ad46341c8d2a135645097a538221678b7acdd1b1919c6e1b21",
+    },
+    WhisperModel {
+        id: "base.en",
+        filename: "ggml-base.en.bin",
+        min_size: 140_000_000, // ~142MB
+        sha256: "a03779c86df3323075f5e796cb2ce5029f00ec8869eee3fdfb897afe36c6d002",
+    },
+    WhisperModel {
+        id: "base",
+        filename: "ggml-base.bin",
+        min_size: 140_000_000,
+        sha256: "60ed5bc3dd14eea856493d334349b405782ddcaf0028d4b5df4088345fba2efe",
+    },
+    WhisperModel {
+        id: "small.en",
+        filename: "ggml-small.en.bin",
+        min_size: 460_000_000, // ~466MB
+        sha256: "c6138d6d58ecc8322097e0f987c32f1be8bb0a18532a3f88f734d1bbf9c41e5d",
+    },
+    WhisperModel {
+        id: "small",
+        filename: "ggml-small.bin",
+        min_size: 460_000_000,
+        sha256: "1be3a9b2063867b937e64e2ec7483364a79917e157fa98c5d94b5c1fffea987b",
+    },
+    WhisperModel {
+        id: "medium.en",
+        filename: "ggml-medium.en.bin",
+        min_size: 1_500_000_000, // ~1.5GB
+        sha256: "cc37e93478338ec7700281a7ac30a10128929eb8f427dda2e865faa8f6da4356",
+    },
+    WhisperModel {
+        id: "medium",
+        filename: "ggml-medium.bin",
+        min_size: 1_500_000_000,
+        sha256: "6c14d5adee5f86394037b4e4e8b59f1673b6cee10e3cf0b11bbdbee79c156208",
+    },
+    WhisperModel {
+        id: "large-v3",
+        filename: "ggml-large-v3.bin",
+        min_size: 3_000_000_000, // ~3.1GB
+        sha256: "64d182b440b98d5203c4f9bd541544d84c605196c4f7b845dfa11fb23594d1e2",
+    },
+    WhisperModel {
+        id: "large-v3-turbo",
+        filename: "ggml-large-v3-turbo.bin",
+        min_size: 1_600_000_000, // ~1.6GB
+        sha256: "1fc70f774d38eb169993ac391eea357ef47c88757ef72ee5943879b7e8e2bc69",
+    },
+];
+
+pub(crate) fn whisper_model(model_id: &str) -> Result<&'static WhisperModel, String> {
+    WHISPER_MODELS
+        .iter()
+        .find(|model| model.id == model_id)
+        .ok_or_else(|| "Unknown model id".to_string())
+}
+
+/// Hash a file off the async runtime; models run to several gigabytes
+async fn file_sha256(path: &std::path::Path) -> Result<String, String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| format!("Failed to open model file: {}", e))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .map_err(|e| format!("Failed to read model file: {}", e))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .map_err(|e| format!("Checksum task failed: {}", e))?
+}
+
+/// Whether the file at `path` hashes to the known checksum for the model
+async fn checksum_matches(path: &std::path::Path, model: &WhisperModel) -> Result<bool, String> {
+    let actual = file_sha256(path).await?;
+    if actual != model.sha256 {
+        eprintln!("[Whisper] Checksum mismatch for {}: expected {}, got {}", model.id, model.sha256, actual);
+    }
+    Ok(actual == model.sha256)
+}
+
+fn whisper_models_dir(app: &AppHandle) -> Result<std::path::PathBuf, String> {
     let app_dir = app
         .path()
         .app_data_dir()
         .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
-    let models_dir = app_dir.join("whisper_models");
+    Ok(app_dir.join("whisper_models"))
+}
+
+/// Whether a known model is on disk, and whether it's large enough to be complete
+#[derive(Debug, Clone, Serialize)]
+pub struct WhisperModelStatus {
+    pub model_id: String,
+    pub present: bool,
+    pub path: String,
+    pub size_bytes: u64,
+    pub valid: bool,
+}
+
+fn whisper_model_status(models_dir: &std::path::Path, model: &WhisperModel) -> WhisperModelStatus {
+    let path = models_dir.join(model.filename);
+    let size_bytes = std::fs::metadata(&path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
+
+    WhisperModelStatus {
+        model_id: model.id.to_string(),
+        present: size_bytes.is_some(),
+        path: path.to_string_lossy().to_string(),
+        size_bytes: size_bytes.unwrap_or(0),
+        valid: size_bytes.is_some_and(|size| size >= model.min_size),
+    }
+}
+
+/// Check whether a model is downloaded, without downloading it
+#[tauri::command]
+pub async fn get_whisper_model_status(app: AppHandle, model_id: String) -> Result<WhisperModelStatus, String> {
+    Ok(whisper_model_status(&whisper_models_dir(&app)?, whisper_model(model_id.trim())?))
+}
+
+/// Status of every known model, in size order
+#[tauri::command]
+pub async fn list_whisper_models(app: AppHandle) -> Result<Vec<WhisperModelStatus>, String> {
+    let models_dir = whisper_models_dir(&app)?;
+    Ok(WHISPER_MODELS
+        .iter()
+        .map(|model| whisper_model_status(&models_dir, model))
+        .collect())
+}
+
+/// Delete a downloaded model and any partial download of it. The configured model is
+/// only deleted with `force`, which also clears the model setting.
+/// Returns whether anything was removed.
+#[tauri::command]
+pub async fn delete_whisper_model(app: AppHandle, model_id: String, force: Option<bool>) -> Result<bool, String> {
+    let model_id = model_id.trim();
+    let models_dir = whisper_models_dir(&app)?;
+    let filename = whisper_model(model_id)?.filename;
+    let path = models_dir.join(filename);
+    let temp_path = models_dir.join(format!("{}.download", filename));
+
+    let store = app
+        .store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    let configured = store
+        .get("whisper_model_path")
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .is_some_and(|configured| std::path::Path::new(&configured) == path);
+
+    if configured {
+        if !force.unwrap_or(false) {
+            return Err(format!(
+                "Model {} is the configured whisper model. Choose another model first or force the deletion.",
+                model_id
+            ));
+        }
+        store.set("whisper_model_path", json!(""));
+        store
+            .save()
+            .map_err(|e| format!("Failed to save store: {}", e))?;
+    }
+
+    let mut removed = false;
+    for file in [&path, &temp_path] {
+        match std::fs::remove_file(file) {
+            Ok(()) => removed = true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to delete {}: {}", file.display(), e)),
+        }
+    }
+
+    if removed {
+        println!("[Whisper] Deleted model {}", model_id);
+    }
+    Ok(removed)
+}
+
+/// Hash a downloaded model and compare it with the known checksum. Unlike the status
+/// commands this reads the whole file, so it catches corruption the size check misses.
+#[tauri::command]
+pub async fn verify_whisper_model(app: AppHandle, model_id: String) -> Result<bool, String> {
+    let model = whisper_model(model_id.trim())?;
+    let path = whisper_models_dir(&app)?.join(model.filename);
+    if !path.is_file() {
+        return Err(format!("Model {} is not downloaded", model.id));
+    }
+
+    checksum_matches(&path, model).await
+}
+
+/// Total bytes used by downloaded models, partial downloads included
+#[tauri::command]
+pub async fn get_whisper_models_disk_usage(app: AppHandle) -> Result<u64, String> {
+    let entries = match std::fs::read_dir(whisper_models_dir(&app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read models directory: {}", e)),
+    };
+
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum())
+}
+
+#[tauri::command]
+pub async fn download_whisper_model(app: AppHandle, model_id: String) -> Result<String, String> {
+    download_model(&app, whisper_model(model_id.trim())?).await
+}
+
+/// Download a model unless a complete-looking copy is already on disk, resuming any
+/// partial download. The file is only moved into place once its checksum matches.
+/// Returns the model's path.
+pub(crate) async fn download_model(app: &AppHandle, model: &WhisperModel) -> Result<String, String> {
+    let model_id = model.id;
+    let url = model.url();
+    let models_dir = whisper_models_dir(app)?;
     std::fs::create_dir_all(&models_dir)
         .map_err(|e| format!("Failed to create models directory: {}", e))?;
 
-    let filename = url
-        .split('/')
-        .last()
-        .ok_or_else(|| "Invalid model URL".to_string())?;
+    let filename = model.filename;
     let dest_path = models_dir.join(filename);
     let temp_path = models_dir.join(format!("{}.download", filename));
 
     // Check if model already exists and is valid (large enough)
-    let min_size = get_min_model_size(model_id);
+    let min_size = model.min_size;
     if dest_path.exists() {
         if let Ok(metadata) = std::fs::metadata(&dest_path) {
             if metadata.len() >= min_size {
@@ -180,45 +837,103 @@ pub async fn download_whisper_model(app: AppHandle, model_id: String) -> Result<
         }
     }
 
-    // Remove any partial download
-    let _ = std::fs::remove_file(&temp_path);
+    // Resume from a previous partial download if one exists
+    let resume_from = std::fs::metadata(&temp_path).map(|m| m.len()).unwrap_or(0);
 
     let client = reqwest::Client::new();
-    let response = client
-        .get(url)
+    let mut request = client.get(&url);
+    if resume_from > 0 {
+        println!("[Whisper] Resuming {} from {} bytes", filename, resume_from);
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let mut response = request
         .send()
         .await
         .map_err(|e| format!("Failed to download model: {}", e))?;
 
+    // The partial file can't be resumed (e.g. already complete or changed upstream), start over
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        println!("[Whisper] Server rejected resume range, restarting download");
+        let _ = std::fs::remove_file(&temp_path);
+        response = client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to download model: {}", e))?;
+    }
+
     if !response.status().is_success() {
         return Err(format!("Download failed: {}", response.status()));
     }
 
+    let resumed = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut downloaded: u64 = if resumed { resume_from } else { 0 };
+
     // Get expected content length if available
-    let expected_size = response.content_length();
+    let expected_size = response.content_length().map(|len| len + downloaded);
     println!("[Whisper] Downloading {} (expected size: {:?} bytes)", filename, expected_size);
 
-    let mut file = std::fs::File::create(&temp_path)
-        .map_err(|e| format!("Failed to create model file: {}", e))?;
+    let mut file = if resumed {
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&temp_path)
+            .map_err(|e| format!("Failed to open partial model file: {}", e))?
+    } else {
+        // Server ignored the range request, so the partial file is replaced
+        std::fs::File::create(&temp_path)
+            .map_err(|e| format!("Failed to create model file: {}", e))?
+    };
     let mut stream = response.bytes_stream();
-    let mut downloaded: u64 = 0;
+    let mut last_progress: u64 = downloaded;
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
         std::io::Write::write_all(&mut file, &chunk)
             .map_err(|e| format!("Failed to write model file: {}", e))?;
         downloaded += chunk.len() as u64;
+
+        if downloaded - last_progress >= DOWNLOAD_PROGRESS_INTERVAL {
+            last_progress = downloaded;
+            let _ = app.emit("whisper-download-progress", WhisperDownloadProgress {
+                model_id: model_id.to_string(),
+                downloaded,
+                total: expected_size,
+            });
+        }
     }
 
+    let _ = app.emit("whisper-download-progress", WhisperDownloadProgress {
+        model_id: model_id.to_string(),
+        downloaded,
+        total: expected_size,
+    });
+
     // Verify download size if we know expected size
     if let Some(expected) = expected_size {
-        if downloaded != expected {
-            let _ = std::fs::remove_file(&temp_path);
+        if downloaded < expected {
+            // Keep the partial file so the next attempt can resume
             return Err(format!("Incomplete download: got {} bytes, expected {}", downloaded, expected));
         }
+        if downloaded > expected {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(format!("Corrupt download: got {} bytes, expected {}", downloaded, expected));
+        }
+    }
+
+    if downloaded < min_size {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("Downloaded model is too small ({} < {} bytes)", downloaded, min_size));
+    }
+
+    // The size check above is only a quick filter; the hash catches truncated or corrupted files
+    drop(file);
+    println!("[Whisper] Downloaded {} bytes, verifying checksum", downloaded);
+    if !checksum_matches(&temp_path, model).await? {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(format!("Downloaded model {} failed checksum verification", model_id));
     }
 
-    println!("[Whisper] Downloaded {} bytes, moving to final location", downloaded);
+    println!("[Whisper] Checksum verified, moving to final location");
 
     // Remove existing file and move temp to final
     let _ = std::fs::remove_file(&dest_path);
@@ -227,3 +942,40 @@ pub async fn download_whisper_model(app: AppHandle, model_id: String) -> Result<
 
     Ok(dest_path.to_string_lossy().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whisper_models_are_named_after_their_ids() {
+        for model in WHISPER_MODELS {
+            assert_eq!(model.filename, format!("ggml-{}.bin", model.id));
+            assert_eq!(model.sha256.len(), 64);
+            assert!(std::ptr::eq(whisper_model(model.id).unwrap(), model));
+        }
+        assert!(whisper_model("huge").is_err());
+    }
+
+    #[test]
+    fn trim_repeated_words_drops_words_from_the_overlap() {
+        assert_eq!(
+            trim_repeated_words("we should meet on Tuesday", "on tuesday. After lunch works"),
+            "After lunch works"
+        );
+    }
+
+    #[test]
+    fn trim_repeated_words_keeps_text_without_a_repeat() {
+        assert_eq!(
+            trim_repeated_words("we should meet on Tuesday", "after lunch works"),
+            "after lunch works"
+        );
+        assert_eq!(trim_repeated_words("", "after lunch"), "after lunch");
+    }
+
+    #[test]
+    fn trim_repeated_words_prefers_the_longest_repeat() {
+        assert_eq!(trim_repeated_words("one two one two", "one two one two three"), "three");
+    }
+}
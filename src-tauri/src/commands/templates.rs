@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+const STORE_PATH: &str = "settings.json";
+const PROMPT_TEMPLATES_KEY: &str = "prompt_templates";
+
+/// Reusable prompt whose body may contain `{name}` placeholders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub body: String,
+}
+
+fn read_templates(app: &AppHandle) -> Result<Vec<PromptTemplate>, String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match store.get(PROMPT_TEMPLATES_KEY) {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| format!("Invalid prompt templates setting: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn write_templates(app: &AppHandle, templates: &[PromptTemplate]) -> Result<(), String> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    let value = serde_json::to_value(templates)
+        .map_err(|e| format!("Failed to serialize prompt templates: {}", e))?;
+    store.set(PROMPT_TEMPLATES_KEY, value);
+
+    store.save()
+        .map_err(|e| format!("Failed to save store: {}", e))
+}
+
+/// Substitute `{name}` placeholders from `vars`. Braces that don't enclose a plain
+/// name (letters, digits, underscores) are kept as written, and substituted values
+/// aren't scanned again. Placeholders without a value stay verbatim unless `strict`.
+fn render_template(body: &str, vars: &HashMap<String, String>, strict: bool) -> Result<String, String> {
+    let mut rendered = String::with_capacity(body.len());
+    let mut missing: Vec<&str> = Vec::new();
+    let mut rest = body;
+
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+
+        if name_len == 0 || !after[name_len..].starts_with('}') {
+            rendered.push('{');
+            rest = after;
+            continue;
+        }
+
+        let name = &after[..name_len];
+        match vars.get(name) {
+            Some(value) => rendered.push_str(value),
+            None => {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+                rendered.push_str(&rest[open..open + name_len + 2]);
+            }
+        }
+        rest = &after[name_len + 1..];
+    }
+    rendered.push_str(rest);
+
+    if strict && !missing.is_empty() {
+        return Err(format!("Missing values for: {}", missing.join(", ")));
+    }
+    Ok(rendered)
+}
+
+#[tauri::command]
+pub async fn list_prompt_templates(app: AppHandle) -> Result<Vec<PromptTemplate>, String> {
+    read_templates(&app)
+}
+
+/// Create a template, or replace the body of the one with the same name
+#[tauri::command]
+pub async fn save_prompt_template(app: AppHandle, name: String, body: String) -> Result<PromptTemplate, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Template name cannot be empty".to_string());
+    }
+
+    let template = PromptTemplate { name, body };
+    let mut templates = read_templates(&app)?;
+    match templates.iter_mut().find(|t| t.name == template.name) {
+        Some(existing) => existing.body = template.body.clone(),
+        None => templates.push(template.clone()),
+    }
+    templates.sort_by_key(|t| t.name.to_lowercase());
+
+    write_templates(&app, &templates)?;
+    Ok(template)
+}
+
+/// Delete a template by name; returns whether one was removed
+#[tauri::command]
+pub async fn delete_prompt_template(app: AppHandle, name: String) -> Result<bool, String> {
+    let mut templates = read_templates(&app)?;
+    let before = templates.len();
+    templates.retain(|t| t.name != name);
+
+    if templates.len() == before {
+        return Ok(false);
+    }
+    write_templates(&app, &templates)?;
+    Ok(true)
+}
+
+/// Render a template with the given values. With `strict`, any placeholder left
+/// without a value is an error instead of being kept verbatim.
+#[tauri::command]
+pub async fn apply_prompt_template(
+    app: AppHandle,
+    name: String,
+    vars: HashMap<String, String>,
+    strict: Option<bool>,
+) -> Result<String, String> {
+    let templates = read_templates(&app)?;
+    let template = templates
+        .iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| format!("Prompt template not found: {}", name))?;
+
+    render_template(&template.body, &vars, strict.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn substitutes_every_placeholder() {
+        let rendered = render_template("Dear {name}, see you {day}. Bye {name}!", &vars(&[("name", "Ann"), ("day", "Monday")]), true);
+
+        assert_eq!(rendered.unwrap(), "Dear Ann, see you Monday. Bye Ann!");
+    }
+
+    #[test]
+    fn lenient_mode_keeps_missing_placeholders() {
+        let rendered = render_template("Dear {name}, see you {day}.", &vars(&[("name", "Ann")]), false);
+
+        assert_eq!(rendered.unwrap(), "Dear Ann, see you {day}.");
+    }
+
+    #[test]
+    fn strict_mode_names_each_missing_variable_once() {
+        let error = render_template("{greeting} {name}, {day} and {day}", &vars(&[("name", "Ann")]), true).unwrap_err();
+
+        assert_eq!(error, "Missing values for: greeting, day");
+    }
+
+    #[test]
+    fn unterminated_braces_are_kept_as_written() {
+        for strict in [false, true] {
+            let rendered = render_template("Open {{ and {name", &vars(&[("name", "Ann")]), strict);
+
+            assert_eq!(rendered.unwrap(), "Open {{ and {name");
+        }
+    }
+
+    #[test]
+    fn substituted_values_are_not_rescanned() {
+        let rendered = render_template("Note: {note}", &vars(&[("note", "{{x}} and {x}"), ("x", "boom")]), true);
+
+        assert_eq!(rendered.unwrap(), "Note: {{x}} and {x}");
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::commands::chat::{ImageRef, Message};
+use crate::providers::ImagePart;
+
+/// Images attached to messages, one file per image named by its id. Keeping them
+/// out of `database.json` keeps every database write small.
+pub fn images_dir(app: &AppHandle) -> PathBuf {
+    let app_dir = app.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("."));
+    app_dir.join("images")
+}
+
+fn write_image(dir: &Path, image: &ImagePart) -> Result<ImageRef> {
+    let bytes = STANDARD.decode(image.data.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid image data: {}", e))?;
+    let id = Uuid::new_v4().to_string();
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(&id), bytes)?;
+    Ok(ImageRef {
+        id,
+        mime_type: image.mime_type.clone(),
+        data: None,
+    })
+}
+
+fn read_image(dir: &Path, image: &ImageRef) -> Result<ImagePart> {
+    let data = match &image.data {
+        Some(data) => data.clone(),
+        None => {
+            let bytes = fs::read(dir.join(&image.id))
+                .map_err(|e| anyhow::anyhow!("Failed to read image {}: {}", image.id, e))?;
+            STANDARD.encode(bytes)
+        }
+    };
+    Ok(ImagePart {
+        mime_type: image.mime_type.clone(),
+        data,
+    })
+}
+
+/// Save images sent with a message and return the references to keep on it
+pub fn save_images(app: &AppHandle, images: &[ImagePart]) -> Result<Vec<ImageRef>> {
+    let dir = images_dir(app);
+    images.iter().map(|image| write_image(&dir, image)).collect()
+}
+
+/// Read a message's images back for sending to a provider
+pub fn load_images(app: &AppHandle, images: &[ImageRef]) -> Result<Vec<ImagePart>> {
+    let dir = images_dir(app);
+    images.iter().map(|image| read_image(&dir, image)).collect()
+}
+
+/// Move images stored inline by older versions out to files. An image that can't be
+/// written stays inline so nothing is lost. Returns the number moved.
+pub(super) fn move_inline_images(dir: &Path, messages: &mut [Message]) -> usize {
+    let mut moved = 0;
+    for image in messages.iter_mut().flat_map(|m| m.images.iter_mut()) {
+        let Some(data) = image.data.clone() else {
+            continue;
+        };
+        let inline = ImagePart { mime_type: image.mime_type.clone(), data };
+        match write_image(dir, &inline) {
+            Ok(stored) => {
+                *image = stored;
+                moved += 1;
+            }
+            Err(e) => eprintln!("[DB] Failed to move inline image out of the database: {}", e),
+        }
+    }
+    moved
+}
+
+/// Delete image files no message refers to any more, left behind by deleted
+/// messages and conversations. Returns the number deleted.
+pub(super) fn remove_unreferenced_images(dir: &Path, messages: &[Message]) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let referenced: HashSet<&str> = messages
+        .iter()
+        .flat_map(|m| m.images.iter())
+        .map(|image| image.id.as_str())
+        .collect();
+
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let unreferenced = path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !referenced.contains(name));
+        if path.is_file() && unreferenced {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message_with_images(images: serde_json::Value) -> Message {
+        serde_json::from_value(json!({
+            "id": "m1",
+            "conversation_id": "c1",
+            "role": "user",
+            "content": "What is this?",
+            "provider": "openai",
+            "model": "gpt-4o",
+            "created_at": "2026-01-01T00:00:00Z",
+            "images": images,
+        }))
+        .unwrap()
+    }
+
+    fn test_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("omnichat-images-test-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn inline_images_move_to_files_and_read_back() {
+        let dir = test_dir();
+        let mut messages = vec![message_with_images(json!([
+            { "mime_type": "image/png", "data": "aGVsbG8=" }
+        ]))];
+
+        let moved = move_inline_images(&dir, &mut messages);
+        let image = &messages[0].images[0];
+        let written = fs::read(dir.join(&image.id)).unwrap();
+        let part = read_image(&dir, image).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(moved, 1);
+        assert!(image.data.is_none());
+        assert_eq!(written, b"hello");
+        let serialized = serde_json::to_value(&messages[0]).unwrap();
+        assert!(serialized["images"][0].get("data").is_none());
+        assert_eq!(part.mime_type, "image/png");
+        assert_eq!(part.data, "aGVsbG8=");
+    }
+
+    #[test]
+    fn unreferenced_images_are_removed() {
+        let dir = test_dir();
+        let kept = write_image(&dir, &ImagePart {
+            mime_type: "image/png".to_string(),
+            data: "aGVsbG8=".to_string(),
+        })
+        .unwrap();
+        fs::write(dir.join("orphan"), b"stale").unwrap();
+        let messages = vec![message_with_images(json!([
+            { "id": kept.id, "mime_type": "image/png" }
+        ]))];
+
+        let removed = remove_unreferenced_images(&dir, &messages).unwrap();
+        let kept_exists = dir.join(&kept.id).exists();
+        let orphan_exists = dir.join("orphan").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(removed, 1);
+        assert!(kept_exists);
+        assert!(!orphan_exists);
+    }
+}
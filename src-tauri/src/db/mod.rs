@@ -1,14 +1,23 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::AppHandle;
 use tauri::Manager;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{RwLock, RwLockMappedWriteGuard, RwLockReadGuard, RwLockWriteGuard};
 use uuid::Uuid;
 use chrono::Utc;
 
-use crate::commands::chat::{Conversation, Message, SearchConversationResult};
+mod images;
+mod search;
+
+use crate::commands::chat::{BatchResult, Conversation, FavoriteMessage, Message, ModelLock, SearchConversationResult, TagCount};
 use crate::commands::knowledge::{Bucket, BucketFile};
+use crate::rag::EmbeddingModelChoice;
+use search::SearchIndex;
+
+pub use images::{images_dir, load_images, save_images};
 
 #[derive(Serialize, Deserialize, Default)]
 struct Database {
@@ -18,6 +27,38 @@ struct Database {
     bucket_files: Vec<BucketFile>,
 }
 
+/// The parsed database, read from disk on first use and kept in memory after that.
+/// Reads share the lock; writes hold it exclusively until the change is saved to disk,
+/// so concurrent commands can't clobber each other's writes.
+#[derive(Default)]
+pub struct DbState(RwLock<Option<Database>>);
+
+impl DbState {
+    async fn read(&self, load: impl Fn() -> Database) -> RwLockReadGuard<'_, Database> {
+        loop {
+            if let Ok(db) = RwLockReadGuard::try_map(self.0.read().await, |db| db.as_ref()) {
+                return db;
+            }
+            self.0.write().await.get_or_insert_with(&load);
+        }
+    }
+
+    async fn write(&self, load: impl FnOnce() -> Database) -> RwLockMappedWriteGuard<'_, Database> {
+        RwLockWriteGuard::map(self.0.write().await, |db| db.get_or_insert_with(load))
+    }
+}
+
+async fn read_db(app: &AppHandle) -> RwLockReadGuard<'_, Database> {
+    app.state::<DbState>().inner().read(|| load_db(app)).await
+}
+
+async fn write_db(app: &AppHandle) -> RwLockMappedWriteGuard<'_, Database> {
+    app.state::<DbState>().inner().write(|| load_db(app)).await
+}
+
+/// Full-text index for conversation search, dropped whenever the database is written
+static SEARCH_INDEX: std::sync::Mutex<Option<SearchIndex>> = std::sync::Mutex::new(None);
+
 fn get_db_path(app: &AppHandle) -> PathBuf {
     let app_dir = app.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("."));
     fs::create_dir_all(&app_dir).ok();
@@ -25,55 +66,239 @@ fn get_db_path(app: &AppHandle) -> PathBuf {
 }
 
 fn load_db(app: &AppHandle) -> Database {
-    let path = get_db_path(app);
-    if path.exists() {
-        let content = fs::read_to_string(&path).unwrap_or_default();
+    load_db_file(&get_db_path(app))
+}
+
+fn load_db_file(path: &Path) -> Database {
+    let mut db: Database = if path.exists() {
+        let content = fs::read_to_string(path).unwrap_or_default();
         serde_json::from_str(&content).unwrap_or_default()
     } else {
         Database::default()
+    };
+    assign_missing_seqs(&mut db);
+    db
+}
+
+/// Number messages saved before `seq` existed in their current display order.
+/// The sort is stable, so messages sharing a timestamp keep their save order.
+fn assign_missing_seqs(db: &mut Database) {
+    if db.messages.iter().all(|m| m.seq != 0) {
+        return;
+    }
+
+    let mut order: Vec<usize> = (0..db.messages.len()).collect();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&db.messages[a], &db.messages[b]);
+        a.created_at.cmp(&b.created_at).then(a.seq.cmp(&b.seq))
+    });
+    for (seq, index) in order.into_iter().enumerate() {
+        db.messages[index].seq = seq as u64 + 1;
     }
 }
 
+fn next_message_seq(db: &Database) -> u64 {
+    db.messages.iter().map(|m| m.seq).max().unwrap_or(0) + 1
+}
+
+/// Chronological order, with `seq` breaking ties between identical timestamps
+fn sort_messages(messages: &mut [Message]) {
+    messages.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.seq.cmp(&b.seq)));
+}
+
 fn save_db(app: &AppHandle, db: &Database) -> Result<()> {
-    let path = get_db_path(app);
+    save_db_file(&get_db_path(app), db)
+}
+
+fn save_db_file(path: &Path, db: &Database) -> Result<()> {
+    write_db_file(path, db)?;
+
+    if let Ok(mut index) = SEARCH_INDEX.lock() {
+        *index = None;
+    }
+    Ok(())
+}
+
+fn write_db_file(path: &Path, db: &Database) -> Result<()> {
     let content = serde_json::to_string_pretty(db)?;
-    fs::write(path, content)?;
+
+    // Write to a temp file first so a crash mid-write never truncates the real database
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, path)?;
     Ok(())
 }
 
+/// Load the database into memory at startup so the first command doesn't pay for it,
+/// dropping any empty replies left by streams the app quit during and moving inline
+/// images out to files
 pub async fn init_database(app: &AppHandle) -> Result<()> {
-    let _ = load_db(app);
+    let mut db = write_db(app).await;
+    let removed = remove_empty_replies(&mut db);
+    if removed > 0 {
+        println!("[DB] Removed {} empty assistant messages left by interrupted streams", removed);
+    }
+    let moved = images::move_inline_images(&images_dir(app), &mut db.messages);
+    if moved > 0 {
+        println!("[DB] Moved {} inline images to files", moved);
+    }
+    if removed > 0 || moved > 0 {
+        save_db(app, &db)?;
+    }
+
+    let orphaned = images::remove_unreferenced_images(&images_dir(app), &db.messages)?;
+    if orphaned > 0 {
+        println!("[DB] Removed {} images no message refers to", orphaned);
+    }
     Ok(())
 }
 
+/// Drop assistant messages with no content. Replies with some content that never
+/// finished streaming are kept, still marked `partial`.
+fn remove_empty_replies(db: &mut Database) -> usize {
+    let before = db.messages.len();
+    db.messages.retain(|m| m.role != "assistant" || !m.content.trim().is_empty());
+    before - db.messages.len()
+}
+
 // Conversation operations
+/// Serialized database contents, for backups
+pub async fn export_database(app: &AppHandle) -> Result<Vec<u8>> {
+    let db = read_db(app).await;
+    Ok(serde_json::to_vec_pretty(&*db)?)
+}
+
+/// Replace the whole database with a previously exported one
+pub async fn import_database(app: &AppHandle, content: &[u8]) -> Result<()> {
+    let mut db = write_db(app).await;
+    let mut imported: Database = serde_json::from_slice(content)?;
+    assign_missing_seqs(&mut imported);
+    images::move_inline_images(&images_dir(app), &mut imported.messages);
+    save_db(app, &imported)?;
+    *db = imported;
+    Ok(())
+}
+
 pub async fn create_conversation(app: &AppHandle, conversation: &Conversation) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.conversations.insert(0, conversation.clone());
     save_db(app, &db)
 }
 
-pub async fn get_conversations(app: &AppHandle) -> Result<Vec<Conversation>> {
-    let db = load_db(app);
-    let mut conversations = db.conversations;
+pub async fn get_conversations(app: &AppHandle, include_archived: bool) -> Result<Vec<Conversation>> {
+    let db = read_db(app).await;
+    let mut conversations: Vec<Conversation> = db.conversations
+        .iter()
+        .filter(|c| c.deleted_at.is_none())
+        .filter(|c| include_archived || !c.archived)
+        .cloned()
+        .collect();
+    sort_conversations(&mut conversations);
+    Ok(conversations)
+}
+
+/// Pinned conversations first, then the most recently updated
+fn sort_conversations(conversations: &mut [Conversation]) {
     conversations.sort_by(|a, b| {
         if a.pinned != b.pinned {
             return b.pinned.cmp(&a.pinned);
         }
         b.updated_at.cmp(&a.updated_at)
     });
-    Ok(conversations)
 }
 
+/// Return one page of the unarchived conversations in `folder` and/or carrying `tag`,
+/// in list order, with the number of conversations matching the filter
+pub async fn get_conversations_page(
+    app: &AppHandle,
+    offset: usize,
+    limit: usize,
+    folder: Option<&str>,
+    tag: Option<&str>,
+) -> Result<(Vec<Conversation>, usize)> {
+    let db = read_db(app).await;
+    let mut conversations: Vec<Conversation> = db.conversations
+        .iter()
+        .filter(|c| c.deleted_at.is_none() && !c.archived)
+        .filter(|c| folder.is_none_or(|f| c.folder.as_deref() == Some(f)))
+        .filter(|c| tag.is_none_or(|t| c.tags.iter().any(|ct| ct == t)))
+        .cloned()
+        .collect();
+    sort_conversations(&mut conversations);
+
+    let total_count = conversations.len();
+    let page = conversations.into_iter().skip(offset).take(limit).collect();
+    Ok((page, total_count))
+}
+
+/// A single conversation, archived or not; trashed conversations aren't found
+pub async fn get_conversation(app: &AppHandle, id: &str) -> Result<Conversation> {
+    let db = read_db(app).await;
+    db.conversations
+        .iter()
+        .find(|c| c.id == id && c.deleted_at.is_none())
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Conversation not found"))
+}
+
+/// Soft delete: the conversation and its messages stay on disk until purged
 pub async fn delete_conversation(app: &AppHandle, id: &str) -> Result<()> {
-    let mut db = load_db(app);
-    db.conversations.retain(|c| c.id != id);
-    db.messages.retain(|m| m.conversation_id != id);
+    let mut db = write_db(app).await;
+    if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
+        conv.deleted_at = Some(Utc::now().to_rfc3339());
+    }
     save_db(app, &db)
 }
 
+pub async fn restore_conversation(app: &AppHandle, id: &str) -> Result<()> {
+    let mut db = write_db(app).await;
+    let conv = db.conversations
+        .iter_mut()
+        .find(|c| c.id == id && c.deleted_at.is_some())
+        .ok_or_else(|| anyhow::anyhow!("Conversation is not in the trash"))?;
+    conv.deleted_at = None;
+    save_db(app, &db)
+}
+
+pub async fn get_trash(app: &AppHandle) -> Result<Vec<Conversation>> {
+    let db = read_db(app).await;
+    let mut trash: Vec<Conversation> = db.conversations
+        .iter()
+        .filter(|c| c.deleted_at.is_some())
+        .cloned()
+        .collect();
+    trash.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    Ok(trash)
+}
+
+/// Permanently remove conversations trashed at least `older_than_days` ago, with their messages
+pub async fn purge_deleted(app: &AppHandle, older_than_days: u32) -> Result<usize> {
+    let mut db = write_db(app).await;
+    let cutoff = Utc::now() - chrono::Duration::days(older_than_days as i64);
+
+    let purged: HashSet<String> = db.conversations
+        .iter()
+        .filter(|c| {
+            c.deleted_at
+                .as_deref()
+                .and_then(|deleted_at| chrono::DateTime::parse_from_rfc3339(deleted_at).ok())
+                .is_some_and(|deleted_at| deleted_at <= cutoff)
+        })
+        .map(|c| c.id.clone())
+        .collect();
+
+    if purged.is_empty() {
+        return Ok(0);
+    }
+
+    db.conversations.retain(|c| !purged.contains(&c.id));
+    db.messages.retain(|m| !purged.contains(&m.conversation_id));
+    save_db(app, &db)?;
+    Ok(purged.len())
+}
+
 pub async fn update_conversation_title(app: &AppHandle, id: &str, title: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
         conv.title = title.to_string();
     }
@@ -85,7 +310,7 @@ pub async fn update_conversation_tags(
     id: &str,
     tags: &[String],
 ) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
         conv.tags = tags.to_vec();
     }
@@ -97,27 +322,225 @@ pub async fn update_conversation_folder(
     id: &str,
     folder: Option<&str>,
 ) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
         conv.folder = folder.map(|value| value.to_string());
     }
     save_db(app, &db)
 }
 
+pub async fn update_conversation_system_prompt(
+    app: &AppHandle,
+    id: &str,
+    system_prompt: Option<&str>,
+) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
+        conv.system_prompt = system_prompt
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+    }
+    save_db(app, &db)
+}
+
+pub async fn get_conversation_system_prompt(app: &AppHandle, id: &str) -> Result<Option<String>> {
+    let db = read_db(app).await;
+    Ok(db.conversations
+        .iter()
+        .find(|c| c.id == id)
+        .and_then(|c| c.system_prompt.clone()))
+}
+
+pub async fn update_conversation_locked_model(
+    app: &AppHandle,
+    id: &str,
+    locked_model: Option<ModelLock>,
+) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
+        conv.locked_model = locked_model;
+    }
+    save_db(app, &db)
+}
+
+pub async fn get_conversation_locked_model(app: &AppHandle, id: &str) -> Result<Option<ModelLock>> {
+    let db = read_db(app).await;
+    Ok(db.conversations
+        .iter()
+        .find(|c| c.id == id)
+        .and_then(|c| c.locked_model.clone()))
+}
+
 pub async fn update_conversation_pinned(
     app: &AppHandle,
     id: &str,
     pinned: bool,
 ) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
         conv.pinned = pinned;
     }
     save_db(app, &db)
 }
 
+pub async fn update_conversation_archived(
+    app: &AppHandle,
+    id: &str,
+    archived: bool,
+) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
+        conv.archived = archived;
+    }
+    save_db(app, &db)
+}
+
+/// Apply `update` to every listed conversation that isn't in the trash, in a single load/save
+fn update_conversations(
+    db: &mut Database,
+    ids: &[String],
+    mut update: impl FnMut(&mut Conversation),
+) -> BatchResult {
+    let mut affected = 0;
+    let mut not_found = Vec::new();
+
+    for id in ids {
+        match db.conversations.iter_mut().find(|c| &c.id == id && c.deleted_at.is_none()) {
+            Some(conv) => {
+                update(conv);
+                affected += 1;
+            }
+            None => not_found.push(id.clone()),
+        }
+    }
+
+    BatchResult { affected, not_found }
+}
+
+pub async fn delete_conversations(app: &AppHandle, ids: &[String]) -> Result<BatchResult> {
+    let mut db = write_db(app).await;
+    let deleted_at = Utc::now().to_rfc3339();
+    let result = update_conversations(&mut db, ids, |conv| {
+        conv.deleted_at = Some(deleted_at.clone());
+    });
+    save_db(app, &db)?;
+    Ok(result)
+}
+
+pub async fn add_tag_to_conversations(app: &AppHandle, ids: &[String], tag: &str) -> Result<BatchResult> {
+    let mut db = write_db(app).await;
+    let result = update_conversations(&mut db, ids, |conv| {
+        if !conv.tags.iter().any(|t| t == tag) {
+            conv.tags.push(tag.to_string());
+        }
+    });
+    save_db(app, &db)?;
+    Ok(result)
+}
+
+pub async fn move_conversations_to_folder(
+    app: &AppHandle,
+    ids: &[String],
+    folder: Option<&str>,
+) -> Result<BatchResult> {
+    let mut db = write_db(app).await;
+    let result = update_conversations(&mut db, ids, |conv| {
+        conv.folder = folder.map(|value| value.to_string());
+    });
+    save_db(app, &db)?;
+    Ok(result)
+}
+
+/// Replace tag `from` with `to` on every conversation, including trashed ones,
+/// without duplicating `to` where it's already present. Returns how many changed.
+pub async fn rename_tag(app: &AppHandle, from: &str, to: &str) -> Result<usize> {
+    let mut db = write_db(app).await;
+    let mut changed = 0;
+    for conv in db.conversations.iter_mut() {
+        if !conv.tags.iter().any(|t| t == from) {
+            continue;
+        }
+        let has_target = conv.tags.iter().any(|t| t == to);
+        let mut renamed = false;
+        conv.tags.retain_mut(|t| {
+            if t != from {
+                return true;
+            }
+            // Keep the first occurrence in place unless the target tag is already there
+            if has_target || renamed {
+                return false;
+            }
+            *t = to.to_string();
+            renamed = true;
+            true
+        });
+        changed += 1;
+    }
+
+    if changed > 0 {
+        save_db(app, &db)?;
+    }
+    Ok(changed)
+}
+
+/// Remove a tag from every conversation, including trashed ones. Returns how many changed.
+pub async fn delete_tag(app: &AppHandle, tag: &str) -> Result<usize> {
+    let mut db = write_db(app).await;
+    let mut changed = 0;
+    for conv in db.conversations.iter_mut() {
+        let before = conv.tags.len();
+        conv.tags.retain(|t| t != tag);
+        if conv.tags.len() != before {
+            changed += 1;
+        }
+    }
+
+    if changed > 0 {
+        save_db(app, &db)?;
+    }
+    Ok(changed)
+}
+
+/// Every tag in use on a conversation outside the trash, with how many use it,
+/// most used first
+pub async fn get_all_tags(app: &AppHandle) -> Result<Vec<TagCount>> {
+    let db = read_db(app).await;
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for conv in db.conversations.iter().filter(|c| c.deleted_at.is_none()) {
+        let tags: HashSet<&str> = conv.tags.iter().map(String::as_str).collect();
+        for tag in tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+
+    let mut tags: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag: tag.to_string(), count })
+        .collect();
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    Ok(tags)
+}
+
+/// Move every conversation in folder `from` to `to` (or out of any folder), including
+/// trashed ones so they come back in the right place. Returns how many were moved.
+pub async fn reassign_folder(app: &AppHandle, from: &str, to: Option<&str>) -> Result<usize> {
+    let mut db = write_db(app).await;
+    let mut moved = 0;
+    for conv in db.conversations.iter_mut() {
+        if conv.folder.as_deref().map(str::trim) == Some(from) {
+            conv.folder = to.map(|value| value.to_string());
+            moved += 1;
+        }
+    }
+
+    if moved > 0 {
+        save_db(app, &db)?;
+    }
+    Ok(moved)
+}
+
 pub async fn update_conversation_timestamp(app: &AppHandle, id: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(conv) = db.conversations.iter_mut().find(|c| c.id == id) {
         conv.updated_at = chrono::Utc::now().to_rfc3339();
     }
@@ -126,8 +549,36 @@ pub async fn update_conversation_timestamp(app: &AppHandle, id: &str) -> Result<
 
 // Message operations
 pub async fn save_message(app: &AppHandle, message: &Message) -> Result<()> {
-    let mut db = load_db(app);
-    db.messages.push(message.clone());
+    save_message_to(app.state::<DbState>().inner(), &get_db_path(app), message).await
+}
+
+/// Append a message to the database at `path`, whose in-memory copy `state` holds
+async fn save_message_to(state: &DbState, path: &Path, message: &Message) -> Result<()> {
+    let mut db = state.write(|| load_db_file(path)).await;
+    append_message(&mut db, message);
+    save_db_file(path, &db)
+}
+
+fn append_message(db: &mut Database, message: &Message) {
+    let seq = next_message_seq(db);
+    db.messages.push(Message { seq, ..message.clone() });
+}
+
+/// Save a finished regeneration and drop the answer it replaces in a single write
+pub async fn replace_message(app: &AppHandle, replaced_id: &str, message: &Message) -> Result<()> {
+    let mut db = write_db(app).await;
+    db.messages.retain(|m| m.id != replaced_id);
+    append_message(&mut db, message);
+    save_db(app, &db)
+}
+
+/// Write a streamed reply's final content, clearing `partial` once it completed
+pub async fn finish_message(app: &AppHandle, message_id: &str, content: &str, partial: bool) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(message) = db.messages.iter_mut().find(|m| m.id == message_id) {
+        message.content = content.to_string();
+        message.partial = partial;
+    }
     save_db(app, &db)
 }
 
@@ -136,34 +587,110 @@ pub async fn update_message_content(
     message_id: &str,
     content: &str,
 ) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     if let Some(message) = db.messages.iter_mut().find(|m| m.id == message_id) {
         message.content = content.to_string();
     }
     save_db(app, &db)
 }
 
+pub async fn toggle_message_favorite(app: &AppHandle, message_id: &str) -> Result<bool> {
+    let mut db = write_db(app).await;
+    let message = db.messages
+        .iter_mut()
+        .find(|m| m.id == message_id)
+        .ok_or_else(|| anyhow::anyhow!("Message not found"))?;
+    message.favorited = !message.favorited;
+    let favorited = message.favorited;
+    save_db(app, &db)?;
+    Ok(favorited)
+}
+
+pub async fn get_favorite_messages(app: &AppHandle) -> Result<Vec<FavoriteMessage>> {
+    let db = read_db(app).await;
+    let titles: HashMap<&str, &str> = db.conversations
+        .iter()
+        .filter(|c| c.deleted_at.is_none())
+        .map(|c| (c.id.as_str(), c.title.as_str()))
+        .collect();
+
+    let mut favorites: Vec<FavoriteMessage> = db.messages
+        .iter()
+        .filter(|m| m.favorited)
+        .filter_map(|m| {
+            let title = titles.get(m.conversation_id.as_str())?;
+            Some(FavoriteMessage {
+                message: m.clone(),
+                conversation_title: title.to_string(),
+            })
+        })
+        .collect();
+    favorites.sort_by(|a, b| {
+        (&b.message.created_at, b.message.seq).cmp(&(&a.message.created_at, a.message.seq))
+    });
+
+    Ok(favorites)
+}
+
 pub async fn delete_message(app: &AppHandle, message_id: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.messages.retain(|m| m.id != message_id);
     save_db(app, &db)
 }
 
-pub async fn get_messages(app: &AppHandle, conversation_id: &str) -> Result<Vec<Message>> {
-    let db = load_db(app);
+/// A conversation's messages, oldest first. Messages follow their conversation
+/// into the trash, so a trashed conversation has none.
+fn conversation_messages(db: &Database, conversation_id: &str) -> Vec<Message> {
+    let trashed = db.conversations
+        .iter()
+        .any(|c| c.id == conversation_id && c.deleted_at.is_some());
+    if trashed {
+        return Vec::new();
+    }
+
     let mut messages: Vec<Message> = db.messages
-        .into_iter()
+        .iter()
         .filter(|m| m.conversation_id == conversation_id)
+        .cloned()
         .collect();
-    messages.sort_by(|a, b| a.created_at.cmp(&b.created_at));
-    Ok(messages)
+    sort_messages(&mut messages);
+    messages
+}
+
+pub async fn get_messages(app: &AppHandle, conversation_id: &str) -> Result<Vec<Message>> {
+    let db = read_db(app).await;
+    Ok(conversation_messages(&db, conversation_id))
+}
+
+/// Return one page of a conversation's messages (oldest first) and the total message count
+pub async fn get_messages_paginated(
+    app: &AppHandle,
+    conversation_id: &str,
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<Message>, usize)> {
+    let db = read_db(app).await;
+    let messages = conversation_messages(&db, conversation_id);
+
+    let total_count = messages.len();
+    let page = messages.into_iter().skip(offset).take(limit).collect();
+    Ok((page, total_count))
 }
 
 fn build_snippet(content: &str, match_index: usize, match_len: usize) -> String {
     let preview_radius = 40usize;
-    let start = match_index.saturating_sub(preview_radius);
-    let end = (match_index + match_len + preview_radius).min(content.len());
-    let snippet = content.get(start..end).unwrap_or(content).trim();
+    let mut start = match_index.saturating_sub(preview_radius).min(content.len());
+    let mut end = (match_index + match_len + preview_radius).min(content.len());
+
+    // Byte offsets can land inside a multibyte character; widen to the enclosing boundaries
+    while !content.is_char_boundary(start) {
+        start -= 1;
+    }
+    while !content.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let snippet = content[start..end].trim();
     let prefix = if start > 0 { "..." } else { "" };
     let suffix = if end < content.len() { "..." } else { "" };
     format!("{}{}{}", prefix, snippet, suffix)
@@ -173,98 +700,88 @@ pub async fn search_conversations(
     app: &AppHandle,
     query: &str,
 ) -> Result<Vec<SearchConversationResult>> {
-    let db = load_db(app);
-    let needle = query.to_lowercase();
-    let mut results: Vec<SearchConversationResult> = Vec::new();
-
-    for conv in db.conversations.iter() {
-        let title_lower = conv.title.to_lowercase();
-        if title_lower.contains(&needle) {
-            results.push(SearchConversationResult {
-                id: conv.id.clone(),
-                title: conv.title.clone(),
-                updated_at: conv.updated_at.clone(),
-                snippet: "Title match".to_string(),
-                pinned: conv.pinned,
-                tags: conv.tags.clone(),
-                folder: conv.folder.clone(),
-            });
-            continue;
-        }
+    let db = read_db(app).await;
+    let mut cached = SEARCH_INDEX
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Search index lock poisoned"))?;
 
-        if let Some(tag) = conv
-            .tags
+    // Rebuilt lazily after any write invalidates it
+    let index = cached.get_or_insert_with(|| {
+        let conversations: Vec<Conversation> = db.conversations
             .iter()
-            .find(|tag| tag.to_lowercase().contains(&needle))
-        {
-            results.push(SearchConversationResult {
-                id: conv.id.clone(),
-                title: conv.title.clone(),
-                updated_at: conv.updated_at.clone(),
-                snippet: format!("Tag: {}", tag),
-                pinned: conv.pinned,
-                tags: conv.tags.clone(),
-                folder: conv.folder.clone(),
-            });
-            continue;
-        }
+            .filter(|c| c.deleted_at.is_none())
+            .cloned()
+            .collect();
+        SearchIndex::build(&conversations, &db.messages)
+    });
 
-        if let Some(folder) = conv
-            .folder
-            .as_ref()
-            .and_then(|value| {
-                if value.to_lowercase().contains(&needle) {
-                    Some(value)
-                } else {
-                    None
-                }
-            })
-        {
-            results.push(SearchConversationResult {
-                id: conv.id.clone(),
-                title: conv.title.clone(),
-                updated_at: conv.updated_at.clone(),
-                snippet: format!("Folder: {}", folder),
-                pinned: conv.pinned,
-                tags: conv.tags.clone(),
-                folder: conv.folder.clone(),
-            });
-            continue;
-        }
+    Ok(index.search(query))
+}
 
-        for msg in db.messages.iter().filter(|m| m.conversation_id == conv.id) {
-            let content_lower = msg.content.to_lowercase();
-            if let Some(index) = content_lower.find(&needle) {
-                let snippet = build_snippet(&msg.content, index, needle.len());
-                results.push(SearchConversationResult {
-                    id: conv.id.clone(),
-                    title: conv.title.clone(),
-                    updated_at: conv.updated_at.clone(),
-                    snippet,
-                    pinned: conv.pinned,
-                    tags: conv.tags.clone(),
-                    folder: conv.folder.clone(),
-                });
-                break;
-            }
-        }
+pub async fn clone_conversation(
+    app: &AppHandle,
+    source_id: &str,
+    title: &str,
+) -> Result<Conversation> {
+    let mut db = write_db(app).await;
+    let source = db
+        .conversations
+        .iter()
+        .find(|c| c.id == source_id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Conversation not found"))?;
+
+    let new_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+
+    let conversation = Conversation {
+        id: new_id.clone(),
+        title: title.to_string(),
+        created_at: now.clone(),
+        updated_at: now,
+        pinned: false,
+        tags: source.tags.clone(),
+        folder: source.folder.clone(),
+        system_prompt: source.system_prompt.clone(),
+        archived: false,
+        deleted_at: None,
+        locked_model: source.locked_model.clone(),
+    };
+
+    db.conversations.insert(0, conversation.clone());
+
+    let first_seq = next_message_seq(&db);
+    let mut cloned_messages: Vec<Message> = db.messages
+        .iter()
+        .filter(|m| m.conversation_id == source_id)
+        .map(|message| {
+            let mut cloned = message.clone();
+            cloned.id = Uuid::new_v4().to_string();
+            cloned.conversation_id = new_id.clone();
+            // Stars stay with the original, like pins on the conversation itself
+            cloned.favorited = false;
+            cloned
+        })
+        .collect();
+    
+    sort_messages(&mut cloned_messages);
+    for (offset, message) in cloned_messages.iter_mut().enumerate() {
+        message.seq = first_seq + offset as u64;
     }
+    db.messages.extend(cloned_messages);
 
-    results.sort_by(|a, b| {
-        if a.pinned != b.pinned {
-            return b.pinned.cmp(&a.pinned);
-        }
-        b.updated_at.cmp(&a.updated_at)
-    });
-    Ok(results)
+    save_db(app, &db)?;
+    Ok(conversation)
 }
 
-pub async fn clone_conversation(
+/// Clone a conversation up to and including the given message
+pub async fn branch_conversation(
     app: &AppHandle,
     source_id: &str,
+    from_message_id: &str,
     title: &str,
 ) -> Result<Conversation> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     let source = db
         .conversations
         .iter()
@@ -272,6 +789,13 @@ pub async fn clone_conversation(
         .cloned()
         .ok_or_else(|| anyhow::anyhow!("Conversation not found"))?;
 
+    let (branch_point, branch_seq) = db.messages
+        .iter()
+        .find(|m| m.id == from_message_id && m.conversation_id == source_id)
+        .map(|m| (m.created_at.clone(), m.seq))
+        .ok_or_else(|| anyhow::anyhow!("Message not found in conversation"))?;
+    let branch_time = chrono::DateTime::parse_from_rfc3339(&branch_point).ok();
+
     let new_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
@@ -283,22 +807,40 @@ pub async fn clone_conversation(
         pinned: false,
         tags: source.tags.clone(),
         folder: source.folder.clone(),
+        system_prompt: source.system_prompt.clone(),
+        archived: false,
+        deleted_at: None,
+        locked_model: source.locked_model.clone(),
     };
 
     db.conversations.insert(0, conversation.clone());
 
-    let cloned_messages: Vec<Message> = db.messages
+    let first_seq = next_message_seq(&db);
+    let mut branched_messages: Vec<Message> = db.messages
         .iter()
         .filter(|m| m.conversation_id == source_id)
+        .filter(|m| {
+            let ordering = match (branch_time, chrono::DateTime::parse_from_rfc3339(&m.created_at).ok()) {
+                (Some(branch_time), Some(created)) => created.cmp(&branch_time),
+                _ => m.created_at.cmp(&branch_point),
+            };
+            ordering.then(m.seq.cmp(&branch_seq)).is_le()
+        })
         .map(|message| {
             let mut cloned = message.clone();
             cloned.id = Uuid::new_v4().to_string();
             cloned.conversation_id = new_id.clone();
+            // Stars stay with the original, like pins on the conversation itself
+            cloned.favorited = false;
             cloned
         })
         .collect();
     
-    db.messages.extend(cloned_messages);
+    sort_messages(&mut branched_messages);
+    for (offset, message) in branched_messages.iter_mut().enumerate() {
+        message.seq = first_seq + offset as u64;
+    }
+    db.messages.extend(branched_messages);
 
     save_db(app, &db)?;
     Ok(conversation)
@@ -306,25 +848,34 @@ pub async fn clone_conversation(
 
 // Bucket operations
 pub async fn create_bucket(app: &AppHandle, bucket: &Bucket) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.buckets.insert(0, bucket.clone());
     save_db(app, &db)
 }
 
 pub async fn get_buckets(app: &AppHandle) -> Result<Vec<Bucket>> {
-    let db = load_db(app);
-    Ok(db.buckets)
+    let db = read_db(app).await;
+    Ok(db.buckets.clone())
+}
+
+pub async fn get_bucket(app: &AppHandle, id: &str) -> Result<Bucket> {
+    let db = read_db(app).await;
+    db.buckets
+        .iter()
+        .find(|b| b.id == id)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("Bucket not found"))
 }
 
 pub async fn delete_bucket(app: &AppHandle, id: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.buckets.retain(|b| b.id != id);
     db.bucket_files.retain(|f| f.bucket_id != id);
     save_db(app, &db)
 }
 
 pub async fn update_bucket_file_count(app: &AppHandle, bucket_id: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     let count = db.bucket_files.iter().filter(|f| f.bucket_id == bucket_id).count() as i32;
     if let Some(bucket) = db.buckets.iter_mut().find(|b| b.id == bucket_id) {
         bucket.file_count = count;
@@ -332,20 +883,212 @@ pub async fn update_bucket_file_count(app: &AppHandle, bucket_id: &str) -> Resul
     save_db(app, &db)
 }
 
+pub async fn get_buckets_by_tag(app: &AppHandle, tag: &str) -> Result<Vec<Bucket>> {
+    let db = read_db(app).await;
+    Ok(db.buckets
+        .iter()
+        .filter(|b| b.tags.iter().any(|t| t == tag))
+        .cloned()
+        .collect())
+}
+
+pub async fn update_bucket(app: &AppHandle, id: &str, name: &str, description: &str) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(bucket) = db.buckets.iter_mut().find(|b| b.id == id) {
+        bucket.name = name.to_string();
+        bucket.description = description.to_string();
+    }
+    save_db(app, &db)
+}
+
+pub async fn update_bucket_tags(app: &AppHandle, id: &str, tags: &[String]) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(bucket) = db.buckets.iter_mut().find(|b| b.id == id) {
+        bucket.tags = tags.to_vec();
+    }
+    save_db(app, &db)
+}
+
+pub async fn update_bucket_embedding_model(
+    app: &AppHandle,
+    bucket_id: &str,
+    model: EmbeddingModelChoice,
+) -> Result<()> {
+    let mut db = write_db(app).await;
+    if let Some(bucket) = db.buckets.iter_mut().find(|b| b.id == bucket_id) {
+        bucket.embedding_model = model;
+    }
+    save_db(app, &db)
+}
+
 // Bucket file operations
 pub async fn create_bucket_file(app: &AppHandle, file: &BucketFile) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.bucket_files.insert(0, file.clone());
     save_db(app, &db)
 }
 
 pub async fn get_bucket_files(app: &AppHandle, bucket_id: &str) -> Result<Vec<BucketFile>> {
-    let db = load_db(app);
-    Ok(db.bucket_files.into_iter().filter(|f| f.bucket_id == bucket_id).collect())
+    let db = read_db(app).await;
+    Ok(db.bucket_files.iter().filter(|f| f.bucket_id == bucket_id).cloned().collect())
+}
+
+/// Overwrite the recorded chunk count of each file in the bucket, keyed by filename
+pub async fn update_bucket_file_chunk_counts(
+    app: &AppHandle,
+    bucket_id: &str,
+    counts: &HashMap<String, usize>,
+) -> Result<()> {
+    let mut db = write_db(app).await;
+    for file in db.bucket_files.iter_mut().filter(|f| f.bucket_id == bucket_id) {
+        file.chunk_count = counts.get(&file.filename).copied().unwrap_or(0) as i32;
+    }
+    save_db(app, &db)
 }
 
 pub async fn delete_bucket_file(app: &AppHandle, file_id: &str) -> Result<()> {
-    let mut db = load_db(app);
+    let mut db = write_db(app).await;
     db.bucket_files.retain(|f| f.id != file_id);
     save_db(app, &db)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn message(id: usize) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": format!("message-{}", id),
+            "conversation_id": "conversation",
+            "role": "user",
+            "content": format!("Message {}", id),
+            "provider": "openai",
+            "model": "gpt-4o",
+            "created_at": "2025-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    /// Races `save_message` against a database file of the test's own
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_saves_lose_no_messages() {
+        const COUNT: usize = 64;
+        let dir = std::env::temp_dir().join(format!("omnichat-db-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = Arc::new(dir.join("database.json"));
+        let state = Arc::new(DbState::default());
+
+        let saves: Vec<_> = (0..COUNT)
+            .map(|i| {
+                let (state, path) = (state.clone(), path.clone());
+                tokio::spawn(async move {
+                    save_message_to(&state, &path, &message(i)).await.unwrap();
+                })
+            })
+            .collect();
+        for save in saves {
+            save.await.unwrap();
+        }
+
+        let saved = load_db_file(&path);
+        let temp_left_behind = path.with_extension("json.tmp").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(saved.messages.len(), COUNT);
+        let ids: HashSet<&str> = saved.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids.len(), COUNT);
+        let mut seqs: Vec<u64> = saved.messages.iter().map(|m| m.seq).collect();
+        seqs.sort_unstable();
+        assert_eq!(seqs, (1..=COUNT as u64).collect::<Vec<_>>());
+        assert!(!temp_left_behind);
+    }
+
+    #[test]
+    fn startup_cleanup_removes_only_empty_replies() {
+        let reply = |id: usize, content: &str, partial: bool| Message {
+            role: "assistant".to_string(),
+            content: content.to_string(),
+            partial,
+            ..message(id)
+        };
+        let mut db = Database {
+            messages: vec![
+                message(0),
+                reply(1, "", true),
+                reply(2, "Half an ans", true),
+                reply(3, "  \n", false),
+                reply(4, "A full answer.", false),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(remove_empty_replies(&mut db), 2);
+        let kept: Vec<(&str, bool)> = db.messages.iter().map(|m| (m.id.as_str(), m.partial)).collect();
+        assert_eq!(kept, vec![("message-0", false), ("message-2", true), ("message-4", false)]);
+    }
+
+    #[test]
+    fn trashed_conversation_has_no_messages() {
+        let conversation: Conversation = serde_json::from_value(serde_json::json!({
+            "id": "conversation",
+            "title": "Colors",
+            "created_at": "2025-01-01T00:00:00Z",
+            "updated_at": "2025-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        let mut db = Database {
+            conversations: vec![conversation],
+            messages: vec![message(0), message(1)],
+            ..Default::default()
+        };
+        assert_eq!(conversation_messages(&db, "conversation").len(), 2);
+
+        db.conversations[0].deleted_at = Some("2025-01-02T00:00:00Z".to_string());
+        assert!(conversation_messages(&db, "conversation").is_empty());
+    }
+
+    #[test]
+    fn snippet_of_match_at_start_has_no_leading_ellipsis() {
+        let content = format!("needle{}", " filler".repeat(20));
+        let snippet = build_snippet(&content, 0, "needle".len());
+
+        assert!(snippet.starts_with("needle"), "{}", snippet);
+        assert!(snippet.ends_with("..."), "{}", snippet);
+    }
+
+    #[test]
+    fn snippet_of_match_at_end_has_no_trailing_ellipsis() {
+        let content = format!("{}needle", "filler ".repeat(20));
+        let snippet = build_snippet(&content, content.len() - "needle".len(), "needle".len());
+
+        assert!(snippet.starts_with("..."), "{}", snippet);
+        assert!(snippet.ends_with("needle"), "{}", snippet);
+    }
+
+    #[test]
+    fn snippet_widens_to_emoji_boundaries() {
+        // The ASCII around the match puts both radius cut points inside four-byte emoji
+        let content = format!("{}xyneedlez{}", "😀".repeat(20), "😀".repeat(20));
+        let snippet = build_snippet(&content, content.find("needle").unwrap(), "needle".len());
+
+        assert_eq!(snippet, format!("...{}xyneedlez{}...", "😀".repeat(10), "😀".repeat(10)));
+    }
+
+    #[test]
+    fn snippet_widens_to_cjk_boundaries() {
+        let content = format!("{}needle{}", "漢".repeat(30), "字".repeat(30));
+        let snippet = build_snippet(&content, content.find("needle").unwrap(), "needle".len());
+
+        assert_eq!(snippet, format!("...{}needle{}...", "漢".repeat(14), "字".repeat(14)));
+    }
+
+    #[test]
+    fn snippet_of_short_multibyte_content_is_whole() {
+        let content = "日本語 needle 🎉";
+        let snippet = build_snippet(content, content.find("needle").unwrap(), "needle".len());
+
+        assert_eq!(snippet, content);
+    }
+}
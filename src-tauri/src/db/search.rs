@@ -0,0 +1,352 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::commands::chat::{Conversation, Message, SearchConversationResult};
+
+/// Relevance multipliers so title and tag hits outrank passing mentions in messages
+const TITLE_WEIGHT: f32 = 3.0;
+const TAG_WEIGHT: f32 = 2.0;
+const FOLDER_WEIGHT: f32 = 2.0;
+const MESSAGE_WEIGHT: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DocKind {
+    Title,
+    Tag,
+    Folder,
+    Message,
+}
+
+impl DocKind {
+    fn weight(self) -> f32 {
+        match self {
+            DocKind::Title => TITLE_WEIGHT,
+            DocKind::Tag => TAG_WEIGHT,
+            DocKind::Folder => FOLDER_WEIGHT,
+            DocKind::Message => MESSAGE_WEIGHT,
+        }
+    }
+}
+
+struct Doc {
+    conversation: usize,
+    kind: DocKind,
+    text: String,
+    token_count: usize,
+}
+
+/// Occurrences of a term in one document, as (byte offset, byte length) into the original text
+struct Posting {
+    doc: usize,
+    spans: Vec<(usize, usize)>,
+}
+
+/// A conversation's best matching document, used for ranking and its snippet
+#[derive(Clone, Copy)]
+struct Hit {
+    score: f32,
+    doc: usize,
+    /// Byte offset and length of the match in the document's text
+    span: (usize, usize),
+}
+
+/// Inverted index over conversation titles, tags, folders and message content
+pub struct SearchIndex {
+    conversations: Vec<Conversation>,
+    docs: Vec<Doc>,
+    terms: BTreeMap<String, Vec<Posting>>,
+    avg_doc_len: f32,
+}
+
+/// Split text into lowercase word tokens along with their byte span in the original text
+fn tokenize(text: &str) -> Vec<(String, usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (i, c) in text.char_indices() {
+        if c.is_alphanumeric() {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            tokens.push((text[s..i].to_lowercase(), s, i - s));
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((text[s..].to_lowercase(), s, text.len() - s));
+    }
+
+    tokens
+}
+
+impl SearchIndex {
+    pub fn build(conversations: &[Conversation], messages: &[Message]) -> Self {
+        let mut index = SearchIndex {
+            conversations: conversations.to_vec(),
+            docs: Vec::new(),
+            terms: BTreeMap::new(),
+            avg_doc_len: 0.0,
+        };
+
+        let positions: HashMap<&str, usize> = conversations
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.as_str(), i))
+            .collect();
+
+        for (i, conv) in conversations.iter().enumerate() {
+            index.add_doc(i, DocKind::Title, &conv.title);
+            for tag in &conv.tags {
+                index.add_doc(i, DocKind::Tag, tag);
+            }
+            if let Some(folder) = &conv.folder {
+                index.add_doc(i, DocKind::Folder, folder);
+            }
+        }
+
+        for message in messages {
+            if let Some(&i) = positions.get(message.conversation_id.as_str()) {
+                index.add_doc(i, DocKind::Message, &message.content);
+            }
+        }
+
+        let total_tokens: usize = index.docs.iter().map(|d| d.token_count).sum();
+        index.avg_doc_len = total_tokens as f32 / index.docs.len().max(1) as f32;
+        index
+    }
+
+    fn add_doc(&mut self, conversation: usize, kind: DocKind, text: &str) {
+        let doc = self.docs.len();
+        let tokens = tokenize(text);
+
+        let mut spans_by_term: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for (term, offset, len) in &tokens {
+            spans_by_term.entry(term.clone()).or_default().push((*offset, *len));
+        }
+        for (term, spans) in spans_by_term {
+            self.terms.entry(term).or_default().push(Posting { doc, spans });
+        }
+
+        self.docs.push(Doc {
+            conversation,
+            kind,
+            text: text.to_string(),
+            token_count: tokens.len(),
+        });
+    }
+
+    /// Rank conversations matching every query term (prefix match on each term),
+    /// ordered by relevance, then pinned first, then most recently updated. When no
+    /// conversation matches that way, such as for a query that is mostly punctuation
+    /// (`c++`) or part of a word (`script` in `JavaScript`), fall back to a plain
+    /// case-insensitive substring match.
+    pub fn search(&self, query: &str) -> Vec<SearchConversationResult> {
+        let results = self.term_search(query);
+        if results.is_empty() {
+            self.substring_search(query)
+        } else {
+            results
+        }
+    }
+
+    fn term_search(&self, query: &str) -> Vec<SearchConversationResult> {
+        let query_terms: Vec<String> = tokenize(query).into_iter().map(|(t, _, _)| t).collect();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        // Per conversation: total score, and the best single hit for the snippet
+        let mut scores: HashMap<usize, f32> = HashMap::new();
+        let mut best_hits: HashMap<usize, Hit> = HashMap::new();
+        let mut matched_terms: HashMap<usize, usize> = HashMap::new();
+        let doc_count = self.docs.len() as f32;
+
+        for query_term in &query_terms {
+            let mut term_scores: HashMap<usize, f32> = HashMap::new();
+
+            for (_, postings) in self.terms
+                .range(query_term.clone()..)
+                .take_while(|(term, _)| term.starts_with(query_term.as_str()))
+            {
+                let df = postings.len() as f32;
+                let idf = ((doc_count - df + 0.5) / (df + 0.5) + 1.0).ln();
+
+                for posting in postings {
+                    let doc = &self.docs[posting.doc];
+                    // BM25 term frequency saturation with document length normalization
+                    let tf = posting.spans.len() as f32;
+                    let len_norm = 1.0 - 0.75 + 0.75 * doc.token_count as f32 / self.avg_doc_len.max(1.0);
+                    let score = idf * tf * 2.2 / (tf + 1.2 * len_norm) * doc.kind.weight();
+
+                    *term_scores.entry(doc.conversation).or_default() += score;
+
+                    let hit = Hit { score, doc: posting.doc, span: posting.spans[0] };
+                    let best = best_hits.entry(doc.conversation).or_insert(Hit { score: 0.0, ..hit });
+                    if score > best.score {
+                        *best = hit;
+                    }
+                }
+            }
+
+            for (conversation, score) in term_scores {
+                *scores.entry(conversation).or_default() += score;
+                *matched_terms.entry(conversation).or_default() += 1;
+            }
+        }
+
+        let mut ranked: Vec<(usize, f32)> = scores
+            .into_iter()
+            .filter(|(conversation, _)| matched_terms.get(conversation) == Some(&query_terms.len()))
+            .collect();
+
+        ranked.sort_by(|(a_idx, a_score), (b_idx, b_score)| {
+            let a = &self.conversations[*a_idx];
+            let b = &self.conversations[*b_idx];
+            b_score
+                .partial_cmp(a_score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.pinned.cmp(&a.pinned))
+                .then_with(|| b.updated_at.cmp(&a.updated_at))
+        });
+
+        ranked
+            .into_iter()
+            .map(|(conversation, _)| self.result(conversation, best_hits.get(&conversation)))
+            .collect()
+    }
+
+    /// Conversations with a document containing the whole query, ignoring case. Each
+    /// one's best document decides its rank, then pinned first, then most recently updated.
+    fn substring_search(&self, query: &str) -> Vec<SearchConversationResult> {
+        let needle = query.trim().to_lowercase();
+        if needle.is_empty() {
+            return Vec::new();
+        }
+
+        // Per conversation: the highest weighted matching document and the match's span
+        let mut best_hits: HashMap<usize, Hit> = HashMap::new();
+        for (i, doc) in self.docs.iter().enumerate() {
+            let text = doc.text.to_lowercase();
+            let Some(offset) = text.find(&needle) else { continue };
+            // Lowercasing can change byte lengths; then the offset doesn't map back
+            let span = if text.len() == doc.text.len() { (offset, needle.len()) } else { (0, 0) };
+
+            let hit = Hit { score: doc.kind.weight(), doc: i, span };
+            let best = best_hits.entry(doc.conversation).or_insert(hit);
+            if hit.score > best.score {
+                *best = hit;
+            }
+        }
+
+        let mut ranked: Vec<(usize, Hit)> = best_hits.into_iter().collect();
+        ranked.sort_by(|(a_idx, a_hit), (b_idx, b_hit)| {
+            let a = &self.conversations[*a_idx];
+            let b = &self.conversations[*b_idx];
+            b_hit.score
+                .partial_cmp(&a_hit.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.pinned.cmp(&a.pinned))
+                .then_with(|| b.updated_at.cmp(&a.updated_at))
+        });
+
+        ranked
+            .into_iter()
+            .map(|(conversation, hit)| self.result(conversation, Some(&hit)))
+            .collect()
+    }
+
+    /// Search result for a conversation, with a snippet from the matched document
+    fn result(&self, conversation: usize, hit: Option<&Hit>) -> SearchConversationResult {
+        let conv = &self.conversations[conversation];
+        let snippet = match hit {
+            Some(&Hit { doc, span: (offset, len), .. }) => {
+                let doc = &self.docs[doc];
+                match doc.kind {
+                    DocKind::Title => "Title match".to_string(),
+                    DocKind::Tag => format!("Tag: {}", doc.text),
+                    DocKind::Folder => format!("Folder: {}", doc.text),
+                    DocKind::Message => super::build_snippet(&doc.text, offset, len),
+                }
+            }
+            None => String::new(),
+        };
+
+        SearchConversationResult {
+            id: conv.id.clone(),
+            title: conv.title.clone(),
+            updated_at: conv.updated_at.clone(),
+            snippet,
+            pinned: conv.pinned,
+            tags: conv.tags.clone(),
+            folder: conv.folder.clone(),
+            archived: conv.archived,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(id: &str, title: &str) -> Conversation {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "created_at": "2025-01-01T00:00:00Z",
+            "updated_at": "2025-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn message(conversation_id: &str, content: &str) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "id": format!("{}-message", conversation_id),
+            "conversation_id": conversation_id,
+            "role": "user",
+            "content": content,
+            "provider": "openai",
+            "model": "gpt-4o",
+            "created_at": "2025-01-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn index() -> SearchIndex {
+        SearchIndex::build(
+            &[conversation("a", "Build tooling"), conversation("b", "Weekend plans")],
+            &[
+                message("a", "How do I link a C++ library from JavaScript?"),
+                message("b", "Hiking on Saturday, then a film."),
+            ],
+        )
+    }
+
+    fn ids(results: &[SearchConversationResult]) -> Vec<&str> {
+        results.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn term_search_matches_word_prefixes() {
+        let results = index().search("hik sat");
+
+        assert_eq!(ids(&results), vec!["b"]);
+        assert!(results[0].snippet.contains("Hiking"), "{}", results[0].snippet);
+    }
+
+    #[test]
+    fn falls_back_to_substring_inside_words() {
+        let results = index().search("Script");
+
+        assert_eq!(ids(&results), vec!["a"]);
+        assert!(results[0].snippet.contains("JavaScript"), "{}", results[0].snippet);
+    }
+
+    #[test]
+    fn falls_back_to_substring_for_punctuation() {
+        // Tokenizing leaves no terms at all, so only the substring match finds it
+        assert_eq!(ids(&index().search("++")), vec!["a"]);
+    }
+
+    #[test]
+    fn no_match_returns_nothing() {
+        assert!(index().search("quantum").is_empty());
+        assert!(index().search("   ").is_empty());
+    }
+}
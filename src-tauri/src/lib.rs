@@ -10,26 +10,80 @@ pub fn run() {
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .manage(db::DbState::default())
+        .manage(commands::chat::ActiveStreams::default())
+        .manage(commands::chat::StreamBuffers::default())
+        .manage(commands::speech::ActiveTranscriptions::default())
         .invoke_handler(tauri::generate_handler![
             commands::chat::send_message,
             commands::chat::send_message_stream,
+            commands::chat::estimate_request_tokens,
+            commands::chat::cancel_stream,
+            commands::chat::get_stream_state,
             commands::chat::regenerate_last_assistant,
+            commands::chat::regenerate_with,
+            commands::chat::regenerate_last_assistant_stream,
             commands::chat::compare_response,
+            commands::chat::compare_multi,
+            commands::chat::compare_response_stream,
+            commands::chat::get_models,
+            commands::chat::validate_api_key,
+            commands::chat::get_provider_status,
+            commands::chat::get_conversation,
             commands::chat::get_conversations,
+            commands::chat::get_conversations_page,
             commands::chat::search_conversations,
             commands::chat::get_messages,
+            commands::chat::get_messages_paginated,
             commands::chat::create_conversation,
             commands::chat::delete_conversation,
+            commands::chat::restore_conversation,
+            commands::chat::get_trash,
+            commands::chat::purge_deleted,
+            commands::chat::delete_conversations,
+            commands::chat::add_tag_to_conversations,
+            commands::chat::get_all_tags,
+            commands::chat::rename_tag,
+            commands::chat::delete_tag,
+            commands::chat::move_conversations_to_folder,
+            commands::chat::rename_folder,
+            commands::chat::delete_folder,
             commands::chat::update_conversation_title,
             commands::chat::update_conversation_pinned,
+            commands::chat::update_conversation_archived,
+            commands::chat::update_conversation_system_prompt,
+            commands::chat::update_conversation_locked_model,
             commands::chat::update_conversation_tags,
             commands::chat::update_conversation_folder,
             commands::chat::update_message_content,
+            commands::chat::toggle_message_favorite,
+            commands::chat::get_favorite_messages,
+            commands::chat::delete_message,
             commands::chat::clone_conversation,
+            commands::chat::branch_conversation,
             commands::chat::export_conversation_markdown,
+            commands::chat::export_all_markdown,
+            commands::chat::export_conversation_html,
+            commands::chat::export_conversation_json,
+            commands::chat::export_conversation_with_sources,
             commands::settings::get_api_key,
             commands::settings::set_api_key,
             commands::settings::delete_api_key,
+            commands::settings::get_base_url,
+            commands::settings::set_base_url,
+            commands::settings::get_custom_provider_config,
+            commands::settings::set_custom_provider_config,
+            commands::settings::get_azure_provider_config,
+            commands::settings::set_azure_provider_config,
+            commands::settings::get_store_context_used,
+            commands::settings::set_store_context_used,
+            commands::pricing::get_pricing_table,
+            commands::pricing::set_pricing_table,
+            commands::pricing::estimate_conversation_cost,
+            commands::templates::list_prompt_templates,
+            commands::templates::save_prompt_template,
+            commands::templates::delete_prompt_template,
+            commands::templates::apply_prompt_template,
             commands::settings::get_whisper_config,
             commands::settings::get_default_whisper_model_path,
             commands::settings::get_whisper_model_path,
@@ -37,17 +91,42 @@ pub fn run() {
             commands::settings::set_whisper_model_id,
             commands::settings::ensure_default_whisper_config,
             commands::settings::set_whisper_config,
+            commands::settings::get_whisper_timeout,
+            commands::settings::set_whisper_timeout,
             commands::speech::transcribe_audio,
+            commands::speech::transcribe_audio_stream,
+            commands::speech::transcribe_audio_timestamped,
+            commands::speech::cancel_transcription,
+            commands::speech::detect_language,
+            commands::speech::get_whisper_model_status,
+            commands::speech::list_whisper_models,
             commands::speech::download_whisper_model,
+            commands::speech::delete_whisper_model,
+            commands::speech::verify_whisper_model,
+            commands::speech::get_whisper_models_disk_usage,
             commands::knowledge::create_bucket,
+            commands::knowledge::get_embedding_models,
             commands::knowledge::delete_bucket,
             commands::knowledge::get_buckets,
+            commands::knowledge::get_buckets_by_tag,
+            commands::knowledge::update_bucket,
+            commands::knowledge::update_bucket_tags,
             commands::knowledge::upload_file,
+            commands::knowledge::ingest_url,
             commands::knowledge::delete_file,
+            commands::knowledge::dedupe_bucket,
+            commands::knowledge::reembed_bucket,
             commands::knowledge::get_bucket_files,
+            commands::knowledge::get_bucket_stats,
+            commands::knowledge::export_bucket,
+            commands::knowledge::import_bucket,
+            commands::backup::backup_all,
+            commands::backup::restore_all,
             commands::knowledge::search_bucket,
+            commands::knowledge::search_buckets,
             commands::license::activate_license,
             commands::license::deactivate_license,
+            commands::license::check_license_status,
         ])
         .setup(|app| {
             // Initialize the database
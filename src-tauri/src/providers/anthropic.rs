@@ -2,14 +2,56 @@ use async_trait::async_trait;
 use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
 use tokio::sync::mpsc;
 
-use super::{Message, ModelInfo, Provider, StreamChunk};
+use super::{
+    context_window, count_message_tokens, ensure_vision_support, send_with_retry,
+    stop_sequences, with_json_instruction, with_model_max_tokens, ChatResult, GenerationParams,
+    Message, ModelInfo, Provider, ProviderError, ProviderResult, StreamChunk, Timeouts,
+    TokenUsage, DEFAULT_MAX_TOKENS, MAX_RETRIES,
+};
+use super::sse::SseDecoder;
+
+/// Anthropic documents no cap on `stop_sequences`
+const MAX_STOP_SEQUENCES: usize = usize::MAX;
+
+/// Roughly the 1024-token minimum Anthropic will cache; smaller user messages aren't marked
+const PROMPT_CACHE_MIN_CHARS: usize = 4096;
+
+/// Output token limits as (model id prefix, tokens); the models endpoint doesn't report them.
+/// The longest matching prefix wins, and `claude-3-*` and unknown models get `DEFAULT_MAX_TOKENS`.
+const OUTPUT_LIMITS: &[(&str, u32)] = &[
+    ("claude-3-5-sonnet", 8192),
+    ("claude-3-5-haiku", 8192),
+    ("claude-3-7-sonnet", 64_000),
+    ("claude-sonnet-4", 64_000),
+    ("claude-haiku-4", 64_000),
+    ("claude-opus-4", 32_000),
+];
+
+/// Smallest thinking budget Anthropic accepts
+const MIN_THINKING_BUDGET: u32 = 1024;
+/// Room left for the visible reply on top of the thinking budget
+const THINKING_REPLY_TOKENS: u32 = 4096;
+
+/// Output token limit of a model in `OUTPUT_LIMITS`, if it is listed
+fn known_output_limit(model: &str) -> Option<u32> {
+    OUTPUT_LIMITS
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, tokens)| *tokens)
+}
+
+/// Output token limit of a model, from `OUTPUT_LIMITS`
+fn output_limit(model: &str) -> u32 {
+    known_output_limit(model).unwrap_or(DEFAULT_MAX_TOKENS)
+}
 
 pub struct AnthropicProvider {
     api_key: String,
     client: Client,
+    timeouts: Timeouts,
 }
 
 #[derive(Serialize)]
@@ -18,24 +60,93 @@ struct AnthropicRequest {
     max_tokens: u32,
     messages: Vec<AnthropicMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    system: Option<String>,
+    system: Option<AnthropicContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ThinkingConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
 }
 
+#[derive(Serialize)]
+struct ThinkingConfig {
+    #[serde(rename = "type")]
+    thinking_type: String,
+    budget_tokens: u32,
+}
+
 #[derive(Serialize)]
 struct AnthropicMessage {
     role: String,
-    content: String,
+    content: AnthropicContent,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AnthropicContent {
+    Text(String),
+    Blocks(Vec<AnthropicContentBlock>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicContentBlock {
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image { source: ImageSource },
+}
+
+#[derive(Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    cache_type: String,
+}
+
+impl CacheControl {
+    fn ephemeral() -> Self {
+        Self { cache_type: "ephemeral".to_string() }
+    }
+}
+
+#[derive(Serialize)]
+struct ImageSource {
+    #[serde(rename = "type")]
+    source_type: String,
+    media_type: String,
+    data: String,
 }
 
 #[derive(Deserialize)]
 struct AnthropicResponse {
     content: Vec<ContentBlock>,
+    usage: Option<AnthropicUsage>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AnthropicUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
 }
 
 #[derive(Deserialize)]
 struct ContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    #[serde(default)]
     text: String,
 }
 
@@ -49,17 +160,30 @@ struct StreamEvent {
 #[derive(Deserialize)]
 struct Delta {
     text: Option<String>,
+    thinking: Option<String>,
 }
 
 impl AnthropicProvider {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(api_key: String, timeouts: Timeouts) -> Self {
         Self {
             api_key,
-            client: Client::new(),
+            client: timeouts.client(),
+            timeouts,
         }
     }
 
-    fn prepare_messages(&self, messages: Vec<Message>) -> (Option<String>, Vec<AnthropicMessage>) {
+    /// Split out the system prompt and convert messages to Anthropic's format.
+    /// With `prompt_cache`, the system prompt and a large first user message get cache breakpoints.
+    fn prepare_messages(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        prompt_cache: bool,
+    ) -> ProviderResult<(Option<AnthropicContent>, Vec<AnthropicMessage>)> {
+        // Claude 2 and Instant predate image input
+        let supports_vision = !(model.starts_with("claude-2") || model.starts_with("claude-instant"));
+        ensure_vision_support(&messages, model, supports_vision)?;
+
         let mut system_message: Option<String> = None;
         let mut chat_messages: Vec<AnthropicMessage> = Vec::new();
         
@@ -67,127 +191,429 @@ impl AnthropicProvider {
             if msg.role == "system" {
                 system_message = Some(msg.content);
             } else {
+                let cache_this = prompt_cache
+                    && msg.role == "user"
+                    && !chat_messages.iter().any(|m| m.role == "user")
+                    && msg.content.len() >= PROMPT_CACHE_MIN_CHARS;
+
+                let content = if msg.images.is_empty() && !cache_this {
+                    AnthropicContent::Text(msg.content)
+                } else {
+                    // Images go before the text, as recommended by Anthropic
+                    let mut blocks: Vec<AnthropicContentBlock> = msg.images
+                        .into_iter()
+                        .map(|image| AnthropicContentBlock::Image {
+                            source: ImageSource {
+                                source_type: "base64".to_string(),
+                                media_type: image.mime_type,
+                                data: image.data,
+                            },
+                        })
+                        .collect();
+                    blocks.push(AnthropicContentBlock::Text {
+                        text: msg.content,
+                        cache_control: cache_this.then(CacheControl::ephemeral),
+                    });
+                    AnthropicContent::Blocks(blocks)
+                };
+
                 chat_messages.push(AnthropicMessage {
                     role: msg.role,
-                    content: msg.content,
+                    content,
                 });
             }
         }
         
-        (system_message, chat_messages)
+        // Caching needs the block form of `system`
+        let system = system_message.map(|text| {
+            if prompt_cache {
+                AnthropicContent::Blocks(vec![AnthropicContentBlock::Text {
+                    text,
+                    cache_control: Some(CacheControl::ephemeral()),
+                }])
+            } else {
+                AnthropicContent::Text(text)
+            }
+        });
+
+        Ok((system, chat_messages))
     }
-}
 
-#[async_trait]
-impl Provider for AnthropicProvider {
-    async fn chat(&self, messages: Vec<Message>, model: &str) -> Result<String> {
-        let (system_message, chat_messages) = self.prepare_messages(messages);
+    /// max_tokens, temperature, top_p and thinking config for a request. Extended thinking
+    /// needs max_tokens above the budget and doesn't allow custom temperature or top_p.
+    /// Budgets Anthropic would reject are refused here with a clear message instead.
+    fn sampling(
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<(u32, Option<f32>, Option<f32>, Option<ThinkingConfig>)> {
+        let max_tokens = params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
+        let Some(budget_tokens) = params.thinking else {
+            return Ok((max_tokens, params.temperature, params.top_p, None));
+        };
+
+        if budget_tokens < MIN_THINKING_BUDGET {
+            return Err(ProviderError::InvalidRequest(format!(
+                "The thinking budget must be at least {} tokens, got {}",
+                MIN_THINKING_BUDGET, budget_tokens
+            )));
+        }
+
+        let max_tokens = max_tokens.max(budget_tokens.saturating_add(THINKING_REPLY_TOKENS));
+        // Unlisted models may be newer than the table, so leave their limit to the API
+        if let Some(limit) = known_output_limit(model).filter(|limit| max_tokens > *limit) {
+            return Err(ProviderError::InvalidRequest(format!(
+                "A thinking budget of {} tokens needs {} output tokens, more than the {} {} allows",
+                budget_tokens, max_tokens, limit, model
+            )));
+        }
+
+        if params.temperature.is_some() || params.top_p.is_some() {
+            println!("[ANTHROPIC] Ignoring temperature/top_p, which extended thinking doesn't support");
+        }
+
+        let thinking = ThinkingConfig {
+            thinking_type: "enabled".to_string(),
+            budget_tokens,
+        };
+        Ok((max_tokens, None, None, Some(thinking)))
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> ProviderResult<AnthropicRequest> {
+        let messages = with_json_instruction(messages, params);
+        let (system_message, chat_messages) =
+            self.prepare_messages(messages, model, params.enable_prompt_cache)?;
+        let (max_tokens, temperature, top_p, thinking) = Self::sampling(model, params)?;
 
-        let request = AnthropicRequest {
+        Ok(AnthropicRequest {
             model: model.to_string(),
-            max_tokens: 4096,
+            max_tokens,
             messages: chat_messages,
             system: system_message,
-            stream: None,
-        };
+            temperature,
+            top_p,
+            stop_sequences: stop_sequences(params, "anthropic", MAX_STOP_SEQUENCES),
+            thinking,
+            stream: stream.then_some(true),
+        })
+    }
+
+    async fn fetch_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        let request_builder = self.client
+            .get("https://api.anthropic.com/v1/models")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .timeout(self.timeouts.request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response("Anthropic", response).await);
+        }
+
+        let result: ModelsResponse = response.json().await?;
+
+        Ok(result.data
+            .into_iter()
+            .map(|m| ModelInfo {
+                name: m.display_name.unwrap_or_else(|| m.id.clone()),
+                max_tokens: output_limit(&m.id),
+                id: m.id,
+                provider: "anthropic".to_string(),
+            })
+            .collect())
+    }
+
+    fn fallback_models() -> Vec<ModelInfo> {
+        [
+            ("claude-sonnet-4-20250514", "Claude Sonnet 4"),
+            ("claude-opus-4-20250514", "Claude Opus 4"),
+            ("claude-3-5-haiku-20241022", "Claude 3.5 Haiku"),
+        ]
+        .into_iter()
+        .map(|(id, name)| ModelInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            provider: "anthropic".to_string(),
+            max_tokens: output_limit(id),
+        })
+        .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
 
-        let response = self.client
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    display_name: Option<String>,
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    async fn chat(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<ChatResult> {
+        let params = &with_model_max_tokens(self, "anthropic", model, params, false).await;
+        let request = self.build_request(messages, model, params, false)?;
+
+        let request_builder = self.client
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
             .json(&request)
-            .send()
-            .await?;
+            .timeout(self.timeouts.request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Anthropic API error: {}", error_text));
+            return Err(ProviderError::from_response("Anthropic", response).await);
         }
 
         let result: AnthropicResponse = response.json().await?;
-        
-        Ok(result.content
-            .first()
-            .map(|c| c.text.clone())
-            .unwrap_or_default())
+
+        // Thinking blocks are the reasoning trace; only text blocks make up the answer
+        let content = result.content
+            .iter()
+            .filter(|c| c.block_type == "text")
+            .map(|c| c.text.as_str())
+            .collect::<String>();
+        if content.trim().is_empty() {
+            let reason = result.stop_reason.map(|r| format!("stop reason: {}", r));
+            return Err(ProviderError::empty_response("Anthropic", reason.as_deref()));
+        }
+        let usage = result.usage.map(|u| TokenUsage {
+            prompt_tokens: u.input_tokens,
+            completion_tokens: u.output_tokens,
+            total_tokens: u.input_tokens + u.output_tokens,
+            cache_read_tokens: u.cache_read_input_tokens,
+            cache_write_tokens: u.cache_creation_input_tokens,
+        });
+
+        Ok(ChatResult { content, usage })
     }
 
     async fn chat_stream(
         &self,
         messages: Vec<Message>,
         model: &str,
+        params: &GenerationParams,
         tx: mpsc::Sender<StreamChunk>,
-    ) -> Result<()> {
-        let (system_message, chat_messages) = self.prepare_messages(messages);
-
-        let request = AnthropicRequest {
-            model: model.to_string(),
-            max_tokens: 4096,
-            messages: chat_messages,
-            system: system_message,
-            stream: Some(true),
-        };
+    ) -> ProviderResult<()> {
+        let params = &with_model_max_tokens(self, "anthropic", model, params, true).await;
+        let request = self.build_request(messages, model, params, true)?;
 
-        let response = self.client
+        let request_builder = self.client
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+            .json(&request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Anthropic API error: {}", error_text));
+            return Err(ProviderError::from_response("Anthropic", response).await);
         }
 
         let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
+        let mut decoder = SseDecoder::new();
 
         while let Some(chunk_result) = stream.next().await {
             let chunk = chunk_result?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            // Process complete SSE events
-            while let Some(pos) = buffer.find("\n\n") {
-                let event_str = buffer[..pos].to_string();
-                buffer = buffer[pos + 2..].to_string();
-
-                for line in event_str.lines() {
-                    if let Some(data) = line.strip_prefix("data: ") {
-                        if data == "[DONE]" {
-                            let _ = tx.send(StreamChunk { delta: String::new(), done: true }).await;
-                            return Ok(());
-                        }
-
-                        if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
-                            if event.event_type == "content_block_delta" {
-                                if let Some(delta) = event.delta {
-                                    if let Some(text) = delta.text {
-                                        let _ = tx.send(StreamChunk { delta: text, done: false }).await;
-                                    }
-                                }
-                            }
-                        }
-                    }
+
+            for data in decoder.push(&chunk) {
+                if send_stream_event(&data, &tx).await {
+                    return Ok(());
                 }
             }
         }
+        if let Some(data) = decoder.finish() {
+            if send_stream_event(&data, &tx).await {
+                return Ok(());
+            }
+        }
 
-        let _ = tx.send(StreamChunk { delta: String::new(), done: true }).await;
+        let _ = tx.send(StreamChunk { delta: String::new(), thinking: false, done: true }).await;
         Ok(())
     }
 
-    fn list_models(&self) -> Vec<ModelInfo> {
-        vec![
-            ModelInfo {
-                id: "claude-sonnet-4-20250514".to_string(),
-                name: "Claude Sonnet 4".to_string(),
-                provider: "anthropic".to_string(),
-                max_tokens: 8192,
-            },
-        ]
+    async fn list_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        match self.fetch_models().await {
+            Ok(models) if !models.is_empty() => Ok(models),
+            Ok(_) => Ok(Self::fallback_models()),
+            Err(e) => {
+                eprintln!("[ANTHROPIC] Failed to fetch models, using fallback list: {}", e);
+                Ok(Self::fallback_models())
+            }
+        }
+    }
+
+    async fn validate(&self) -> ProviderResult<()> {
+        let request_builder = self.client
+            .get("https://api.anthropic.com/v1/models?limit=1")
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .timeout(self.timeouts.request);
+
+        // A single attempt: retries would only delay telling the user their key is wrong
+        let response = send_with_retry(request_builder, 0).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response("Anthropic", response).await);
+        }
+
+        Ok(())
+    }
+
+    fn count_tokens(&self, model: &str, messages: &[Message]) -> usize {
+        count_message_tokens("anthropic", model, messages).0
+    }
+
+    fn context_window(&self, model: &str) -> usize {
+        context_window("anthropic", model)
+    }
+}
+
+/// Forward the text or thinking of one streamed event. Returns true once the `[DONE]`
+/// marker has been passed on as the final chunk.
+async fn send_stream_event(data: &str, tx: &mpsc::Sender<StreamChunk>) -> bool {
+    if data == "[DONE]" {
+        let _ = tx.send(StreamChunk { delta: String::new(), thinking: false, done: true }).await;
+        return true;
+    }
+
+    if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
+        if event.event_type == "content_block_delta" {
+            if let Some(delta) = event.delta {
+                if let Some(text) = delta.text {
+                    let _ = tx.send(StreamChunk { delta: text, thinking: false, done: false }).await;
+                } else if let Some(thinking) = delta.thinking {
+                    let _ = tx.send(StreamChunk { delta: thinking, thinking: true, done: false }).await;
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{ResponseFormat, JSON_ONLY_INSTRUCTION};
+    use super::*;
+
+    fn request_json(messages: Vec<Message>, params: &GenerationParams) -> serde_json::Value {
+        let provider = AnthropicProvider::new("key".to_string(), Timeouts::default());
+        let request = provider.build_request(messages, "claude-sonnet-4-20250514", params, false).unwrap();
+        serde_json::to_value(&request).unwrap()
+    }
+
+    fn user(content: &str) -> Message {
+        Message { role: "user".to_string(), content: content.to_string(), images: Vec::new() }
+    }
+
+    #[test]
+    fn output_limit_matches_model_family() {
+        assert_eq!(output_limit("claude-3-opus-20240229"), 4096);
+        assert_eq!(output_limit("claude-3-haiku-20240307"), 4096);
+        assert_eq!(output_limit("claude-3-5-haiku-20241022"), 8192);
+        assert_eq!(output_limit("claude-opus-4-20250514"), 32_000);
+        assert_eq!(output_limit("claude-sonnet-4-20250514"), 64_000);
+        assert_eq!(output_limit("claude-next"), DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn thinking_reserves_room_for_the_reply() {
+        let params = GenerationParams { thinking: Some(2048), temperature: Some(0.5), ..Default::default() };
+        let (max_tokens, temperature, _, thinking) =
+            AnthropicProvider::sampling("claude-sonnet-4-20250514", &params).unwrap();
+
+        assert_eq!(max_tokens, 2048 + THINKING_REPLY_TOKENS);
+        assert_eq!(temperature, None);
+        assert_eq!(thinking.map(|t| t.budget_tokens), Some(2048));
+    }
+
+    #[test]
+    fn thinking_budget_below_minimum_is_refused() {
+        let params = GenerationParams { thinking: Some(512), ..Default::default() };
+        let error = AnthropicProvider::sampling("claude-sonnet-4-20250514", &params).unwrap_err();
+
+        assert!(matches!(error, ProviderError::InvalidRequest(_)), "{}", error);
+    }
+
+    #[test]
+    fn thinking_budget_beyond_output_limit_is_refused() {
+        for budget in [60_000, u32::MAX] {
+            let params = GenerationParams { thinking: Some(budget), ..Default::default() };
+            let error = AnthropicProvider::sampling("claude-sonnet-4-20250514", &params).unwrap_err();
+
+            assert!(matches!(error, ProviderError::InvalidRequest(_)), "{}", error);
+        }
+    }
+
+    #[test]
+    fn json_format_adds_system_instruction() {
+        let params = GenerationParams { response_format: ResponseFormat::Json, ..Default::default() };
+        let request = request_json(vec![user("List three colors.")], &params);
+
+        assert_eq!(request["system"], JSON_ONLY_INSTRUCTION);
+    }
+
+    #[test]
+    fn json_format_extends_existing_system_prompt() {
+        let params = GenerationParams { response_format: ResponseFormat::Json, ..Default::default() };
+        let system = Message { role: "system".to_string(), content: "Be brief.".to_string(), images: Vec::new() };
+        let request = request_json(vec![system, user("List three colors.")], &params);
+
+        assert_eq!(request["system"], format!("Be brief.\n\n{}", JSON_ONLY_INSTRUCTION));
+        assert_eq!(request["messages"].as_array().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn text_format_sends_no_system_prompt() {
+        let request = request_json(vec![user("List three colors.")], &GenerationParams::default());
+
+        assert!(request.get("system").is_none());
+    }
+
+    #[tokio::test]
+    async fn stream_events_split_mid_character_arrive_whole() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut decoder = SseDecoder::new();
+        let body = concat!(
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"café\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"thinking\":\"hm\"}}",
+        )
+        .as_bytes();
+        // Split inside the two bytes of "é"
+        let split = body.iter().position(|&b| b == 0xC3).unwrap() + 1;
+
+        let mut events = decoder.push(&body[..split]);
+        events.extend(decoder.push(&body[split..]));
+        events.extend(decoder.finish());
+        for data in &events {
+            assert!(!send_stream_event(data, &tx).await);
+        }
+        drop(tx);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            chunks.push((chunk.delta, chunk.thinking));
+        }
+        assert_eq!(chunks, [("café".to_string(), false), ("hm".to_string(), true)]);
     }
 }
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use super::custom::validate_base_url;
+use super::openai_compatible::{CompatibleConfig, JsonMode, OpenAICompatibleProvider};
+use super::{ModelInfo, Timeouts, DEFAULT_MAX_TOKENS, STORE_PATH};
+
+const CONFIG_KEY: &str = "azure_provider";
+
+/// Latest generally available data-plane API version
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+/// Azure OpenAI accepts at most four stop sequences per request
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// An Azure OpenAI resource, whose models are reached through named deployments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AzureProviderConfig {
+    /// Resource endpoint, e.g. `https://my-resource.openai.azure.com`
+    pub endpoint: String,
+    /// Deployment names, offered as the provider's models
+    pub deployments: Vec<String>,
+    /// `api-version` sent with every request; a recent GA version when unset
+    #[serde(default)]
+    pub api_version: Option<String>,
+}
+
+pub fn read_config(app: &AppHandle) -> Result<Option<AzureProviderConfig>> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| anyhow::anyhow!("Failed to open store: {}", e))?;
+
+    match store.get(CONFIG_KEY) {
+        Some(value) => Ok(Some(serde_json::from_value(value)?)),
+        None => Ok(None),
+    }
+}
+
+/// Save the settings, or clear them with `None`. The endpoint is checked and blank
+/// fields are dropped first. Returns what was saved.
+pub fn save_config(app: &AppHandle, config: Option<AzureProviderConfig>) -> Result<Option<AzureProviderConfig>> {
+    let config = match config {
+        Some(config) => Some(normalize(config)?),
+        None => None,
+    };
+
+    let store = app.store(STORE_PATH)
+        .map_err(|e| anyhow::anyhow!("Failed to open store: {}", e))?;
+    match &config {
+        Some(config) => store.set(CONFIG_KEY, serde_json::to_value(config)?),
+        None => {
+            store.delete(CONFIG_KEY);
+        }
+    }
+    store.save()
+        .map_err(|e| anyhow::anyhow!("Failed to save store: {}", e))?;
+
+    Ok(config)
+}
+
+fn normalize(config: AzureProviderConfig) -> Result<AzureProviderConfig> {
+    let mut deployments: Vec<String> = Vec::new();
+    for deployment in config.deployments {
+        let deployment = deployment.trim().to_string();
+        if !deployment.is_empty() && !deployments.contains(&deployment) {
+            deployments.push(deployment);
+        }
+    }
+    if deployments.is_empty() {
+        return Err(anyhow::anyhow!("At least one deployment name is required"));
+    }
+
+    Ok(AzureProviderConfig {
+        endpoint: validate_base_url(&config.endpoint)?,
+        deployments,
+        api_version: config.api_version
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()),
+    })
+}
+
+/// Chat completions go to `{endpoint}/openai/deployments/{deployment}/chat/completions`,
+/// authenticated with an `api-key` header instead of a bearer token
+pub fn provider(config: AzureProviderConfig, api_key: &str, timeouts: Timeouts) -> Result<OpenAICompatibleProvider> {
+    let endpoint = validate_base_url(&config.endpoint)?;
+    let api_version = config.api_version.unwrap_or_else(|| DEFAULT_API_VERSION.to_string());
+
+    let models = config.deployments
+        .into_iter()
+        .map(|deployment| ModelInfo {
+            name: deployment.clone(),
+            id: deployment,
+            provider: "azure".to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+        })
+        .collect();
+
+    let provider = OpenAICompatibleProvider::new(
+        CompatibleConfig {
+            name: "azure",
+            display_name: "Azure OpenAI",
+            base_url: format!("{}/openai", endpoint),
+            extra_headers: &[],
+            max_stop_sequences: MAX_STOP_SEQUENCES,
+            json_mode: JsonMode::ResponseFormat,
+            // Deployment names don't say which model they serve, so let Azure decide
+            supports_vision: |_| true,
+            is_chat_model: |_| true,
+            chat_path: "/deployments/{model}/chat/completions",
+            validate_path: "/models",
+            fallback_models: &[],
+        },
+        String::new(),
+        timeouts,
+    );
+
+    Ok(provider
+        .with_auth_header(Some(("api-key".to_string(), api_key.trim().to_string())))
+        .with_query(vec![("api-version".to_string(), api_version)])
+        .with_models(models))
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use super::openai_compatible::{CompatibleConfig, JsonMode, OpenAICompatibleProvider};
+use super::{ModelInfo, Timeouts, DEFAULT_MAX_TOKENS, STORE_PATH};
+
+const CONFIG_KEY: &str = "custom_provider";
+
+/// Compatible servers differ here, so keep to OpenAI's own limit
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// A model offered by the custom endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomModel {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+/// A user-supplied OpenAI-compatible endpoint, such as a self-hosted vLLM server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomProviderConfig {
+    pub base_url: String,
+    /// Header carrying the credentials; `Authorization` when unset
+    #[serde(default)]
+    pub auth_header_name: Option<String>,
+    /// Sent verbatim; when unset, the stored API key is sent as a bearer token
+    #[serde(default)]
+    pub auth_header_value: Option<String>,
+    /// Models offered for the endpoint; when empty its models list is queried
+    #[serde(default)]
+    pub models: Vec<CustomModel>,
+}
+
+/// Check that `url` is an absolute http(s) URL, returning it without a trailing slash
+pub fn validate_base_url(url: &str) -> Result<String> {
+    let url = url.trim().trim_end_matches('/');
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| anyhow::anyhow!("Invalid base URL: {}", e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(anyhow::anyhow!("Unsupported URL scheme: {}", parsed.scheme()));
+    }
+    if parsed.host_str().is_none() {
+        return Err(anyhow::anyhow!("Base URL has no host"));
+    }
+    Ok(url.to_string())
+}
+
+pub fn read_config(app: &AppHandle) -> Result<Option<CustomProviderConfig>> {
+    let store = app.store(STORE_PATH)
+        .map_err(|e| anyhow::anyhow!("Failed to open store: {}", e))?;
+
+    match store.get(CONFIG_KEY) {
+        Some(value) => Ok(Some(serde_json::from_value(value)?)),
+        None => Ok(None),
+    }
+}
+
+/// Save the settings, or clear them with `None`. The base URL is checked and blank
+/// optional fields are dropped first. Returns what was saved.
+pub fn save_config(app: &AppHandle, config: Option<CustomProviderConfig>) -> Result<Option<CustomProviderConfig>> {
+    let config = match config {
+        Some(config) => Some(normalize(config)?),
+        None => None,
+    };
+
+    let store = app.store(STORE_PATH)
+        .map_err(|e| anyhow::anyhow!("Failed to open store: {}", e))?;
+    match &config {
+        Some(config) => store.set(CONFIG_KEY, serde_json::to_value(config)?),
+        None => {
+            store.delete(CONFIG_KEY);
+        }
+    }
+    store.save()
+        .map_err(|e| anyhow::anyhow!("Failed to save store: {}", e))?;
+
+    Ok(config)
+}
+
+fn normalize(config: CustomProviderConfig) -> Result<CustomProviderConfig> {
+    let non_blank = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+    Ok(CustomProviderConfig {
+        base_url: validate_base_url(&config.base_url)?,
+        auth_header_name: non_blank(config.auth_header_name),
+        auth_header_value: non_blank(config.auth_header_value),
+        models: config.models
+            .into_iter()
+            .map(|m| CustomModel {
+                id: m.id.trim().to_string(),
+                name: non_blank(m.name),
+                max_tokens: m.max_tokens,
+            })
+            .filter(|m| !m.id.is_empty())
+            .collect(),
+    })
+}
+
+pub fn provider(config: CustomProviderConfig, api_key: &str, timeouts: Timeouts) -> Result<OpenAICompatibleProvider> {
+    let base_url = validate_base_url(&config.base_url)?;
+
+    let api_key = api_key.trim();
+    let auth_value = config.auth_header_value
+        .or_else(|| (!api_key.is_empty()).then(|| format!("Bearer {}", api_key)));
+    let auth_header = auth_value.map(|value| {
+        (config.auth_header_name.unwrap_or_else(|| "Authorization".to_string()), value)
+    });
+
+    let models = config.models
+        .into_iter()
+        .map(|m| ModelInfo {
+            name: m.name.unwrap_or_else(|| m.id.clone()),
+            max_tokens: m.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            id: m.id,
+            provider: "custom".to_string(),
+        })
+        .collect();
+
+    let provider = OpenAICompatibleProvider::new(
+        CompatibleConfig {
+            name: "custom",
+            display_name: "Custom provider",
+            base_url,
+            extra_headers: &[],
+            max_stop_sequences: MAX_STOP_SEQUENCES,
+            json_mode: JsonMode::ResponseFormat,
+            // Whatever the server hosts decides what it accepts
+            supports_vision: |_| true,
+            is_chat_model: |_| true,
+            chat_path: "/chat/completions",
+            validate_path: "/models",
+            fallback_models: &[],
+        },
+        String::new(),
+        timeouts,
+    );
+
+    Ok(provider.with_auth_header(auth_header).with_models(models))
+}
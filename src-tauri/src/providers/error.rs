@@ -0,0 +1,107 @@
+use reqwest::{Response, StatusCode};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+/// Failure from a provider call, classified so the UI can react to the cause
+#[derive(Debug, Clone, Error)]
+pub enum ProviderError {
+    #[error("Authentication failed. Please check your API key.")]
+    AuthFailed,
+    #[error("Rate limited by the provider{}", retry_after.map(|s| format!(", retry after {}s", s)).unwrap_or_default())]
+    RateLimited { retry_after: Option<u64> },
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("{0}")]
+    EmptyResponse(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type ProviderResult<T> = std::result::Result<T, ProviderError>;
+
+impl ProviderError {
+    /// Stable identifier the frontend can match on
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProviderError::AuthFailed => "auth_failed",
+            ProviderError::RateLimited { .. } => "rate_limited",
+            ProviderError::InvalidRequest(_) => "invalid_request",
+            ProviderError::Network(_) => "network",
+            ProviderError::EmptyResponse(_) => "empty_response",
+            ProviderError::Other(_) => "other",
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            ProviderError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// A response without any answer text, with whatever reason the API gave for it
+    pub fn empty_response(provider: &str, reason: Option<&str>) -> Self {
+        match reason {
+            Some(reason) => ProviderError::EmptyResponse(format!("{} returned an empty response ({})", provider, reason)),
+            None => ProviderError::EmptyResponse(format!("{} returned an empty response", provider)),
+        }
+    }
+
+    /// Classify a non-success HTTP response, consuming its body for the message
+    pub async fn from_response(provider: &str, response: Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        let body = response.text().await.unwrap_or_default();
+        eprintln!("[{}] API error ({}): {}", provider.to_uppercase(), status, body);
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ProviderError::AuthFailed,
+            // Gemini reports a bad key as a 400 rather than a 401
+            StatusCode::BAD_REQUEST if body.contains("API_KEY_INVALID") => ProviderError::AuthFailed,
+            StatusCode::TOO_MANY_REQUESTS => ProviderError::RateLimited { retry_after },
+            StatusCode::BAD_REQUEST
+            | StatusCode::NOT_FOUND
+            | StatusCode::PAYLOAD_TOO_LARGE
+            | StatusCode::UNPROCESSABLE_ENTITY => {
+                ProviderError::InvalidRequest(format!("{} API error: {}", provider, body))
+            }
+            _ => ProviderError::Other(format!("{} API error ({}): {}", provider, status, body)),
+        }
+    }
+}
+
+/// Serialized as `{ kind, message, retry_after }` for the frontend
+impl Serialize for ProviderError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ProviderError", 3)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("retry_after", &self.retry_after())?;
+        state.end()
+    }
+}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ProviderError::Network(format!("Request timed out: {}", e))
+        } else if e.is_decode() {
+            ProviderError::Other(format!("Invalid response from provider: {}", e))
+        } else {
+            ProviderError::Network(e.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProviderError {
+    fn from(e: serde_json::Error) -> Self {
+        ProviderError::Other(format!("Invalid response from provider: {}", e))
+    }
+}
@@ -2,14 +2,23 @@ use async_trait::async_trait;
 use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
 use tokio::sync::mpsc;
 
-use super::{Message, ModelInfo, Provider, StreamChunk};
+use super::{
+    context_window, count_message_tokens, ensure_vision_support, send_with_retry,
+    stop_sequences, with_model_max_tokens, ChatResult, GenerationParams, Message, ModelInfo,
+    ResponseFormat, Provider, ProviderError, ProviderResult, StreamChunk, Timeouts, TokenUsage,
+    MAX_RETRIES,
+};
+use super::sse::SseDecoder;
+
+/// Gemini accepts at most five stop sequences per request
+const MAX_STOP_SEQUENCES: usize = 5;
 
 pub struct GeminiProvider {
     api_key: String,
     client: Client,
+    timeouts: Timeouts,
 }
 
 #[derive(Serialize, Clone)]
@@ -17,6 +26,23 @@ struct GeminiRequest {
     contents: Vec<GeminiContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     system_instruction: Option<GeminiContent>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GeminiGenerationConfig>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -27,39 +53,186 @@ struct GeminiContent {
 }
 
 #[derive(Serialize, Clone)]
-struct GeminiPart {
-    text: String,
+#[serde(untagged)]
+enum GeminiPart {
+    Text { text: String },
+    InlineData { inline_data: InlineData },
+}
+
+#[derive(Serialize, Clone)]
+struct InlineData {
+    mime_type: String,
+    data: String,
 }
 
 #[derive(Deserialize)]
 struct GeminiResponse {
+    #[serde(default)]
     candidates: Vec<Candidate>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
+    #[serde(rename = "promptFeedback")]
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+/// Set when the prompt itself was rejected, in which case there are no candidates
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+    block_reason_message: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
+    #[serde(default)]
+    total_token_count: u32,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct Candidate {
+    // Absent when a candidate is blocked, e.g. by safety filters
+    #[serde(default)]
     content: CandidateContent,
+    finish_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
 #[derive(Deserialize)]
+struct SafetyRating {
+    category: String,
+    #[serde(default)]
+    blocked: bool,
+}
+
+#[derive(Deserialize, Default)]
 struct CandidateContent {
+    #[serde(default)]
     parts: Vec<ResponsePart>,
 }
 
 #[derive(Deserialize)]
 struct ResponsePart {
+    #[serde(default)]
     text: String,
 }
 
+impl GeminiResponse {
+    fn text(&self) -> String {
+        self.candidates
+            .first()
+            .map(|c| c.content.parts.iter().map(|p| p.text.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Why the response carries no text, as far as Gemini said: a blocked prompt, or
+    /// the candidate's finish reason along with any safety categories that blocked it
+    fn empty_reason(&self) -> Option<String> {
+        if let Some(feedback) = &self.prompt_feedback {
+            if let Some(reason) = &feedback.block_reason {
+                return Some(match &feedback.block_reason_message {
+                    Some(message) => format!("prompt blocked: {}, {}", reason, message),
+                    None => format!("prompt blocked: {}", reason),
+                });
+            }
+        }
+
+        let candidate = self.candidates.first()?;
+        let finish_reason = candidate.finish_reason.as_deref()?;
+        let blocked: Vec<&str> = candidate.safety_ratings
+            .iter()
+            .filter(|r| r.blocked)
+            .map(|r| r.category.as_str())
+            .collect();
+        if blocked.is_empty() {
+            Some(format!("finish reason: {}", finish_reason))
+        } else {
+            Some(format!("finish reason: {}, blocked categories: {}", finish_reason, blocked.join(", ")))
+        }
+    }
+}
+
+/// What a stream has produced so far, to explain a stream that ends without text
+#[derive(Default)]
+struct StreamStatus {
+    received_text: bool,
+    empty_reason: Option<String>,
+}
+
+/// Pull complete top-level JSON objects out of a JSON array (`[{...},\n{...}]`) stream
+/// body, leaving any incomplete tail. Brackets and commas between objects are skipped.
+/// Works on raw bytes, since JSON's structural characters never occur inside a multi-byte
+/// UTF-8 sequence, and decodes each object only once it is complete.
+fn extract_json_objects(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut objects = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut object_start: Option<usize> = None;
+
+    for (i, &b) in buffer.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match b {
+            b'"' if depth > 0 => in_string = true,
+            b'{' => {
+                if depth == 0 {
+                    object_start = Some(i);
+                }
+                depth += 1;
+            }
+            b'}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some(start) = object_start.take() {
+                        objects.push(String::from_utf8_lossy(&buffer[start..=i]).into_owned());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Keep an unfinished object for the next chunk; drop separators we've moved past
+    let keep_from = object_start.unwrap_or(buffer.len());
+    buffer.drain(..keep_from);
+    objects
+}
+
 impl GeminiProvider {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(api_key: String, timeouts: Timeouts) -> Self {
         Self {
             api_key,
-            client: Client::new(),
+            client: timeouts.client(),
+            timeouts,
         }
     }
 
-    fn prepare_messages(&self, messages: Vec<Message>) -> (Option<GeminiContent>, Vec<GeminiContent>) {
+    fn prepare_messages(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+    ) -> ProviderResult<(Option<GeminiContent>, Vec<GeminiContent>)> {
+        // The original gemini-pro / 1.0 text models don't accept images
+        let supports_vision = !(model == "gemini-pro" || model.starts_with("gemini-1.0-pro"));
+        ensure_vision_support(&messages, model, supports_vision)?;
+
         let mut system_instruction: Option<GeminiContent> = None;
         let mut contents: Vec<GeminiContent> = Vec::new();
         
@@ -67,18 +240,92 @@ impl GeminiProvider {
             if msg.role == "system" {
                 system_instruction = Some(GeminiContent {
                     role: None,
-                    parts: vec![GeminiPart { text: msg.content }],
+                    parts: vec![GeminiPart::Text { text: msg.content }],
                 });
             } else {
                 let role = if msg.role == "assistant" { "model" } else { "user" };
+                let mut parts = vec![GeminiPart::Text { text: msg.content }];
+                parts.extend(msg.images.into_iter().map(|image| GeminiPart::InlineData {
+                    inline_data: InlineData {
+                        mime_type: image.mime_type,
+                        data: image.data,
+                    },
+                }));
                 contents.push(GeminiContent {
                     role: Some(role.to_string()),
-                    parts: vec![GeminiPart { text: msg.content }],
+                    parts,
                 });
             }
         }
 
-        (system_instruction, contents)
+        Ok((system_instruction, contents))
+    }
+
+    /// Forward the text of one streamed response object, logging anything unparseable
+    async fn send_stream_object(&self, object: &str, tx: &mpsc::Sender<StreamChunk>, status: &mut StreamStatus) {
+        let response = match serde_json::from_str::<GeminiResponse>(object) {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("[GEMINI] Failed to parse stream chunk: {} ({})", e, object);
+                return;
+            }
+        };
+
+        if let Some(reason) = response.empty_reason() {
+            status.empty_reason = Some(reason);
+        }
+
+        let Some(candidate) = response.candidates.first() else { return };
+        for part in &candidate.content.parts {
+            if !part.text.is_empty() {
+                status.received_text = true;
+                let _ = tx.send(StreamChunk { 
+                    delta: part.text.clone(), 
+                    thinking: false,
+                    done: false 
+                }).await;
+            }
+        }
+    }
+
+    fn generation_config(&self, params: &GenerationParams) -> Option<GeminiGenerationConfig> {
+        let stop_sequences = stop_sequences(params, "gemini", MAX_STOP_SEQUENCES);
+        let response_mime_type = match params.response_format {
+            ResponseFormat::Text => None,
+            ResponseFormat::Json => Some("application/json".to_string()),
+        };
+
+        if params.temperature.is_none()
+            && params.top_p.is_none()
+            && params.max_tokens.is_none()
+            && stop_sequences.is_none()
+            && response_mime_type.is_none()
+        {
+            return None;
+        }
+
+        Some(GeminiGenerationConfig {
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_output_tokens: params.max_tokens,
+            stop_sequences,
+            response_mime_type,
+        })
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<GeminiRequest> {
+        let (system_instruction, contents) = self.prepare_messages(messages, model)?;
+
+        Ok(GeminiRequest {
+            contents,
+            system_instruction,
+            generation_config: self.generation_config(params),
+        })
     }
 
     fn build_url(&self, version: &str, model: &str, action: &str, extra_query: Option<&str>) -> String {
@@ -99,133 +346,346 @@ impl GeminiProvider {
         model: &str,
         action: &str,
         stream: bool,
-    ) -> Result<reqwest::Response> {
+    ) -> ProviderResult<reqwest::Response> {
         // Always use v1beta as it supports system_instruction and newer models
         let url = self.build_url("v1beta", model, action, if stream { Some("alt=sse") } else { None });
         println!("[GEMINI] POST request to: {}", url.split("?key=").next().unwrap_or(&url));
 
-        let response = self.client
+        let mut request_builder = self.client
             .post(&url)
             .header("Content-Type", "application/json")
-            .json(request)
-            .send()
-            .await?;
+            .json(request);
+        // Streams can legitimately run longer than the request timeout; the client's
+        // read timeout catches a stalled one instead
+        if !stream {
+            request_builder = request_builder.timeout(self.timeouts.request);
+        }
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
 
         println!("[GEMINI] Response status: {}", response.status());
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Gemini API error: {}", error_text));
+            return Err(ProviderError::from_response("Gemini", response).await);
         }
 
         Ok(response)
     }
+
+    async fn fetch_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        let url = format!(
+            "https://generativelanguage.googleapis.com/v1beta/models?key={}",
+            self.api_key
+        );
+
+        let request_builder = self.client
+            .get(&url)
+            .timeout(self.timeouts.request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response("Gemini", response).await);
+        }
+
+        let result: ModelsResponse = response.json().await?;
+
+        Ok(result.models
+            .into_iter()
+            .filter(|m| m.supported_generation_methods.iter().any(|g| g == "generateContent"))
+            .map(|m| {
+                let id = m.name.trim_start_matches("models/").to_string();
+                ModelInfo {
+                    name: m.display_name.unwrap_or_else(|| id.clone()),
+                    id,
+                    provider: "gemini".to_string(),
+                    max_tokens: m.output_token_limit.unwrap_or(8192),
+                }
+            })
+            .collect())
+    }
+
+    fn fallback_models() -> Vec<ModelInfo> {
+        vec![
+            ModelInfo {
+                id: "gemini-2.0-flash".to_string(),
+                name: "Gemini 2.0 Flash".to_string(),
+                provider: "gemini".to_string(),
+                max_tokens: 8192,
+            },
+            ModelInfo {
+                id: "gemini-2.0-flash-exp".to_string(),
+                name: "Gemini 2.0 Flash (Experimental)".to_string(),
+                provider: "gemini".to_string(),
+                max_tokens: 8192,
+            },
+            ModelInfo {
+                id: "gemini-1.5-pro".to_string(),
+                name: "Gemini 1.5 Pro".to_string(),
+                provider: "gemini".to_string(),
+                max_tokens: 8192,
+            },
+            ModelInfo {
+                id: "gemini-1.5-flash".to_string(),
+                name: "Gemini 1.5 Flash".to_string(),
+                provider: "gemini".to_string(),
+                max_tokens: 8192,
+            },
+        ]
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    #[serde(default)]
+    models: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelEntry {
+    name: String,
+    display_name: Option<String>,
+    output_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
 }
 
 #[async_trait]
 impl Provider for GeminiProvider {
-    async fn chat(&self, messages: Vec<Message>, model: &str) -> Result<String> {
-        let (system_instruction, contents) = self.prepare_messages(messages);
-
-        let request = GeminiRequest {
-            contents,
-            system_instruction,
-        };
+    async fn chat(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<ChatResult> {
+        let params = &with_model_max_tokens(self, "gemini", model, params, false).await;
+        let request = self.build_request(messages, model, params)?;
 
         let response = self.post_request(&request, model, "generateContent", false).await?;
 
         let result: GeminiResponse = response.json().await?;
-        
-        Ok(result.candidates
-            .first()
-            .and_then(|c| c.content.parts.first())
-            .map(|p| p.text.clone())
-            .unwrap_or_default())
+
+        let content = result.text();
+        if content.trim().is_empty() {
+            return Err(ProviderError::empty_response("Gemini", result.empty_reason().as_deref()));
+        }
+        let usage = result.usage_metadata.map(|u| TokenUsage {
+            prompt_tokens: u.prompt_token_count,
+            completion_tokens: u.candidates_token_count,
+            total_tokens: u.total_token_count,
+            ..Default::default()
+        });
+
+        Ok(ChatResult { content, usage })
     }
 
     async fn chat_stream(
         &self,
         messages: Vec<Message>,
         model: &str,
+        params: &GenerationParams,
         tx: mpsc::Sender<StreamChunk>,
-    ) -> Result<()> {
-        let (system_instruction, contents) = self.prepare_messages(messages);
-
-        let request = GeminiRequest {
-            contents,
-            system_instruction,
-        };
+    ) -> ProviderResult<()> {
+        let params = &with_model_max_tokens(self, "gemini", model, params, true).await;
+        let request = self.build_request(messages, model, params)?;
         let response = self.post_request(&request, model, "streamGenerateContent", true).await?;
 
+        let is_sse = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+
         let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = chunk_result?;
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
-
-            // Process complete SSE events (separated by double newlines)
-            while let Some(pos) = buffer.find("\n\n") {
-                let event_str = buffer[..pos].to_string();
-                buffer = buffer[pos + 2..].to_string();
-
-                // Parse SSE data line
-                for line in event_str.lines() {
-                    if let Some(data) = line.strip_prefix("data: ") {
-                        let data = data.trim();
-                        if data.is_empty() {
-                            continue;
-                        }
-
-                        // Parse Gemini JSON response
-                        if let Ok(response) = serde_json::from_str::<GeminiResponse>(data) {
-                            if let Some(candidate) = response.candidates.first() {
-                                if let Some(part) = candidate.content.parts.first() {
-                                    if !part.text.is_empty() {
-                                        let _ = tx.send(StreamChunk { 
-                                            delta: part.text.clone(), 
-                                            done: false 
-                                        }).await;
-                                    }
-                                }
-                            }
-                        }
-                    }
+        let mut status = StreamStatus::default();
+
+        if is_sse {
+            // Each event is parsed only once it is complete, so objects split across
+            // network chunks or data lines arrive whole
+            let mut decoder = SseDecoder::new();
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = chunk_result?;
+                for data in decoder.push(&chunk) {
+                    self.send_stream_object(&data, &tx, &mut status).await;
                 }
             }
+            if let Some(data) = decoder.finish() {
+                self.send_stream_object(&data, &tx, &mut status).await;
+            }
+        } else {
+            // Without alt=sse taking effect the body is a JSON array streamed piecemeal
+            // Bytes are buffered so a character split across network chunks arrives whole
+            let mut buffer = Vec::new();
+            while let Some(chunk_result) = stream.next().await {
+                let chunk = chunk_result?;
+                buffer.extend_from_slice(&chunk);
+
+                for object in extract_json_objects(&mut buffer) {
+                    self.send_stream_object(&object, &tx, &mut status).await;
+                }
+            }
+
+            let rest = String::from_utf8_lossy(&buffer);
+            if !rest.trim().trim_matches(|c| c == ']' || c == ',').trim().is_empty() {
+                eprintln!("[GEMINI] Discarding incomplete stream data: {}", rest.trim());
+            }
         }
 
-        // Process any remaining data in buffer
-        for line in buffer.lines() {
-            if let Some(data) = line.strip_prefix("data: ") {
-                let data = data.trim();
-                if let Ok(response) = serde_json::from_str::<GeminiResponse>(data) {
-                    if let Some(candidate) = response.candidates.first() {
-                        if let Some(part) = candidate.content.parts.first() {
-                            if !part.text.is_empty() {
-                                let _ = tx.send(StreamChunk { 
-                                    delta: part.text.clone(), 
-                                    done: false 
-                                }).await;
-                            }
-                        }
-                    }
-                }
+        if !status.received_text {
+            return Err(ProviderError::empty_response("Gemini", status.empty_reason.as_deref()));
+        }
+
+        let _ = tx.send(StreamChunk { delta: String::new(), thinking: false, done: true }).await;
+        Ok(())
+    }
+
+    async fn list_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        match self.fetch_models().await {
+            Ok(models) if !models.is_empty() => Ok(models),
+            Ok(_) => Ok(Self::fallback_models()),
+            Err(e) => {
+                eprintln!("[GEMINI] Failed to fetch models, using fallback list: {}", e);
+                Ok(Self::fallback_models())
             }
         }
+    }
+
+    async fn validate(&self) -> ProviderResult<()> {
+        let request_builder = self.client
+            .get(format!(
+                "https://generativelanguage.googleapis.com/v1beta/models?pageSize=1&key={}",
+                self.api_key
+            ))
+            .timeout(self.timeouts.request);
+
+        // A single attempt: retries would only delay telling the user their key is wrong
+        let response = send_with_retry(request_builder, 0).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response("Gemini", response).await);
+        }
 
-        let _ = tx.send(StreamChunk { delta: String::new(), done: true }).await;
         Ok(())
     }
 
-    fn list_models(&self) -> Vec<ModelInfo> {
+    fn count_tokens(&self, model: &str, messages: &[Message]) -> usize {
+        count_message_tokens("gemini", model, messages).0
+    }
+
+    fn context_window(&self, model: &str) -> usize {
+        context_window("gemini", model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<Message> {
         vec![
-            ModelInfo {
-                id: "gemini-2.0-flash-exp".to_string(),
-                name: "Gemini 2.0 Flash".to_string(),
-                provider: "gemini".to_string(),
-                max_tokens: 8192,
-            },
+            Message { role: "system".to_string(), content: "Be brief.".to_string(), images: Vec::new() },
+            Message { role: "user".to_string(), content: "List three colors.".to_string(), images: Vec::new() },
         ]
     }
+
+    fn request_json(params: &GenerationParams) -> serde_json::Value {
+        let provider = GeminiProvider::new("key".to_string(), Timeouts::default());
+        let request = provider.build_request(messages(), "gemini-2.0-flash", params).unwrap();
+        serde_json::to_value(&request).unwrap()
+    }
+
+    #[test]
+    fn json_format_sets_response_mime_type() {
+        let params = GenerationParams { response_format: ResponseFormat::Json, ..Default::default() };
+        let request = request_json(&params);
+
+        assert_eq!(request["generationConfig"]["responseMimeType"], "application/json");
+        // Gemini's JSON mode is native, so the system instruction is left alone
+        assert_eq!(request["system_instruction"]["parts"][0]["text"], "Be brief.");
+    }
+
+    /// The text of each streamed response object
+    fn texts(objects: &[String]) -> Vec<String> {
+        objects
+            .iter()
+            .map(|object| serde_json::from_str::<GeminiResponse>(object).unwrap().text())
+            .collect()
+    }
+
+    const FIRST: &str = r#"{"candidates":[{"content":{"parts":[{"text":"Hello, "}]}}]}"#;
+    // Braces, brackets and an escaped quote inside a string must not end the object early
+    const SECOND: &str = r#"{"candidates":[{"content":{"parts":[{"text":"a } ] \" {world"}]}}]}"#;
+
+    #[test]
+    fn extracts_objects_from_json_array_body() {
+        let body = format!("[{},\r\n{}\n]", FIRST, SECOND);
+        let mut buffer = Vec::new();
+        let mut objects = Vec::new();
+
+        // Feed a few bytes at a time so objects are split across chunks
+        for piece in body.as_bytes().chunks(7) {
+            buffer.extend_from_slice(piece);
+            objects.extend(extract_json_objects(&mut buffer));
+        }
+
+        assert_eq!(objects, vec![FIRST.to_string(), SECOND.to_string()]);
+        assert_eq!(texts(&objects), vec!["Hello, ", "a } ] \" {world"]);
+        assert!(buffer.is_empty(), "{:?}", String::from_utf8_lossy(&buffer));
+    }
+
+    #[test]
+    fn extractor_keeps_incomplete_object_for_next_chunk() {
+        let mut buffer = format!("[{},\n{}", FIRST, &SECOND[..20]).into_bytes();
+
+        assert_eq!(extract_json_objects(&mut buffer), vec![FIRST.to_string()]);
+        assert_eq!(buffer, SECOND[..20].as_bytes());
+
+        buffer.extend_from_slice(SECOND[20..].as_bytes());
+        assert_eq!(extract_json_objects(&mut buffer), vec![SECOND.to_string()]);
+    }
+
+    #[test]
+    fn extractor_reassembles_characters_split_across_chunks() {
+        let object = r#"{"candidates":[{"content":{"parts":[{"text":"Grüße 👋"}]}}]}"#;
+        let body = format!("[{}]", object);
+        // Split inside the two bytes of "ü" and again inside the four of "👋"
+        let umlaut = body.find('ü').unwrap() + 1;
+        let wave = body.find('👋').unwrap() + 2;
+
+        let mut buffer = Vec::new();
+        let mut objects = Vec::new();
+        for piece in [&body.as_bytes()[..umlaut], &body.as_bytes()[umlaut..wave], &body.as_bytes()[wave..]] {
+            buffer.extend_from_slice(piece);
+            objects.extend(extract_json_objects(&mut buffer));
+        }
+
+        assert_eq!(objects, vec![object.to_string()]);
+        assert_eq!(texts(&objects), vec!["Grüße 👋"]);
+    }
+
+    #[test]
+    fn extracts_objects_from_sse_body() {
+        let body = format!("data: {}\r\n\r\ndata: {}\r\n\r\n", FIRST, SECOND);
+
+        // The SSE path decodes events first; the extractor copes with the same body
+        // should the server ignore alt=sse in its content type
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for piece in body.as_bytes().chunks(7) {
+            events.extend(decoder.push(piece));
+        }
+        events.extend(decoder.finish());
+        assert_eq!(texts(&events), vec!["Hello, ", "a } ] \" {world"]);
+
+        let mut buffer = body.into_bytes();
+        assert_eq!(extract_json_objects(&mut buffer), vec![FIRST.to_string(), SECOND.to_string()]);
+    }
+
+    #[test]
+    fn text_format_omits_generation_config() {
+        let request = request_json(&GenerationParams::default());
+
+        assert!(request.get("generationConfig").is_none());
+    }
 }
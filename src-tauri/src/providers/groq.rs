@@ -0,0 +1,45 @@
+use super::openai_compatible::{CompatibleConfig, JsonMode, OpenAICompatibleProvider};
+use super::Timeouts;
+
+const BASE_URL: &str = "https://api.groq.com/openai/v1";
+
+/// Groq accepts at most four stop sequences per request
+const MAX_STOP_SEQUENCES: usize = 4;
+
+const FALLBACK_MODELS: &[(&str, &str, u32)] = &[
+    ("llama-3.3-70b-versatile", "Llama 3.3 70B Versatile", 32768),
+    ("llama-3.1-8b-instant", "Llama 3.1 8B Instant", 8192),
+    ("meta-llama/llama-4-scout-17b-16e-instruct", "Llama 4 Scout 17B", 8192),
+    ("mixtral-8x7b-32768", "Mixtral 8x7B", 4096),
+    ("gemma2-9b-it", "Gemma 2 9B", 4096),
+];
+
+pub fn provider(api_key: String, timeouts: Timeouts) -> OpenAICompatibleProvider {
+    OpenAICompatibleProvider::new(
+        CompatibleConfig {
+            name: "groq",
+            display_name: "Groq",
+            base_url: BASE_URL.to_string(),
+            extra_headers: &[],
+            max_stop_sequences: MAX_STOP_SEQUENCES,
+            json_mode: JsonMode::ResponseFormat,
+            supports_vision,
+            is_chat_model,
+            chat_path: "/chat/completions",
+            validate_path: "/models",
+            fallback_models: FALLBACK_MODELS,
+        },
+        api_key,
+        timeouts,
+    )
+}
+
+/// Only Groq's Llama 4 models accept image input
+fn supports_vision(model: &str) -> bool {
+    model.contains("llama-4")
+}
+
+/// The models endpoint also lists speech-to-text, text-to-speech and moderation models
+fn is_chat_model(id: &str) -> bool {
+    !id.contains("whisper") && !id.contains("tts") && !id.contains("guard")
+}
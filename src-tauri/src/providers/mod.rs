@@ -2,21 +2,63 @@ mod anthropic;
 mod openai;
 mod gemini;
 mod deepseek;
+mod openrouter;
+mod groq;
+mod custom;
+mod azure;
+mod openai_compatible;
+mod error;
+mod sse;
+mod tokens;
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 use tokio::sync::mpsc;
 
 pub use anthropic::AnthropicProvider;
-pub use openai::OpenAIProvider;
 pub use gemini::GeminiProvider;
-pub use deepseek::DeepSeekProvider;
+pub use error::{ProviderError, ProviderResult};
+pub use tokens::{context_window, count_message_tokens};
+pub use custom::{
+    read_config as read_custom_provider_config, save_config as save_custom_provider_config,
+    CustomProviderConfig,
+};
+pub use azure::{
+    read_config as read_azure_provider_config, save_config as save_azure_provider_config,
+    AzureProviderConfig,
+};
+
+const STORE_PATH: &str = "settings.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    #[serde(default)]
+    pub images: Vec<ImagePart>,
+}
+
+/// Base64-encoded image attached to a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagePart {
+    pub mime_type: String,
+    pub data: String,
+}
+
+/// Fail early when images are sent to a model that can't read them
+fn ensure_vision_support(messages: &[Message], model: &str, supports_vision: bool) -> ProviderResult<()> {
+    if !supports_vision && messages.iter().any(|m| !m.images.is_empty()) {
+        return Err(ProviderError::InvalidRequest(format!("Model {} does not support image input", model)));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,31 +69,448 @@ pub struct ModelInfo {
     pub max_tokens: u32,
 }
 
+/// Optional sampling overrides for a single request.
+/// Fields left as `None` fall back to each provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Sequences that end generation when produced
+    #[serde(default)]
+    pub stop: Vec<String>,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// Mark the system prompt and large leading context as cacheable (Anthropic only)
+    #[serde(default)]
+    pub enable_prompt_cache: bool,
+    /// Token budget for extended thinking (Anthropic only)
+    #[serde(default)]
+    pub thinking: Option<u32>,
+}
+
+/// Shape the reply should take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+const JSON_ONLY_INSTRUCTION: &str = "Respond with a single valid JSON value only. \
+Do not wrap it in markdown code fences or add any text before or after it.";
+
+/// For providers without a native JSON mode, ask for JSON through the system message
+fn with_json_instruction(mut messages: Vec<Message>, params: &GenerationParams) -> Vec<Message> {
+    if params.response_format != ResponseFormat::Json {
+        return messages;
+    }
+
+    match messages.iter_mut().find(|m| m.role == "system") {
+        Some(system) => {
+            system.content = format!("{}\n\n{}", system.content, JSON_ONLY_INSTRUCTION);
+        }
+        None => messages.insert(0, Message {
+            role: "system".to_string(),
+            content: JSON_ONLY_INSTRUCTION.to_string(),
+            images: Vec::new(),
+        }),
+    }
+
+    messages
+}
+
+/// Stop sequences to send to a provider, capped at the number its API accepts.
+/// Extras are dropped with a warning rather than failing the request.
+fn stop_sequences(params: &GenerationParams, provider_name: &str, limit: usize) -> Option<Vec<String>> {
+    let mut stop: Vec<String> = params.stop.iter()
+        .filter(|s| !s.is_empty())
+        .cloned()
+        .collect();
+
+    if stop.len() > limit {
+        println!(
+            "[{}] Only {} stop sequences are supported, dropping {}",
+            provider_name.to_uppercase(),
+            limit,
+            stop.len() - limit
+        );
+        stop.truncate(limit);
+    }
+
+    if stop.is_empty() { None } else { Some(stop) }
+}
+
+/// Token counts reported by the provider for a single response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    /// Prompt tokens served from the provider's prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_tokens: Option<u32>,
+    /// Prompt tokens written to the provider's prompt cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write_tokens: Option<u32>,
+}
+
+/// Result of a non-streaming chat request
+#[derive(Debug, Clone)]
+pub struct ChatResult {
+    pub content: String,
+    pub usage: Option<TokenUsage>,
+}
+
 /// Chunk sent during streaming
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamChunk {
     pub delta: String,
+    /// Reasoning text rather than part of the answer
+    #[serde(default)]
+    pub thinking: bool,
     pub done: bool,
 }
 
 #[async_trait]
 pub trait Provider: Send + Sync {
-    async fn chat(&self, messages: Vec<Message>, model: &str) -> Result<String>;
+    async fn chat(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<ChatResult>;
     async fn chat_stream(
         &self,
         messages: Vec<Message>,
         model: &str,
+        params: &GenerationParams,
         tx: mpsc::Sender<StreamChunk>,
-    ) -> Result<()>;
-    fn list_models(&self) -> Vec<ModelInfo>;
+    ) -> ProviderResult<()>;
+    async fn list_models(&self) -> ProviderResult<Vec<ModelInfo>>;
+    /// Make a cheap authenticated call to check that the API key works
+    async fn validate(&self) -> ProviderResult<()>;
+    /// Estimate the prompt tokens `messages` will use with `model`, without a network call
+    fn count_tokens(&self, model: &str, messages: &[Message]) -> usize;
+    /// Context window of `model` in tokens
+    fn context_window(&self, model: &str) -> usize;
+}
+
+/// Output token cap when neither the request nor the model list provides one
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+/// Default output cap for non-streamed requests, whose whole reply must arrive
+/// within the request timeout
+const NON_STREAMING_MAX_TOKENS: u32 = 8192;
+
+/// Drop the oldest messages until the prompt, plus room for the reply, fits in the
+/// model's context window. The reply gets the `max_tokens` the request will actually
+/// send, which defaults to the model's output limit, capped for non-streamed requests
+/// and on OpenRouter. System messages (the
+/// system prompt and any knowledge base context) and the latest message are always
+/// kept, and a user turn is never left opening with the assistant's reply. Fails if the
+/// kept messages alone don't fit.
+pub async fn trim_history_to_fit(
+    provider: &dyn Provider,
+    provider_name: &str,
+    model: &str,
+    mut messages: Vec<Message>,
+    params: &GenerationParams,
+    stream: bool,
+) -> ProviderResult<Vec<Message>> {
+    let params = with_model_max_tokens(provider, &provider_name.to_lowercase(), model, params, stream).await;
+    let reply_tokens = params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize;
+    let budget = provider.context_window(model).saturating_sub(reply_tokens);
+
+    // Counting is additive per message, so each one's share can be taken off the total
+    let overhead = provider.count_tokens(model, &[]);
+    let mut tokens = provider.count_tokens(model, &messages);
+    let mut dropped = 0;
+
+    while tokens > budget {
+        let last = messages.len().saturating_sub(1);
+        let Some(oldest) = messages.iter().take(last).position(|m| m.role != "system") else {
+            return Err(ProviderError::InvalidRequest(format!(
+                "The message needs about {} tokens, more than the {} available in {}'s context window",
+                tokens, budget, model
+            )));
+        };
+
+        let removed = messages.remove(oldest);
+        tokens -= provider.count_tokens(model, std::slice::from_ref(&removed)) - overhead;
+        dropped += 1;
+
+        // Take the reply to a dropped question with it
+        while oldest + 1 < messages.len() && messages[oldest].role == "assistant" {
+            let removed = messages.remove(oldest);
+            tokens -= provider.count_tokens(model, std::slice::from_ref(&removed)) - overhead;
+            dropped += 1;
+        }
+    }
+
+    if dropped > 0 {
+        println!("[PROVIDER] Trimmed {} old messages to fit {}'s context window", dropped, model);
+    }
+    Ok(messages)
+}
+
+/// How long a chat waits on the model list to learn the model's output limit
+const MODEL_LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Advertised output limits by provider then model id, so chats don't refetch the model list
+static MODEL_MAX_TOKENS: OnceLock<Mutex<HashMap<String, HashMap<String, u32>>>> = OnceLock::new();
+
+/// Remember the output limits from a provider's model list
+pub fn remember_model_limits(provider_name: &str, models: &[ModelInfo]) {
+    let limits = models.iter().map(|m| (m.id.clone(), m.max_tokens)).collect();
+    if let Ok(mut cache) = MODEL_MAX_TOKENS.get_or_init(Default::default).lock() {
+        cache.insert(provider_name.to_string(), limits);
+    }
+}
+
+/// `params` with `max_tokens` defaulted to the model's advertised output limit.
+/// An explicit `max_tokens` always wins; see `default_max_tokens` for the default.
+async fn with_model_max_tokens<P: Provider + ?Sized>(
+    provider: &P,
+    provider_name: &str,
+    model: &str,
+    params: &GenerationParams,
+    stream: bool,
+) -> GenerationParams {
+    let mut params = params.clone();
+    if params.max_tokens.is_some() {
+        return params;
+    }
+
+    let cached = MODEL_MAX_TOKENS
+        .get_or_init(Default::default)
+        .lock()
+        .ok()
+        .and_then(|cache| cache.get(provider_name).map(|limits| limits.get(model).copied()));
+
+    let limit = match cached {
+        Some(limit) => limit,
+        None => match tokio::time::timeout(MODEL_LOOKUP_TIMEOUT, provider.list_models()).await {
+            Ok(Ok(models)) => {
+                remember_model_limits(provider_name, &models);
+                models.iter().find(|m| m.id == model).map(|m| m.max_tokens)
+            }
+            _ => None,
+        },
+    };
+
+    params.max_tokens = Some(default_max_tokens(provider_name, limit, stream));
+    params
+}
+
+/// Output cap for a request that doesn't set one: the model's output limit, or
+/// `DEFAULT_MAX_TOKENS` for models missing from the list. Non-streamed replies are
+/// capped at `NON_STREAMING_MAX_TOKENS`, and OpenRouter at `DEFAULT_MAX_TOKENS`.
+fn default_max_tokens(provider_name: &str, limit: Option<u32>, stream: bool) -> u32 {
+    let limit = limit.unwrap_or(DEFAULT_MAX_TOKENS);
+    // OpenRouter checks the account's credits against the whole reservation, so a model's
+    // full output limit would turn away low-credit accounts with a 402
+    if provider_name == "openrouter" {
+        return limit.min(DEFAULT_MAX_TOKENS);
+    }
+    if !stream {
+        return limit.min(NON_STREAMING_MAX_TOKENS);
+    }
+    limit
+}
+
+/// Read a provider's custom base URL from settings, if one is configured
+fn base_url_setting(app: &AppHandle, provider_name: &str) -> Option<String> {
+    let store = app.store(STORE_PATH).ok()?;
+    store
+        .get(format!("{}_base_url", provider_name))
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .filter(|s| !s.trim().is_empty())
+}
+
+/// Deadlines for provider HTTP calls
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    pub connect: Duration,
+    /// Whole-request deadline for non-streaming calls
+    pub request: Duration,
+    /// Longest wait for the next bytes of a response, so a stalled stream errors out
+    pub read_idle: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(30),
+            request: Duration::from_secs(120),
+            read_idle: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Read `connect_timeout_secs`, `request_timeout_secs` and `stream_idle_timeout_secs`
+    /// from settings, keeping the default for anything unset or zero
+    fn from_settings(app: &AppHandle) -> Self {
+        let defaults = Self::default();
+        let Ok(store) = app.store(STORE_PATH) else {
+            return defaults;
+        };
+        let seconds = |key: &str, default: Duration| {
+            store
+                .get(key)
+                .and_then(|v| v.as_u64())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+
+        Self {
+            connect: seconds("connect_timeout_secs", defaults.connect),
+            request: seconds("request_timeout_secs", defaults.request),
+            read_idle: seconds("stream_idle_timeout_secs", defaults.read_idle),
+        }
+    }
+
+    /// HTTP client for a provider. The whole-request timeout isn't set here because it
+    /// would also cut off long streams; non-streaming calls apply it per request.
+    fn client(&self) -> Client {
+        Client::builder()
+            .connect_timeout(self.connect)
+            .read_timeout(self.read_idle)
+            .build()
+            .unwrap_or_else(|e| {
+                eprintln!("[PROVIDER] Failed to build HTTP client with timeouts, using defaults: {}", e);
+                Client::new()
+            })
+    }
 }
 
-pub fn create_provider(provider_name: &str, api_key: &str) -> Result<Box<dyn Provider>> {
+/// Every provider name `create_provider` accepts
+pub const PROVIDER_NAMES: &[&str] = &["anthropic", "openai", "gemini", "deepseek", "openrouter", "groq", "custom", "azure"];
+
+pub fn create_provider(app: &AppHandle, provider_name: &str, api_key: &str) -> Result<Box<dyn Provider>> {
+    let timeouts = Timeouts::from_settings(app);
+
     match provider_name.to_lowercase().as_str() {
-        "anthropic" => Ok(Box::new(AnthropicProvider::new(api_key.to_string()))),
-        "openai" => Ok(Box::new(OpenAIProvider::new(api_key.to_string()))),
-        "gemini" => Ok(Box::new(GeminiProvider::new(api_key.to_string()))),
-        "deepseek" => Ok(Box::new(DeepSeekProvider::new(api_key.to_string()))),
+        "anthropic" => Ok(Box::new(AnthropicProvider::new(api_key.to_string(), timeouts))),
+        "openai" => Ok(Box::new(openai::provider(
+            api_key.to_string(),
+            base_url_setting(app, "openai"),
+            timeouts,
+        ))),
+        "gemini" => Ok(Box::new(GeminiProvider::new(api_key.to_string(), timeouts))),
+        "deepseek" => Ok(Box::new(deepseek::provider(
+            api_key.to_string(),
+            base_url_setting(app, "deepseek"),
+            timeouts,
+        ))),
+        "openrouter" => Ok(Box::new(openrouter::provider(api_key.to_string(), timeouts))),
+        "groq" => Ok(Box::new(groq::provider(api_key.to_string(), timeouts))),
+        "custom" => {
+            let config = custom::read_config(app)?
+                .ok_or_else(|| anyhow::anyhow!("Custom provider is not configured"))?;
+            Ok(Box::new(custom::provider(config, api_key, timeouts)?))
+        }
+        "azure" => {
+            let config = azure::read_config(app)?
+                .ok_or_else(|| anyhow::anyhow!("Azure OpenAI is not configured"))?;
+            Ok(Box::new(azure::provider(config, api_key, timeouts)?))
+        }
         _ => Err(anyhow::anyhow!("Unknown provider: {}", provider_name)),
     }
 }
+
+/// Default number of retries for rate-limited or failed provider requests
+pub const MAX_RETRIES: u32 = 3;
+
+/// Upper bound on how long a single `Retry-After` wait may last
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Send a request, retrying on HTTP 429, 5xx and connection failures with
+/// exponential backoff. A `Retry-After` header, when present, takes precedence
+/// over the computed delay. Only the initial response is retried, so streaming
+/// bodies are never replayed mid-stream.
+pub async fn send_with_retry(request: RequestBuilder, max_retries: u32) -> ProviderResult<Response> {
+    let mut attempt = 0;
+
+    loop {
+        let builder = request
+            .try_clone()
+            .ok_or_else(|| ProviderError::Other("Request cannot be retried".to_string()))?;
+
+        match builder.send().await {
+            Ok(response) => {
+                let status = response.status();
+                let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+
+                if !retryable || attempt >= max_retries {
+                    return Ok(response);
+                }
+
+                let delay = retry_after(&response).unwrap_or_else(|| backoff_delay(attempt));
+                attempt += 1;
+                println!(
+                    "[RETRY] HTTP {} - retrying in {:?} (attempt {}/{})",
+                    status, delay, attempt, max_retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) if attempt < max_retries && (e.is_connect() || e.is_timeout()) => {
+                let delay = backoff_delay(attempt);
+                attempt += 1;
+                println!(
+                    "[RETRY] Request failed ({}) - retrying in {:?} (attempt {}/{})",
+                    e, delay, attempt, max_retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    Duration::from_millis(500 * 2u64.pow(attempt))
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds: u64 = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(Duration::from_secs(seconds).min(MAX_RETRY_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_default_is_the_model_limit() {
+        assert_eq!(default_max_tokens("anthropic", Some(64_000), true), 64_000);
+        assert_eq!(default_max_tokens("openai", Some(2048), true), 2048);
+    }
+
+    #[test]
+    fn model_missing_from_list_gets_default() {
+        assert_eq!(default_max_tokens("anthropic", None, true), DEFAULT_MAX_TOKENS);
+        assert_eq!(default_max_tokens("anthropic", None, false), DEFAULT_MAX_TOKENS);
+    }
+
+    #[test]
+    fn non_streamed_default_is_capped() {
+        assert_eq!(default_max_tokens("anthropic", Some(64_000), false), NON_STREAMING_MAX_TOKENS);
+        assert_eq!(default_max_tokens("gemini", Some(4096), false), 4096);
+    }
+
+    #[test]
+    fn openrouter_default_is_capped() {
+        assert_eq!(default_max_tokens("openrouter", Some(128_000), true), DEFAULT_MAX_TOKENS);
+        assert_eq!(default_max_tokens("openrouter", Some(1024), false), 1024);
+    }
+}
@@ -0,0 +1,525 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::{
+    context_window, count_message_tokens, ensure_vision_support, send_with_retry,
+    stop_sequences, with_json_instruction, with_model_max_tokens, ChatResult, GenerationParams,
+    Message, ModelInfo, Provider, ProviderError, ProviderResult, ResponseFormat, StreamChunk,
+    Timeouts, TokenUsage, DEFAULT_MAX_TOKENS, MAX_RETRIES,
+};
+use super::sse::SseDecoder;
+
+/// How a backend is asked for a JSON reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonMode {
+    /// `response_format: {"type": "json_object"}`
+    ResponseFormat,
+    /// No native JSON mode, so the system message asks for it
+    Instruction,
+}
+
+/// What sets one OpenAI-compatible backend apart from another
+pub struct CompatibleConfig {
+    /// Provider name used for model entries, logs and the model limit cache
+    pub name: &'static str,
+    /// Provider name as shown in error messages
+    pub display_name: &'static str,
+    /// API root without a trailing slash, e.g. `https://api.openai.com/v1`
+    pub base_url: String,
+    /// Sent with every request in addition to the auth header
+    pub extra_headers: &'static [(&'static str, &'static str)],
+    pub max_stop_sequences: usize,
+    pub json_mode: JsonMode,
+    pub supports_vision: fn(&str) -> bool,
+    /// Keeps chat models from a models list that also holds embedding, audio, etc.
+    pub is_chat_model: fn(&str) -> bool,
+    /// Chat completions endpoint under the base URL; `{model}` is replaced by the model id
+    pub chat_path: &'static str,
+    /// Authenticated endpoint used to check the API key
+    pub validate_path: &'static str,
+    /// Models as (id, name, max output tokens), offered when the list can't be fetched
+    pub fallback_models: &'static [(&'static str, &'static str, u32)],
+}
+
+/// A configured base URL with any trailing slash removed, or `default` when unset
+pub fn base_url_or(base_url: Option<String>, default: &str) -> String {
+    base_url
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Chat backend speaking OpenAI's chat completions API, shared by every provider
+/// that exposes it so request building and stream parsing live in one place
+pub struct OpenAICompatibleProvider {
+    config: CompatibleConfig,
+    /// Header carrying the API key, as (name, value); `None` for open endpoints
+    auth_header: Option<(String, String)>,
+    /// Offered instead of querying the models endpoint when set
+    fixed_models: Vec<ModelInfo>,
+    /// Query parameters sent with every request
+    query: Vec<(String, String)>,
+    client: Client,
+    timeouts: Timeouts,
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormatField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct ResponseFormatField {
+    #[serde(rename = "type")]
+    format_type: String,
+}
+
+#[derive(Serialize)]
+struct ChatMessage {
+    role: String,
+    content: ChatContent,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Option<ResponseMessage>,
+    delta: Option<DeltaMessage>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeltaMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ModelsResponse {
+    data: Vec<ModelEntry>,
+}
+
+/// Model list entry; fields beyond `id` are only sent by some providers
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+    name: Option<String>,
+    active: Option<bool>,
+    max_completion_tokens: Option<u32>,
+    top_provider: Option<TopProvider>,
+}
+
+#[derive(Deserialize)]
+struct TopProvider {
+    max_completion_tokens: Option<u32>,
+}
+
+impl OpenAICompatibleProvider {
+    /// Create a provider authenticating with `Authorization: Bearer <api_key>`
+    pub fn new(config: CompatibleConfig, api_key: String, timeouts: Timeouts) -> Self {
+        Self {
+            config,
+            auth_header: Some(("Authorization".to_string(), format!("Bearer {}", api_key))),
+            fixed_models: Vec::new(),
+            query: Vec::new(),
+            client: timeouts.client(),
+            timeouts,
+        }
+    }
+
+    /// Authenticate with the given (name, value) header instead, or not at all
+    pub fn with_auth_header(mut self, auth_header: Option<(String, String)>) -> Self {
+        self.auth_header = auth_header;
+        self
+    }
+
+    /// Offer these models instead of querying the models endpoint
+    pub fn with_models(mut self, models: Vec<ModelInfo>) -> Self {
+        self.fixed_models = models;
+        self
+    }
+
+    /// Send these query parameters with every request, e.g. an API version
+    pub fn with_query(mut self, query: Vec<(String, String)>) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Add the auth header, any extra headers and the query parameters
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        let builder = match &self.auth_header {
+            Some((name, value)) => builder.header(name.as_str(), value),
+            None => builder,
+        };
+        let builder = if self.query.is_empty() { builder } else { builder.query(&self.query) };
+        self.config
+            .extra_headers
+            .iter()
+            .fold(builder, |builder, (name, value)| builder.header(*name, *value))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.base_url, path)
+    }
+
+    fn chat_url(&self, model: &str) -> String {
+        self.url(&self.config.chat_path.replace("{model}", model))
+    }
+
+    fn build_request(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> ProviderResult<ChatRequest> {
+        ensure_vision_support(&messages, model, (self.config.supports_vision)(model))?;
+
+        let messages = match self.config.json_mode {
+            JsonMode::Instruction => with_json_instruction(messages, params),
+            JsonMode::ResponseFormat => messages,
+        };
+
+        let messages = messages
+            .into_iter()
+            .map(|m| {
+                let content = if m.images.is_empty() {
+                    ChatContent::Text(m.content)
+                } else {
+                    let mut parts = vec![ChatContentPart::Text { text: m.content }];
+                    parts.extend(m.images.into_iter().map(|image| ChatContentPart::ImageUrl {
+                        image_url: ImageUrl {
+                            url: format!("data:{};base64,{}", image.mime_type, image.data),
+                        },
+                    }));
+                    ChatContent::Parts(parts)
+                };
+
+                ChatMessage {
+                    role: m.role,
+                    content,
+                }
+            })
+            .collect();
+
+        let response_format = match (self.config.json_mode, params.response_format) {
+            (JsonMode::ResponseFormat, ResponseFormat::Json) => Some(ResponseFormatField {
+                format_type: "json_object".to_string(),
+            }),
+            _ => None,
+        };
+
+        Ok(ChatRequest {
+            model: model.to_string(),
+            messages,
+            max_tokens: params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            temperature: params.temperature,
+            top_p: params.top_p,
+            stop: stop_sequences(params, self.config.name, self.config.max_stop_sequences),
+            response_format,
+            stream: stream.then_some(true),
+        })
+    }
+
+    async fn fetch_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        let request_builder = self.request(self.client.get(self.url("/models")))
+            .timeout(self.timeouts.request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response(self.config.display_name, response).await);
+        }
+
+        let result: ModelsResponse = response.json().await?;
+
+        let mut models: Vec<ModelInfo> = result.data
+            .into_iter()
+            .filter(|m| m.active.unwrap_or(true) && (self.config.is_chat_model)(&m.id))
+            .map(|m| ModelInfo {
+                name: m.name.unwrap_or_else(|| m.id.clone()),
+                max_tokens: m.max_completion_tokens
+                    .or_else(|| m.top_provider.and_then(|p| p.max_completion_tokens))
+                    .unwrap_or(DEFAULT_MAX_TOKENS),
+                id: m.id,
+                provider: self.config.name.to_string(),
+            })
+            .collect();
+        models.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(models)
+    }
+
+    fn fallback_models(&self) -> Vec<ModelInfo> {
+        self.config
+            .fallback_models
+            .iter()
+            .map(|(id, name, max_tokens)| ModelInfo {
+                id: id.to_string(),
+                name: name.to_string(),
+                provider: self.config.name.to_string(),
+                max_tokens: *max_tokens,
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAICompatibleProvider {
+    async fn chat(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+    ) -> ProviderResult<ChatResult> {
+        let params = &with_model_max_tokens(self, self.config.name, model, params, false).await;
+        let request = self.build_request(messages, model, params, false)?;
+
+        let request_builder = self.request(self.client.post(self.chat_url(model)))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .timeout(self.timeouts.request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response(self.config.display_name, response).await);
+        }
+
+        let result: ChatResponse = response.json().await?;
+
+        let choice = result.choices.first();
+        let content = choice
+            .and_then(|c| c.message.as_ref())
+            .and_then(|m| m.content.clone())
+            .unwrap_or_default();
+        if content.trim().is_empty() {
+            let reason = choice
+                .and_then(|c| c.finish_reason.as_deref())
+                .map(|r| format!("finish reason: {}", r));
+            return Err(ProviderError::empty_response(self.config.display_name, reason.as_deref()));
+        }
+
+        Ok(ChatResult {
+            content,
+            usage: result.usage,
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        model: &str,
+        params: &GenerationParams,
+        tx: mpsc::Sender<StreamChunk>,
+    ) -> ProviderResult<()> {
+        let params = &with_model_max_tokens(self, self.config.name, model, params, true).await;
+        let request = self.build_request(messages, model, params, true)?;
+
+        let request_builder = self.request(self.client.post(self.chat_url(model)))
+            .header("Content-Type", "application/json")
+            .json(&request);
+
+        let response = send_with_retry(request_builder, MAX_RETRIES).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response(self.config.display_name, response).await);
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut decoder = SseDecoder::new();
+
+        while let Some(chunk_result) = stream.next().await {
+            let chunk = chunk_result?;
+
+            for data in decoder.push(&chunk) {
+                if send_stream_event(&data, &tx).await {
+                    return Ok(());
+                }
+            }
+        }
+        if let Some(data) = decoder.finish() {
+            if send_stream_event(&data, &tx).await {
+                return Ok(());
+            }
+        }
+
+        let _ = tx.send(StreamChunk { delta: String::new(), thinking: false, done: true }).await;
+        Ok(())
+    }
+
+    async fn list_models(&self) -> ProviderResult<Vec<ModelInfo>> {
+        if !self.fixed_models.is_empty() {
+            return Ok(self.fixed_models.clone());
+        }
+
+        match self.fetch_models().await {
+            Ok(models) if !models.is_empty() => Ok(models),
+            Ok(_) => Ok(self.fallback_models()),
+            Err(e) => {
+                eprintln!(
+                    "[{}] Failed to fetch models, using fallback list: {}",
+                    self.config.name.to_uppercase(), e
+                );
+                Ok(self.fallback_models())
+            }
+        }
+    }
+
+    async fn validate(&self) -> ProviderResult<()> {
+        let request_builder = self.request(self.client.get(self.url(self.config.validate_path)))
+            .timeout(self.timeouts.request);
+
+        // A single attempt: retries would only delay telling the user their key is wrong
+        let response = send_with_retry(request_builder, 0).await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::from_response(self.config.display_name, response).await);
+        }
+
+        Ok(())
+    }
+
+    fn count_tokens(&self, model: &str, messages: &[Message]) -> usize {
+        count_message_tokens(self.config.name, model, messages).0
+    }
+
+    fn context_window(&self, model: &str) -> usize {
+        context_window(self.config.name, model)
+    }
+}
+
+/// Forward the text of one streamed event. Returns true once the `[DONE]` marker
+/// has been passed on as the final chunk.
+async fn send_stream_event(data: &str, tx: &mpsc::Sender<StreamChunk>) -> bool {
+    if data == "[DONE]" {
+        let _ = tx.send(StreamChunk { delta: String::new(), thinking: false, done: true }).await;
+        return true;
+    }
+
+    if let Ok(response) = serde_json::from_str::<ChatResponse>(data) {
+        if let Some(content) = response.choices.first().and_then(|c| c.delta.as_ref()).and_then(|d| d.content.clone()) {
+            let _ = tx.send(StreamChunk { delta: content, thinking: false, done: false }).await;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{deepseek, openai, JSON_ONLY_INSTRUCTION};
+    use super::*;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message { role: "system".to_string(), content: "Be brief.".to_string(), images: Vec::new() },
+            Message { role: "user".to_string(), content: "List three colors.".to_string(), images: Vec::new() },
+        ]
+    }
+
+    fn json_params() -> GenerationParams {
+        GenerationParams { response_format: ResponseFormat::Json, ..Default::default() }
+    }
+
+    fn request_json(provider: &OpenAICompatibleProvider, model: &str, params: &GenerationParams) -> serde_json::Value {
+        let request = provider.build_request(messages(), model, params, false).unwrap();
+        serde_json::to_value(&request).unwrap()
+    }
+
+    #[test]
+    fn native_json_mode_sets_response_format() {
+        let provider = openai::provider("key".to_string(), None, Timeouts::default());
+        let request = request_json(&provider, "gpt-4o", &json_params());
+
+        assert_eq!(request["response_format"], serde_json::json!({ "type": "json_object" }));
+        assert_eq!(request["messages"][0]["content"], "Be brief.");
+    }
+
+    #[test]
+    fn instruction_json_mode_amends_system_message() {
+        let provider = deepseek::provider("key".to_string(), None, Timeouts::default());
+        let request = request_json(&provider, "deepseek-chat", &json_params());
+
+        assert!(request.get("response_format").is_none());
+        assert_eq!(
+            request["messages"][0]["content"],
+            format!("Be brief.\n\n{}", JSON_ONLY_INSTRUCTION)
+        );
+    }
+
+    #[test]
+    fn text_format_leaves_request_unchanged() {
+        let provider = openai::provider("key".to_string(), None, Timeouts::default());
+        let request = request_json(&provider, "gpt-4o", &GenerationParams::default());
+
+        assert!(request.get("response_format").is_none());
+        assert_eq!(request["messages"][0]["content"], "Be brief.");
+    }
+
+    #[tokio::test]
+    async fn final_event_without_blank_line_is_sent() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut decoder = SseDecoder::new();
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}",
+        );
+
+        for data in decoder.push(body.as_bytes()) {
+            assert!(!send_stream_event(&data, &tx).await);
+        }
+        let last = decoder.finish().unwrap();
+        assert!(!send_stream_event(&last, &tx).await);
+        drop(tx);
+
+        let mut deltas = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            deltas.push(chunk.delta);
+        }
+        assert_eq!(deltas, ["Hello", " world"]);
+    }
+}
@@ -0,0 +1,42 @@
+use super::openai_compatible::{CompatibleConfig, JsonMode, OpenAICompatibleProvider};
+use super::Timeouts;
+
+const BASE_URL: &str = "https://openrouter.ai/api/v1";
+
+/// Attribution headers OpenRouter uses to identify the calling app
+const ATTRIBUTION_HEADERS: &[(&str, &str)] = &[
+    ("HTTP-Referer", "https://github.com/radoslav1992/multy_chat"),
+    ("X-Title", "OmniChat"),
+];
+
+/// OpenRouter forwards `stop` to the underlying model; keep to the strictest common limit
+const MAX_STOP_SEQUENCES: usize = 4;
+
+const FALLBACK_MODELS: &[(&str, &str, u32)] = &[
+    ("openai/gpt-4o", "OpenAI: GPT-4o", 4096),
+    ("anthropic/claude-3.5-sonnet", "Anthropic: Claude 3.5 Sonnet", 8192),
+    ("meta-llama/llama-3.1-70b-instruct", "Meta: Llama 3.1 70B Instruct", 4096),
+];
+
+pub fn provider(api_key: String, timeouts: Timeouts) -> OpenAICompatibleProvider {
+    OpenAICompatibleProvider::new(
+        CompatibleConfig {
+            name: "openrouter",
+            display_name: "OpenRouter",
+            base_url: BASE_URL.to_string(),
+            extra_headers: ATTRIBUTION_HEADERS,
+            max_stop_sequences: MAX_STOP_SEQUENCES,
+            json_mode: JsonMode::ResponseFormat,
+            // Image support varies by routed model, so images are passed through and the
+            // upstream provider decides
+            supports_vision: |_| true,
+            is_chat_model: |_| true,
+            chat_path: "/chat/completions",
+            // The models catalog is public, so check the key against the key-info endpoint instead
+            validate_path: "/key",
+            fallback_models: FALLBACK_MODELS,
+        },
+        api_key,
+        timeouts,
+    )
+}
@@ -0,0 +1,127 @@
+/// Assembles server-sent events from a byte stream, yielding each event's data
+/// only once the whole event has arrived.
+///
+/// Bytes are buffered rather than decoded per network chunk, so a multi-byte
+/// character or a JSON object split across chunks is reassembled intact.
+/// Lines may end in LF or CRLF; comment lines (`:` keep-alives) and fields other
+/// than `data` are ignored, and multiple `data` lines are joined with newlines.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data_lines: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed a network chunk, returning the data of every event it completed
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        let mut consumed = 0;
+        while let Some(offset) = self.buffer[consumed..].iter().position(|&b| b == b'\n') {
+            let end = consumed + offset;
+            let line = self.buffer[consumed..end].strip_suffix(b"\r").unwrap_or(&self.buffer[consumed..end]);
+            let line = String::from_utf8_lossy(line).into_owned();
+            consumed = end + 1;
+
+            if let Some(event) = self.process_line(&line) {
+                events.push(event);
+            }
+        }
+        self.buffer.drain(..consumed);
+
+        events
+    }
+
+    /// Flush the final event when the stream ends without a trailing blank line
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        if !rest.is_empty() {
+            let line = String::from_utf8_lossy(rest.strip_suffix(b"\r").unwrap_or(&rest)).into_owned();
+            if let Some(event) = self.process_line(&line) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<String> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        if field == "data" {
+            self.data_lines.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+        None
+    }
+
+    /// End the current event, returning its data if it had any
+    fn dispatch(&mut self) -> Option<String> {
+        if self.data_lines.is_empty() {
+            return None;
+        }
+        Some(std::mem::take(&mut self.data_lines).join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_json_split_across_pushes() {
+        let mut decoder = SseDecoder::new();
+
+        assert!(decoder.push(b"data: {\"text\":\"caf").is_empty());
+        // The split falls inside the two-byte UTF-8 encoding of 'é'
+        assert!(decoder.push(&[0xC3]).is_empty());
+        assert!(decoder.push(&[0xA9]).is_empty());
+        assert_eq!(decoder.push(b"\"}\n\n"), vec!["{\"text\":\"café\"}"]);
+    }
+
+    #[test]
+    fn handles_crlf_line_endings() {
+        let mut decoder = SseDecoder::new();
+
+        let events = decoder.push(b"data: one\r\n\r\ndata: two\r\n\r\n");
+        assert_eq!(events, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn ignores_keep_alive_comments_and_other_fields() {
+        let mut decoder = SseDecoder::new();
+
+        assert!(decoder.push(b": keep-alive\n\n").is_empty());
+        let events = decoder.push(b"event: message\nid: 7\n: ping\ndata: hello\n\n");
+        assert_eq!(events, vec!["hello"]);
+    }
+
+    #[test]
+    fn joins_multi_line_data_fields() {
+        let mut decoder = SseDecoder::new();
+
+        let events = decoder.push(b"data: first\ndata:second\ndata: third\n\n");
+        assert_eq!(events, vec!["first\nsecond\nthird"]);
+    }
+
+    #[test]
+    fn finish_flushes_event_without_trailing_blank_line() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: [DONE]").is_empty());
+        assert_eq!(decoder.finish().as_deref(), Some("[DONE]"));
+
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: last\n").is_empty());
+        assert_eq!(decoder.finish().as_deref(), Some("last"));
+        assert_eq!(decoder.finish(), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::OnceLock;
+
+use tiktoken_rs::CoreBPE;
+
+use super::Message;
+
+/// Rough characters per token for providers without a bundled tokenizer
+const CHARS_PER_TOKEN: usize = 4;
+/// Formatting tokens the chat format adds around each message
+const TOKENS_PER_MESSAGE: usize = 4;
+/// Tokens that prime the assistant's reply
+const REPLY_PRIMING_TOKENS: usize = 3;
+/// Approximate cost of one attached image
+const TOKENS_PER_IMAGE: usize = 765;
+
+/// Context window assumed for models missing from the table
+const DEFAULT_CONTEXT_WINDOW: usize = 128_000;
+
+/// Known context windows as (provider, model id prefix, tokens).
+/// The longest matching prefix wins, and an empty prefix is the provider's default.
+const CONTEXT_WINDOWS: &[(&str, &str, usize)] = &[
+    ("openai", "", 128_000),
+    ("openai", "gpt-4.1", 1_047_576),
+    ("openai", "gpt-4-turbo", 128_000),
+    ("openai", "gpt-4", 8_192),
+    ("openai", "gpt-4o", 128_000),
+    ("openai", "gpt-3.5-turbo", 16_385),
+    ("openai", "o1", 200_000),
+    ("openai", "o3", 200_000),
+    ("openai", "o4", 200_000),
+    ("anthropic", "", 200_000),
+    ("gemini", "", 1_048_576),
+    ("gemini", "gemini-1.5-pro", 2_097_152),
+    ("deepseek", "", 64_000),
+    ("groq", "", 131_072),
+    ("groq", "mixtral-8x7b", 32_768),
+    ("groq", "gemma2", 8_192),
+];
+
+static O200K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+static CL100K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+
+/// Split an OpenRouter id like `openai/gpt-4o` into the provider name used here and the model
+fn resolve_model<'a>(provider: &'a str, model: &'a str) -> (&'a str, &'a str) {
+    if provider != "openrouter" {
+        return (provider, model);
+    }
+
+    match model.split_once('/') {
+        Some(("google", model)) => ("gemini", model),
+        Some((vendor, model)) => (vendor, model),
+        None => (provider, model),
+    }
+}
+
+/// OpenAI's tokenizer for the model, loaded once. Other providers don't publish theirs.
+fn openai_encoding(provider: &str, model: &str) -> Option<&'static CoreBPE> {
+    if provider != "openai" {
+        return None;
+    }
+
+    // Models before gpt-4o use cl100k_base
+    let legacy = model == "gpt-4" || model.starts_with("gpt-4-") || model.starts_with("gpt-3.5");
+    if legacy {
+        CL100K.get_or_init(|| tiktoken_rs::cl100k_base().ok()).as_ref()
+    } else {
+        O200K.get_or_init(|| tiktoken_rs::o200k_base().ok()).as_ref()
+    }
+}
+
+/// Estimate the prompt tokens a message array will use, without a network call.
+/// Returns the count and whether it came from the provider's real tokenizer
+/// rather than a length heuristic.
+pub fn count_message_tokens(provider: &str, model: &str, messages: &[Message]) -> (usize, bool) {
+    let provider = provider.to_lowercase();
+    let (provider, model) = resolve_model(&provider, model);
+    let encoding = openai_encoding(provider, model);
+
+    let count_text = |text: &str| match encoding {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => text.chars().count().div_ceil(CHARS_PER_TOKEN),
+    };
+
+    let tokens = messages
+        .iter()
+        .map(|m| {
+            TOKENS_PER_MESSAGE
+                + count_text(&m.role)
+                + count_text(&m.content)
+                + m.images.len() * TOKENS_PER_IMAGE
+        })
+        .sum::<usize>()
+        + REPLY_PRIMING_TOKENS;
+
+    (tokens, encoding.is_some())
+}
+
+/// Context window of a model in tokens, from a built-in table
+pub fn context_window(provider: &str, model: &str) -> usize {
+    let provider = provider.to_lowercase();
+    let (provider, model) = resolve_model(&provider, model);
+
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(p, prefix, _)| *p == provider && model.starts_with(prefix))
+        .max_by_key(|(_, prefix, _)| prefix.len())
+        .map(|(_, _, tokens)| *tokens)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use anyhow::Result;
+use instant_distance::{Builder, HnswMap, Point, Search};
+
+use super::dot_product;
+
+/// Buckets with fewer chunks are always scanned exactly; below this the scan is already fast
+pub const ANN_MIN_CHUNKS: usize = 5_000;
+
+/// Candidates kept per graph search, which caps how many results one query can return
+pub const ANN_MAX_RESULTS: usize = 256;
+
+/// Unit-length embedding, so cosine distance is one minus the dot product
+#[derive(Clone)]
+struct Embedding(Vec<f32>);
+
+impl Point for Embedding {
+    fn distance(&self, other: &Self) -> f32 {
+        1.0 - dot_product(&self.0, &other.0)
+    }
+}
+
+/// HNSW graph over one bucket's chunk embeddings, mapping back to chunk positions
+pub struct AnnIndex {
+    map: HnswMap<Embedding, usize>,
+    chunk_count: usize,
+}
+
+impl AnnIndex {
+    fn build(embeddings: Vec<Vec<f32>>) -> Self {
+        let chunk_count = embeddings.len();
+        let points = embeddings.into_iter().map(Embedding).collect();
+        let map = Builder::default()
+            .ef_search(ANN_MAX_RESULTS)
+            .build(points, (0..chunk_count).collect());
+
+        Self { map, chunk_count }
+    }
+
+    /// Number of chunks the index was built from
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_count
+    }
+
+    /// Up to `limit` nearest chunks as (chunk position, cosine similarity), best first
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(usize, f32)> {
+        let mut search = Search::default();
+        self.map
+            .search(&Embedding(query.to_vec()), &mut search)
+            .take(limit)
+            .map(|item| (*item.value, 1.0 - item.distance))
+            .collect()
+    }
+}
+
+enum IndexState {
+    /// A background build is running; the id tells it apart from builds started after an invalidation
+    Building(u64),
+    Ready(Arc<AnnIndex>),
+}
+
+/// Built indexes keyed by bucket directory, dropped whenever the chunk store is rewritten
+static INDEXES: OnceLock<Mutex<HashMap<PathBuf, IndexState>>> = OnceLock::new();
+
+static NEXT_BUILD_ID: AtomicU64 = AtomicU64::new(0);
+
+fn indexes() -> Result<std::sync::MutexGuard<'static, HashMap<PathBuf, IndexState>>> {
+    INDEXES
+        .get_or_init(Default::default)
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Search index cache lock poisoned"))
+}
+
+/// The bucket's index if it has been built. Otherwise starts building it in the
+/// background from `embeddings` and returns `None`, so the caller scans exactly meanwhile.
+/// Call with the bucket's store lock held, so the store can't change before the build
+/// is registered. A ready index over a different number of chunks than `chunk_count`
+/// is stale and gets rebuilt.
+pub fn get_or_build(
+    bucket_path: &Path,
+    chunk_count: usize,
+    embeddings: impl FnOnce() -> Vec<Vec<f32>>,
+) -> Result<Option<Arc<AnnIndex>>> {
+    let mut cache = indexes()?;
+
+    match cache.get(bucket_path) {
+        Some(IndexState::Ready(index)) if index.chunk_count() == chunk_count => {
+            return Ok(Some(index.clone()));
+        }
+        Some(IndexState::Ready(index)) => {
+            println!(
+                "[RAG] Search index for {:?} covers {} chunks but the store has {}, rebuilding",
+                bucket_path, index.chunk_count(), chunk_count
+            );
+        }
+        Some(IndexState::Building(_)) => return Ok(None),
+        None => {}
+    }
+
+    let build_id = NEXT_BUILD_ID.fetch_add(1, Ordering::Relaxed);
+    cache.insert(bucket_path.to_path_buf(), IndexState::Building(build_id));
+    drop(cache);
+
+    let bucket_path = bucket_path.to_path_buf();
+    let embeddings = embeddings();
+    tauri::async_runtime::spawn_blocking(move || {
+        println!("[RAG] Building search index over {} chunks in {:?}", embeddings.len(), bucket_path);
+        let built = std::panic::catch_unwind(AssertUnwindSafe(|| AnnIndex::build(embeddings)));
+
+        let Ok(mut cache) = indexes() else { return };
+        // The store may have changed while building; only keep the index if it wasn't invalidated
+        if !matches!(cache.get(&bucket_path), Some(IndexState::Building(id)) if *id == build_id) {
+            return;
+        }
+        match built {
+            Ok(index) => {
+                println!("[RAG] Search index ready for {:?}", bucket_path);
+                cache.insert(bucket_path, IndexState::Ready(Arc::new(index)));
+            }
+            // Forget the failed build so a later search can try again
+            Err(_) => {
+                eprintln!("[RAG] Building the search index for {:?} failed", bucket_path);
+                cache.remove(&bucket_path);
+            }
+        }
+    });
+
+    Ok(None)
+}
+
+/// Drop the bucket's index (or abandon its pending build) after its chunk store changes
+pub fn invalidate(bucket_path: &Path) -> Result<()> {
+    indexes()?.remove(bucket_path);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Deterministic unit vectors from a xorshift generator
+    pub(crate) fn random_unit_vectors(count: usize, dimensions: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+        };
+
+        (0..count)
+            .map(|_| {
+                let mut vector: Vec<f32> = (0..dimensions).map(|_| next()).collect();
+                let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+                vector.iter_mut().for_each(|x| *x /= norm);
+                vector
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ann_search_recalls_exact_neighbours() {
+        const K: usize = 10;
+        let embeddings = random_unit_vectors(500, 16, 0x9E37_79B9_7F4A_7C15);
+        let queries = random_unit_vectors(20, 16, 0xD1B5_4A32_D192_ED03);
+        let index = AnnIndex::build(embeddings.clone());
+        assert_eq!(index.chunk_count(), embeddings.len());
+
+        let mut found = 0;
+        for query in &queries {
+            let mut exact: Vec<(usize, f32)> = embeddings
+                .iter()
+                .enumerate()
+                .map(|(i, embedding)| (i, dot_product(query, embedding)))
+                .collect();
+            exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let exact: Vec<usize> = exact.into_iter().take(K).map(|(i, _)| i).collect();
+
+            let approximate = index.search(query, K);
+            assert_eq!(approximate.len(), K);
+            for (i, similarity) in &approximate {
+                assert!((similarity - dot_product(query, &embeddings[*i])).abs() < 1e-4);
+            }
+            found += approximate.iter().filter(|(i, _)| exact.contains(i)).count();
+        }
+
+        let recall = found as f32 / (queries.len() * K) as f32;
+        assert!(recall >= 0.9, "recall@{} was {}", K, recall);
+    }
+}
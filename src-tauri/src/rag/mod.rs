@@ -1,28 +1,210 @@
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::io::Read;
+use std::io::{Read, Seek};
+use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
 use tauri::AppHandle;
-use tauri::Manager;
+use tauri::{Emitter, Manager};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 use std::fs;
-use fastembed::{TextEmbedding, InitOptions, EmbeddingModel};
+use fastembed::{TextEmbedding, InitOptions, EmbeddingModel, TextRerank, RerankInitOptions, RerankerModel};
+use quick_xml::events::Event;
+use quick_xml::Reader;
 
-use crate::commands::knowledge::SearchResult;
+use crate::commands::knowledge::{Bucket, SearchResult};
+use crate::db;
+
+mod index;
 
 #[derive(Serialize, Deserialize, Clone)]
 struct Chunk {
     content: String,
     filename: String,
     embedding: Vec<f32>,
+    /// Model that produced `embedding`; unknown for chunks stored before it was recorded
+    #[serde(default)]
+    model: Option<EmbeddingModelChoice>,
+}
+
+/// Chunk layout of store versions 1 and 2, which didn't record the embedding model
+#[derive(Serialize, Deserialize)]
+struct UnstampedChunk {
+    content: String,
+    filename: String,
+    embedding: Vec<f32>,
+}
+
+impl From<UnstampedChunk> for Chunk {
+    fn from(chunk: UnstampedChunk) -> Self {
+        Chunk {
+            content: chunk.content,
+            filename: chunk.filename,
+            embedding: chunk.embedding,
+            model: None,
+        }
+    }
+}
+
+/// How a bucket splits documents into chunks before embedding
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkStrategy {
+    /// Fixed-size windows of words
+    #[default]
+    Words,
+    /// Whole sentences packed up to the chunk size
+    Sentences,
+    /// Fixed-size windows of tokens from the embedding model's own tokenizer
+    Tokens,
+}
+
+/// Where a file's indexed text came from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextSource {
+    /// The document's own text layer
+    #[default]
+    Native,
+    /// Recognized from rendered page images
+    Ocr,
+}
+
+/// Magic bytes at the start of every binary chunk store
+const CHUNKS_MAGIC: &[u8; 4] = b"OMCH";
+/// Binary chunk store format version, bumped on layout changes.
+/// Version 3 records each chunk's embedding model; version 2 stores load with the model
+/// unknown, and version 1 stores are also normalized on load.
+const CHUNKS_VERSION: u32 = 3;
+const UNNORMALIZED_CHUNKS_VERSION: u32 = 1;
+const CHUNKS_FILE: &str = "chunks.bin";
+const LEGACY_CHUNKS_FILE: &str = "chunks.json";
+
+/// Number of chunks embedded per model call during uploads
+const EMBEDDING_BATCH_SIZE: usize = 32;
+/// Texts passed to the model at once by `get_embeddings_local` unless told otherwise,
+/// so memory use doesn't grow with the size of the document
+const DEFAULT_EMBEDDING_MODEL_BATCH: usize = 64;
+
+/// Progress payload for the `embedding-progress` event
+#[derive(Clone, Serialize)]
+pub struct EmbeddingProgress {
+    pub bucket_id: String,
+    pub filename: String,
+    pub done_chunks: usize,
+    pub total_chunks: usize,
+}
+
+/// Minimum cosine similarity for a chunk to count as a search hit
+pub const DEFAULT_MIN_SCORE: f32 = 0.1;
+
+/// Local embedding model a bucket's chunks are embedded with
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum EmbeddingModelChoice {
+    #[default]
+    #[serde(rename = "all-minilm-l6-v2")]
+    AllMiniLmL6V2,
+    #[serde(rename = "all-minilm-l12-v2")]
+    AllMiniLmL12V2,
+    #[serde(rename = "bge-small-en-v1.5")]
+    BgeSmallEnV15,
+    #[serde(rename = "bge-base-en-v1.5")]
+    BgeBaseEnV15,
+    #[serde(rename = "bge-large-en-v1.5")]
+    BgeLargeEnV15,
+    #[serde(rename = "multilingual-e5-small")]
+    MultilingualE5Small,
+}
+
+/// Description of a selectable embedding model for the UI
+#[derive(Debug, Clone, Serialize)]
+pub struct EmbeddingModelInfo {
+    pub id: EmbeddingModelChoice,
+    pub name: &'static str,
+    pub dimensions: usize,
+    pub description: &'static str,
+}
+
+impl EmbeddingModelChoice {
+    pub const ALL: [EmbeddingModelChoice; 6] = [
+        EmbeddingModelChoice::AllMiniLmL6V2,
+        EmbeddingModelChoice::AllMiniLmL12V2,
+        EmbeddingModelChoice::BgeSmallEnV15,
+        EmbeddingModelChoice::BgeBaseEnV15,
+        EmbeddingModelChoice::BgeLargeEnV15,
+        EmbeddingModelChoice::MultilingualE5Small,
+    ];
+
+    fn fastembed_model(self) -> EmbeddingModel {
+        match self {
+            EmbeddingModelChoice::AllMiniLmL6V2 => EmbeddingModel::AllMiniLML6V2,
+            EmbeddingModelChoice::AllMiniLmL12V2 => EmbeddingModel::AllMiniLML12V2,
+            EmbeddingModelChoice::BgeSmallEnV15 => EmbeddingModel::BGESmallENV15,
+            EmbeddingModelChoice::BgeBaseEnV15 => EmbeddingModel::BGEBaseENV15,
+            EmbeddingModelChoice::BgeLargeEnV15 => EmbeddingModel::BGELargeENV15,
+            EmbeddingModelChoice::MultilingualE5Small => EmbeddingModel::MultilingualE5Small,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EmbeddingModelChoice::AllMiniLmL6V2 => "all-MiniLM-L6-v2",
+            EmbeddingModelChoice::AllMiniLmL12V2 => "all-MiniLM-L12-v2",
+            EmbeddingModelChoice::BgeSmallEnV15 => "bge-small-en-v1.5",
+            EmbeddingModelChoice::BgeBaseEnV15 => "bge-base-en-v1.5",
+            EmbeddingModelChoice::BgeLargeEnV15 => "bge-large-en-v1.5",
+            EmbeddingModelChoice::MultilingualE5Small => "multilingual-e5-small",
+        }
+    }
+
+    /// Length of the vectors the model produces
+    pub fn dimensions(self) -> usize {
+        match self {
+            EmbeddingModelChoice::AllMiniLmL6V2
+            | EmbeddingModelChoice::AllMiniLmL12V2
+            | EmbeddingModelChoice::BgeSmallEnV15
+            | EmbeddingModelChoice::MultilingualE5Small => 384,
+            EmbeddingModelChoice::BgeBaseEnV15 => 768,
+            EmbeddingModelChoice::BgeLargeEnV15 => 1024,
+        }
+    }
+
+    pub fn info(self) -> EmbeddingModelInfo {
+        let description = match self {
+            EmbeddingModelChoice::AllMiniLmL6V2 => "Fastest, smallest download (~23MB)",
+            EmbeddingModelChoice::AllMiniLmL12V2 => "Slightly better quality than L6 at about half the speed",
+            EmbeddingModelChoice::BgeSmallEnV15 => "Better English retrieval at a similar size to MiniLM",
+            EmbeddingModelChoice::BgeBaseEnV15 => "High quality English retrieval, slower (~110MB)",
+            EmbeddingModelChoice::BgeLargeEnV15 => "Best English quality, slowest and largest (~335MB)",
+            EmbeddingModelChoice::MultilingualE5Small => "Supports documents in many languages",
+        };
+
+        EmbeddingModelInfo {
+            id: self,
+            name: self.name(),
+            dimensions: self.dimensions(),
+            description,
+        }
+    }
 }
 
+/// Embedding models shared across uploads and searches, each loaded on first use
+static EMBEDDING_MODELS: OnceLock<Mutex<HashMap<EmbeddingModelChoice, &'static Mutex<TextEmbedding>>>> = OnceLock::new();
+
+/// Guards model creation so concurrent first calls don't load it twice
+static EMBEDDING_MODEL_INIT: Mutex<()> = Mutex::new(());
+
 /// Create an embedding model instance
-/// The model files are cached on disk after first download (~23MB)
-fn create_embedding_model(show_progress: bool) -> Result<TextEmbedding> {
-    println!("[RAG] Loading local embedding model (all-MiniLM-L6-v2)...");
+/// The model files are cached on disk after first download
+fn create_embedding_model(choice: EmbeddingModelChoice, show_progress: bool) -> Result<TextEmbedding> {
+    println!("[RAG] Loading local embedding model ({})...", choice.name());
     
     let model = TextEmbedding::try_new(
-        InitOptions::new(EmbeddingModel::AllMiniLML6V2)
+        InitOptions::new(choice.fastembed_model())
             .with_show_download_progress(show_progress)
     )?;
     
@@ -30,27 +212,416 @@ fn create_embedding_model(show_progress: bool) -> Result<TextEmbedding> {
     Ok(model)
 }
 
-fn get_bucket_path(app: &AppHandle, bucket_id: &str) -> PathBuf {
+fn cached_embedding_model(choice: EmbeddingModelChoice) -> Result<Option<&'static Mutex<TextEmbedding>>> {
+    let models = EMBEDDING_MODELS
+        .get_or_init(Default::default)
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Embedding model cache lock poisoned"))?;
+    Ok(models.get(&choice).copied())
+}
+
+/// Get the cached embedding model, loading it on first use
+fn embedding_model(choice: EmbeddingModelChoice, show_progress: bool) -> Result<&'static Mutex<TextEmbedding>> {
+    if let Some(model) = cached_embedding_model(choice)? {
+        return Ok(model);
+    }
+
+    let _init = EMBEDDING_MODEL_INIT
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Embedding model initialization lock poisoned"))?;
+
+    // Another caller may have finished loading while we waited for the lock
+    if let Some(model) = cached_embedding_model(choice)? {
+        return Ok(model);
+    }
+
+    // Models live for the rest of the process, so leaking gives a plain 'static reference
+    let model: &'static Mutex<TextEmbedding> = Box::leak(Box::new(Mutex::new(create_embedding_model(choice, show_progress)?)));
+    EMBEDDING_MODELS
+        .get_or_init(Default::default)
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Embedding model cache lock poisoned"))?
+        .insert(choice, model);
+    Ok(model)
+}
+
+/// Cross-encoder used to rerank search candidates, loaded on first use
+static RERANKER: OnceLock<Mutex<TextRerank>> = OnceLock::new();
+
+/// Guards reranker creation so concurrent first calls don't load it twice
+static RERANKER_INIT: Mutex<()> = Mutex::new(());
+
+/// How many cosine candidates per requested result are passed to the reranker
+const RERANK_CANDIDATE_MULTIPLIER: usize = 4;
+
+/// Get the cached reranker model, loading it on first use
+fn reranker() -> Result<&'static Mutex<TextRerank>> {
+    if let Some(model) = RERANKER.get() {
+        return Ok(model);
+    }
+
+    let _init = RERANKER_INIT
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Reranker initialization lock poisoned"))?;
+
+    if let Some(model) = RERANKER.get() {
+        return Ok(model);
+    }
+
+    println!("[RAG] Loading reranker model (bge-reranker-base)...");
+    let model = TextRerank::try_new(
+        RerankInitOptions::new(RerankerModel::BGERerankerBase)
+            .with_show_download_progress(true)
+    )?;
+    println!("[RAG] Reranker model loaded successfully!");
+
+    Ok(RERANKER.get_or_init(|| Mutex::new(model)))
+}
+
+/// Re-sort search results by cross-encoder relevance to the query, keeping the best `top_k`
+fn rerank_results(query: &str, results: Vec<SearchResult>, top_k: usize) -> Result<Vec<SearchResult>> {
+    if results.is_empty() {
+        return Ok(results);
+    }
+
+    let model = reranker()?
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Reranker lock poisoned"))?;
+
+    let documents: Vec<&str> = results.iter().map(|r| r.content.as_str()).collect();
+    let ranked = model.rerank(query, documents, false, None)?;
+
+    let mut results: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    Ok(ranked
+        .into_iter()
+        .filter_map(|r| {
+            let mut result = results.get_mut(r.index)?.take()?;
+            result.rerank_score = Some(r.score);
+            Some(result)
+        })
+        .take(top_k)
+        .collect())
+}
+
+/// Separator between chunks in an assembled context block
+const CONTEXT_SEPARATOR: &str = "\n\n---\n\n";
+
+/// Assemble a context block from ranked search results, greedily taking the best
+/// chunks whose formatted text still fits within `max_chars`. Returns the context
+/// and the results that made it in, in rank order.
+pub fn build_context(results: Vec<SearchResult>, max_chars: usize) -> (String, Vec<SearchResult>) {
+    let mut context = String::new();
+    let mut used_chars = 0;
+    let mut included = Vec::new();
+
+    for result in results {
+        let entry = format!(
+            "[Source: {}, Relevance: {:.1}%]\n{}",
+            result.filename.replace(char::is_control, " "),
+            result.score * 100.0,
+            result.content
+        );
+        let separator = if included.is_empty() { "" } else { CONTEXT_SEPARATOR };
+        let entry_chars = separator.len() + entry.chars().count();
+
+        // A lower-ranked chunk may still fit where this one didn't
+        if used_chars + entry_chars > max_chars {
+            continue;
+        }
+
+        context.push_str(separator);
+        context.push_str(&entry);
+        used_chars += entry_chars;
+        included.push(result);
+    }
+
+    (context, included)
+}
+
+/// Directory holding every bucket's chunk store
+pub fn buckets_dir(app: &AppHandle) -> PathBuf {
     let app_dir = app.path().app_data_dir().unwrap_or_else(|_| PathBuf::from("."));
-    app_dir.join("buckets").join(bucket_id)
+    app_dir.join("buckets")
+}
+
+fn get_bucket_path(app: &AppHandle, bucket_id: &str) -> PathBuf {
+    buckets_dir(app).join(bucket_id)
+}
+
+/// Per-bucket locks, so concurrent uploads, deletes and re-embeds of one bucket
+/// can't each load the store and overwrite the others' changes
+static STORE_LOCKS: OnceLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> = OnceLock::new();
+
+/// Take the bucket's store lock. Hold it from loading the chunks until they are saved;
+/// loading alone also needs it, since `load_chunks` may migrate the store in place.
+async fn lock_store(bucket_path: &Path) -> Result<tokio::sync::OwnedMutexGuard<()>> {
+    let lock = STORE_LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Chunk store lock table poisoned"))?
+        .entry(bucket_path.to_path_buf())
+        .or_default()
+        .clone();
+    Ok(lock.lock_owned().await)
+}
+
+/// The embedding model the bucket is currently set to
+async fn bucket_model(app: &AppHandle, bucket_id: &str) -> Result<EmbeddingModelChoice> {
+    Ok(db::get_bucket(app, bucket_id).await?.embedding_model)
+}
+
+/// Read the bucket's chunk store, migrating a legacy `chunks.json` on first access.
+/// Chunks stored before the embedding model was recorded are stamped with `model`,
+/// the bucket's current one, and written back.
+fn load_chunks(bucket_path: &Path, model: EmbeddingModelChoice) -> Result<Vec<Chunk>> {
+    let chunks_file = bucket_path.join(CHUNKS_FILE);
+
+    if !chunks_file.exists() {
+        let legacy_file = bucket_path.join(LEGACY_CHUNKS_FILE);
+        if !legacy_file.exists() {
+            return Ok(Vec::new());
+        }
+
+        println!("[RAG] Migrating {:?} to binary chunk store", legacy_file);
+        let content = fs::read_to_string(&legacy_file)?;
+        let (mut chunks, parsed): (Vec<Chunk>, bool) = match serde_json::from_str(&content) {
+            Ok(chunks) => (chunks, true),
+            Err(e) => {
+                eprintln!("[RAG] Corrupt legacy chunk store {:?}, starting empty: {}", legacy_file, e);
+                back_up_corrupt_store(&legacy_file)?;
+                (Vec::new(), false)
+            }
+        };
+        normalize_chunks(&mut chunks);
+        stamp_unrecorded_chunks(&mut chunks, model);
+        save_chunks(bucket_path, &chunks)?;
+        // A corrupt legacy store has already been moved aside
+        if parsed {
+            fs::remove_file(&legacy_file)?;
+        }
+        return Ok(chunks);
+    }
+
+    let bytes = fs::read(&chunks_file)?;
+    let (mut chunks, upgraded) = match decode_chunks(&bytes) {
+        Ok(decoded) => decoded,
+        // A store written by a newer version isn't corrupt, so leave it untouched
+        Err(e) if store_version(&bytes).is_some_and(|v| v > CHUNKS_VERSION) => {
+            return Err(anyhow::anyhow!("{} in {:?}", e, chunks_file));
+        }
+        Err(e) => {
+            let mut chunks = recover_leading_chunks(&bytes);
+            stamp_unrecorded_chunks(&mut chunks, model);
+            eprintln!(
+                "[RAG] Corrupt chunk store {:?} ({}), recovered {} chunks",
+                chunks_file, e, chunks.len()
+            );
+            back_up_corrupt_store(&chunks_file)?;
+            save_chunks(bucket_path, &chunks)?;
+            return Ok(chunks);
+        }
+    };
+
+    let stamped = stamp_unrecorded_chunks(&mut chunks, model);
+    if upgraded || stamped {
+        println!("[RAG] Upgraded chunk store {:?} to version {}", chunks_file, CHUNKS_VERSION);
+        save_chunks(bucket_path, &chunks)?;
+    }
+
+    Ok(chunks)
+}
+
+/// Format version from a binary chunk store's header, if the header is intact
+fn store_version(bytes: &[u8]) -> Option<u32> {
+    if bytes.len() < 8 || &bytes[..4] != CHUNKS_MAGIC {
+        return None;
+    }
+    Some(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]))
+}
+
+/// Decode a binary chunk store, upgrading older versions to the current layout.
+/// The flag is true when the chunks were upgraded and should be written back.
+fn decode_chunks(bytes: &[u8]) -> Result<(Vec<Chunk>, bool)> {
+    let version = store_version(bytes)
+        .ok_or_else(|| anyhow::anyhow!("Invalid chunk store header"))?;
+    if !(UNNORMALIZED_CHUNKS_VERSION..=CHUNKS_VERSION).contains(&version) {
+        return Err(anyhow::anyhow!("Unsupported chunk store version: {}", version));
+    }
+
+    let mut chunks: Vec<Chunk> = if version == CHUNKS_VERSION {
+        bincode::deserialize(&bytes[8..])?
+    } else {
+        bincode::deserialize::<Vec<UnstampedChunk>>(&bytes[8..])?
+            .into_iter()
+            .map(Chunk::from)
+            .collect()
+    };
+
+    if version == UNNORMALIZED_CHUNKS_VERSION {
+        normalize_chunks(&mut chunks);
+    }
+
+    Ok((chunks, version != CHUNKS_VERSION))
+}
+
+/// Decode one bincode value from the front of `bytes`, with the number of bytes it used
+fn read_leading<T: Serialize + DeserializeOwned>(bytes: &[u8]) -> Option<(T, usize)> {
+    let value: T = bincode::deserialize(bytes).ok()?;
+    let size = bincode::serialized_size(&value).ok()?;
+    Some((value, size as usize))
+}
+
+/// Salvage the chunks that still decode from the start of a damaged store,
+/// such as one cut short by an interrupted write
+fn recover_leading_chunks(bytes: &[u8]) -> Vec<Chunk> {
+    let Some(version) = store_version(bytes) else {
+        return Vec::new();
+    };
+    // Header, then bincode's u64 element count ahead of the chunks themselves
+    let Some(mut rest) = bytes.get(16..) else {
+        return Vec::new();
+    };
+
+    let mut chunks = Vec::new();
+    loop {
+        let next = if version == CHUNKS_VERSION {
+            read_leading::<Chunk>(rest)
+        } else {
+            read_leading::<UnstampedChunk>(rest).map(|(chunk, size)| (Chunk::from(chunk), size))
+        };
+        let Some((chunk, size)) = next else { break };
+        rest = &rest[size..];
+        chunks.push(chunk);
+    }
+
+    if version == UNNORMALIZED_CHUNKS_VERSION {
+        normalize_chunks(&mut chunks);
+    }
+    chunks
+}
+
+/// Move an unreadable chunk store aside to `<file>.corrupt` so it can be inspected
+/// and isn't lost when the bucket is next written
+fn back_up_corrupt_store(file: &Path) -> Result<()> {
+    let mut backup = file.as_os_str().to_owned();
+    backup.push(".corrupt");
+    fs::rename(file, &backup)?;
+    println!("[RAG] Backed up corrupt chunk store to {:?}", backup);
+    Ok(())
+}
+
+/// Scale a vector to unit length so cosine similarity reduces to a dot product
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn normalize_chunks(chunks: &mut [Chunk]) {
+    for chunk in chunks {
+        normalize(&mut chunk.embedding);
+    }
+}
+
+/// Record `model` on chunks that don't say which model embedded them, so a later
+/// switch between models of the same dimension is still caught. Returns whether any
+/// chunk was stamped.
+fn stamp_unrecorded_chunks(chunks: &mut [Chunk], model: EmbeddingModelChoice) -> bool {
+    let mut stamped = false;
+    for chunk in chunks.iter_mut().filter(|c| c.model.is_none()) {
+        chunk.model = Some(model);
+        stamped = true;
+    }
+    stamped
+}
+
+/// Encode chunks as a small header followed by bincode-encoded chunks
+fn encode_chunks(chunks: &[Chunk]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(8);
+    bytes.extend_from_slice(CHUNKS_MAGIC);
+    bytes.extend_from_slice(&CHUNKS_VERSION.to_le_bytes());
+    bytes.extend(bincode::serialize(chunks)?);
+    Ok(bytes)
+}
+
+/// Write the bucket's chunk store, dropping any search index built from the old one
+fn save_chunks(bucket_path: &Path, chunks: &[Chunk]) -> Result<()> {
+    // Write to a temp file first so a crash mid-write never truncates the real store
+    let chunks_file = bucket_path.join(CHUNKS_FILE);
+    let temp_file = chunks_file.with_extension("bin.tmp");
+    fs::write(&temp_file, encode_chunks(chunks)?)?;
+    fs::rename(&temp_file, &chunks_file)?;
+    index::invalidate(bucket_path)?;
+    Ok(())
+}
+
+/// Drop the cached search index of a bucket whose chunk store was replaced on disk
+/// without going through this module, such as by restoring a backup
+pub fn invalidate_search_index(bucket_path: &Path) -> Result<()> {
+    index::invalidate(bucket_path)
+}
+
+/// The bucket's chunk store in the current binary format, for bundling into an export
+pub async fn export_chunk_store(app: &AppHandle, bucket_id: &str) -> Result<Vec<u8>> {
+    let bucket_path = get_bucket_path(app, bucket_id);
+    let model = bucket_model(app, bucket_id).await?;
+    let _guard = lock_store(&bucket_path).await?;
+    let chunks = load_chunks(&bucket_path, model)?;
+    encode_chunks(&chunks)
+}
+
+/// Restore an exported chunk store into a new bucket without re-embedding.
+/// Rejects stores whose vectors don't match the dimension of the bucket's model.
+/// Returns the number of chunks restored.
+pub async fn import_chunk_store(
+    app: &AppHandle,
+    bucket_id: &str,
+    bytes: &[u8],
+    model: EmbeddingModelChoice,
+) -> Result<usize> {
+    let (mut chunks, _) = decode_chunks(bytes)?;
+
+    if let Some(other) = chunks.iter().find_map(|c| c.model.filter(|m| *m != model)) {
+        return Err(anyhow::anyhow!(
+            "Chunk store was embedded with {} but the bucket uses {}",
+            other.name(), model.name()
+        ));
+    }
+    if let Some(chunk) = chunks.iter().find(|c| c.embedding.len() != model.dimensions()) {
+        return Err(anyhow::anyhow!(
+            "Chunk store has {}-dimension embeddings but {} produces {}",
+            chunk.embedding.len(), model.name(), model.dimensions()
+        ));
+    }
+    stamp_unrecorded_chunks(&mut chunks, model);
+
+    let bucket_path = get_bucket_path(app, bucket_id);
+    let _guard = lock_store(&bucket_path).await?;
+    fs::create_dir_all(&bucket_path)?;
+    save_chunks(&bucket_path, &chunks)?;
+
+    Ok(chunks.len())
 }
 
 pub async fn init_bucket_store(app: &AppHandle, bucket_id: &str) -> Result<()> {
     let bucket_path = get_bucket_path(app, bucket_id);
+    let _guard = lock_store(&bucket_path).await?;
     fs::create_dir_all(&bucket_path)?;
     
     // Create empty chunks file
-    let chunks_file = bucket_path.join("chunks.json");
-    fs::write(chunks_file, "[]")?;
+    save_chunks(&bucket_path, &[])?;
     
     Ok(())
 }
 
 pub async fn delete_bucket_store(app: &AppHandle, bucket_id: &str) -> Result<()> {
     let bucket_path = get_bucket_path(app, bucket_id);
+    let _guard = lock_store(&bucket_path).await?;
     if bucket_path.exists() {
-        fs::remove_dir_all(bucket_path)?;
+        fs::remove_dir_all(&bucket_path)?;
     }
+    index::invalidate(&bucket_path)?;
     Ok(())
 }
 
@@ -64,42 +635,569 @@ pub fn parse_file(path: &Path, file_type: &str) -> Result<String> {
             Ok(text)
         }
         "docx" => {
-            // For docx, we'll do basic XML parsing
-            let file = fs::File::open(path)?;
-            let mut archive = zip::ZipArchive::new(file)
-                .map_err(|e| anyhow::anyhow!("Failed to open docx: {}", e))?;
-            
-            let mut text = String::new();
-            if let Ok(mut document) = archive.by_name("word/document.xml") {
-                let mut content = String::new();
-                document.read_to_string(&mut content)?;
-                
-                // Simple extraction - remove XML tags and get text
-                let mut in_tag = false;
-                let mut result = String::new();
-                for c in content.chars() {
-                    match c {
-                        '<' => in_tag = true,
-                        '>' => {
-                            in_tag = false;
-                            if result.ends_with("w:p") || result.ends_with("w:br") {
-                                text.push('\n');
-                            }
-                            result.clear();
+            let mut archive = open_office_archive(path)?;
+            let xml = read_archive_part(&mut archive, "word/document.xml")?
+                .ok_or_else(|| anyhow::anyhow!("Not a Word document: word/document.xml is missing"))?;
+            docx_text(&xml)
+        }
+        "pptx" => {
+            let mut archive = open_office_archive(path)?;
+            pptx_to_text(&mut archive)
+        }
+        "xlsx" => {
+            let mut archive = open_office_archive(path)?;
+            xlsx_to_text(&mut archive)
+        }
+        "txt" | "md" => {
+            let content = fs::read_to_string(path)?;
+            Ok(content)
+        }
+        "csv" => {
+            let bytes = fs::read(path)?;
+            Ok(csv_to_text(&bytes))
+        }
+        "html" => {
+            let bytes = fs::read(path)?;
+            Ok(html_to_text(&String::from_utf8_lossy(&bytes)))
+        }
+        _ => Err(anyhow::anyhow!("Unsupported file type: {}", file_type)),
+    }
+}
+
+/// Below this many non-whitespace characters a PDF is treated as scanned
+const OCR_MIN_TEXT_CHARS: usize = 100;
+/// Resolution pages are rendered at for OCR
+const OCR_DPI: &str = "300";
+
+/// Whether extracted PDF text is too sparse to be a real text layer
+pub fn needs_ocr(text: &str) -> bool {
+    text.chars().filter(|c| !c.is_whitespace()).count() < OCR_MIN_TEXT_CHARS
+}
+
+/// Recover text from an image-only PDF by rendering its pages with poppler's `pdftoppm`
+/// and running `tesseract` over each page. Both must be installed and on the PATH.
+pub fn ocr_pdf(path: &Path) -> Result<String> {
+    let work_dir = std::env::temp_dir().join(format!("omnichat-ocr-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&work_dir)?;
+    let result = ocr_pdf_pages(path, &work_dir);
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn ocr_pdf_pages(path: &Path, work_dir: &Path) -> Result<String> {
+    println!("[RAG] Rendering PDF pages for OCR...");
+    let output = Command::new("pdftoppm")
+        .args(["-r", OCR_DPI, "-png"])
+        .arg(path)
+        .arg(work_dir.join("page"))
+        .output()
+        .map_err(|e| anyhow::anyhow!("OCR requires pdftoppm (poppler-utils) to be installed: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to render PDF pages: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // pdftoppm zero-pads page numbers, so name order is page order
+    let mut pages: Vec<PathBuf> = fs::read_dir(work_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    pages.sort();
+
+    let mut text = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        println!("[RAG] OCR page {}/{}", i + 1, pages.len());
+        let output = Command::new("tesseract")
+            .arg(page)
+            .arg("stdout")
+            .output()
+            .map_err(|e| anyhow::anyhow!("OCR requires tesseract to be installed: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "OCR failed on page {}: {}",
+                i + 1,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        text.push_str(&String::from_utf8_lossy(&output.stdout));
+        text.push('\n');
+    }
+
+    println!("[RAG] OCR complete, got {} bytes from {} pages", text.len(), pages.len());
+    Ok(text)
+}
+
+/// Extract text from a fetched web resource, picking the parser from its content type
+pub fn parse_web_content(bytes: &[u8], content_type: &str) -> Result<String> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    match mime.as_str() {
+        "text/html" | "application/xhtml+xml" => Ok(html_to_text(&String::from_utf8_lossy(bytes))),
+        "application/pdf" => pdf_extract::extract_text_from_mem(bytes)
+            .map_err(|e| anyhow::anyhow!("PDF extraction error: {}", e)),
+        "text/csv" => Ok(csv_to_text(bytes)),
+        "application/json" => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        _ if mime.starts_with("text/") => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        // Servers that omit the header are usually serving a page
+        "" => Ok(html_to_text(&String::from_utf8_lossy(bytes))),
+        _ => Err(anyhow::anyhow!("Unsupported content type: {}", mime)),
+    }
+}
+
+/// Encrypted Office files are OLE compound documents rather than zip archives
+const OLE_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+const OFFICE_PASSWORD_ERROR: &str =
+    "This document is password-protected. Remove the password and upload it again.";
+
+fn open_office_archive(path: &Path) -> Result<zip::ZipArchive<fs::File>> {
+    let mut file = fs::File::open(path)?;
+    let mut magic = [0u8; 8];
+    if file.read_exact(&mut magic).is_ok() && magic == OLE_MAGIC {
+        return Err(anyhow::anyhow!(
+            "This document is password-protected or in a legacy binary format (.doc, .ppt, .xls). \
+            Remove the password or re-save it as .docx, .pptx or .xlsx."
+        ));
+    }
+    file.rewind()?;
+
+    zip::ZipArchive::new(file)
+        .map_err(|e| anyhow::anyhow!("Failed to open Office document: {}", e))
+}
+
+/// Read a part of an Office archive as text, `None` if the part doesn't exist
+fn read_archive_part(archive: &mut zip::ZipArchive<fs::File>, name: &str) -> Result<Option<String>> {
+    match archive.by_name(name) {
+        Ok(mut part) => {
+            let mut content = String::new();
+            part.read_to_string(&mut content)?;
+            Ok(Some(content))
+        }
+        Err(zip::result::ZipError::FileNotFound) => Ok(None),
+        Err(zip::result::ZipError::UnsupportedArchive(msg)) if msg == zip::result::ZipError::PASSWORD_REQUIRED => {
+            Err(anyhow::anyhow!(OFFICE_PASSWORD_ERROR))
+        }
+        Err(e) => Err(anyhow::anyhow!("Failed to read {}: {}", name, e)),
+    }
+}
+
+/// Archive parts named `{prefix}N.xml`, in numeric order
+fn numbered_parts(archive: &zip::ZipArchive<fs::File>, prefix: &str) -> Vec<(u32, String)> {
+    let mut parts: Vec<(u32, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name.strip_prefix(prefix)?.strip_suffix(".xml")?.parse().ok()?;
+            Some((number, name.to_string()))
+        })
+        .collect();
+    parts.sort();
+    parts
+}
+
+/// Text carried by a text, CDATA or entity reference event
+fn xml_event_text(event: &Event) -> Result<Option<String>> {
+    Ok(match event {
+        Event::Text(e) => Some(e.decode()?.into_owned()),
+        Event::CData(e) => Some(e.decode()?.into_owned()),
+        Event::GeneralRef(e) => match e.resolve_char_ref()? {
+            Some(c) => Some(c.to_string()),
+            None => quick_xml::escape::resolve_predefined_entity(&e.decode()?).map(str::to_string),
+        },
+        _ => None,
+    })
+}
+
+/// Body text of a Word document: a line per paragraph, list items as indented
+/// `- ` bullets and table rows as tab-separated cells
+fn docx_text(xml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let mut in_run_text = false;
+    // Tab stop definitions (`w:tabs/w:tab`) share the name of literal tabs
+    let mut in_tab_stops = false;
+    let mut list_level: Option<usize> = None;
+    let mut cell_depth = 0usize;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"t" => in_run_text = true,
+                b"tabs" => in_tab_stops = true,
+                b"numPr" => list_level = Some(0),
+                b"tc" => cell_depth += 1,
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"tab" if !in_tab_stops => text.push('\t'),
+                b"br" | b"cr" => text.push(if cell_depth > 0 { ' ' } else { '\n' }),
+                b"ilvl" if list_level.is_some() => {
+                    list_level = e.try_get_attribute("w:val")?
+                        .and_then(|attr| attr.unescape_value().ok()?.parse().ok())
+                        .or(list_level);
+                }
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_run_text = false,
+                b"tabs" => in_tab_stops = false,
+                // Paragraph properties come before the runs, so the bullet goes here
+                b"pPr" => {
+                    if let Some(level) = list_level.take() {
+                        text.push_str(&"  ".repeat(level));
+                        text.push_str("- ");
+                    }
+                }
+                // Paragraphs inside a cell stay on the row's line
+                b"p" => text.push(if cell_depth > 0 { ' ' } else { '\n' }),
+                b"tc" => {
+                    while text.ends_with(' ') {
+                        text.pop();
+                    }
+                    text.push('\t');
+                    cell_depth = cell_depth.saturating_sub(1);
+                }
+                b"tr" => {
+                    if text.ends_with('\t') {
+                        text.pop();
+                    }
+                    text.push('\n');
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ if in_run_text => {
+                if let Some(run) = xml_event_text(&event)? {
+                    text.push_str(&run);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// Flatten slides into text, one blank-line separated block per slide
+fn pptx_to_text(archive: &mut zip::ZipArchive<fs::File>) -> Result<String> {
+    let mut slides = Vec::new();
+    for (number, name) in numbered_parts(archive, "ppt/slides/slide") {
+        let Some(xml) = read_archive_part(archive, &name)? else { continue };
+        let text = slide_text(&xml)?;
+        if !text.trim().is_empty() {
+            slides.push(format!("Slide {}:\n{}", number, text.trim_end()));
+        }
+    }
+    Ok(slides.join("\n\n"))
+}
+
+/// Text runs (`a:t`) of a slide with a line per paragraph (`a:p`)
+fn slide_text(xml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let mut in_run = false;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"t" => in_run = true,
+                b"br" => text.push('\n'),
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"br" => text.push('\n'),
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_run = false,
+                b"p" => text.push('\n'),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ if in_run => {
+                if let Some(run) = xml_event_text(&event)? {
+                    text.push_str(&run);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(text)
+}
+
+/// Flatten worksheets into tab-separated rows, one blank-line separated block per sheet
+fn xlsx_to_text(archive: &mut zip::ZipArchive<fs::File>) -> Result<String> {
+    let shared_strings = match read_archive_part(archive, "xl/sharedStrings.xml")? {
+        Some(xml) => xlsx_shared_strings(&xml)?,
+        None => Vec::new(),
+    };
+
+    let mut sheets = Vec::new();
+    for (name, part) in xlsx_sheets(archive)? {
+        let Some(xml) = read_archive_part(archive, &part)? else { continue };
+        let rows = xlsx_sheet_rows(&xml, &shared_strings)?;
+        if !rows.is_empty() {
+            sheets.push(format!("Sheet: {}\n{}", name, rows.join("\n")));
+        }
+    }
+    Ok(sheets.join("\n\n"))
+}
+
+/// Sheet names and their archive parts in workbook order, falling back to
+/// numbered worksheet parts when the workbook can't be resolved
+fn xlsx_sheets(archive: &mut zip::ZipArchive<fs::File>) -> Result<Vec<(String, String)>> {
+    let workbook = read_archive_part(archive, "xl/workbook.xml")?;
+    let rels = read_archive_part(archive, "xl/_rels/workbook.xml.rels")?;
+
+    if let (Some(workbook), Some(rels)) = (workbook, rels) {
+        // Relationship id -> part path
+        let mut targets = HashMap::new();
+        let mut reader = Reader::from_str(&rels);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                    if let (Some(id), Some(target)) = (e.try_get_attribute("Id")?, e.try_get_attribute("Target")?) {
+                        let target = target.unescape_value()?;
+                        let path = match target.strip_prefix('/') {
+                            Some(absolute) => absolute.to_string(),
+                            None => format!("xl/{}", target),
+                        };
+                        targets.insert(id.unescape_value()?.into_owned(), path);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let mut sheets = Vec::new();
+        let mut reader = Reader::from_str(&workbook);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+                    if let (Some(name), Some(id)) = (e.try_get_attribute("name")?, e.try_get_attribute("r:id")?) {
+                        if let Some(path) = targets.get(id.unescape_value()?.as_ref()) {
+                            sheets.push((name.unescape_value()?.into_owned(), path.clone()));
                         }
-                        _ if !in_tag => text.push(c),
-                        _ => result.push(c),
                     }
                 }
+                Event::Eof => break,
+                _ => {}
             }
-            Ok(text)
         }
-        "txt" | "md" => {
-            let content = fs::read_to_string(path)?;
-            Ok(content)
+
+        if !sheets.is_empty() {
+            return Ok(sheets);
+        }
+    }
+
+    Ok(numbered_parts(archive, "xl/worksheets/sheet")
+        .into_iter()
+        .map(|(number, part)| (format!("Sheet{}", number), part))
+        .collect())
+}
+
+/// The workbook's shared string table; rich-text runs are joined and phonetic hints skipped
+fn xlsx_shared_strings(xml: &str) -> Result<Vec<String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+    let mut in_phonetic = false;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"si" => current.clear(),
+                b"t" => in_text = true,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"si" => strings.push(std::mem::take(&mut current)),
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"si" => strings.push(String::new()),
+            Event::Eof => break,
+            _ if in_text && !in_phonetic => {
+                if let Some(text) = xml_event_text(&event)? {
+                    current.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(strings)
+}
+
+/// Non-empty rows of a worksheet with cells joined by tabs. Formula cells use their cached value.
+fn xlsx_sheet_rows(xml: &str, shared_strings: &[String]) -> Result<Vec<String>> {
+    let mut reader = Reader::from_str(xml);
+    let mut rows = Vec::new();
+    let mut cells: Vec<String> = Vec::new();
+    let mut cell_type = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"c" => {
+                    cell_type = match e.try_get_attribute("t")? {
+                        Some(attr) => attr.unescape_value()?.into_owned(),
+                        None => String::new(),
+                    };
+                    value.clear();
+                }
+                // `v` holds the value, `t` the text of an inline string
+                b"v" | b"t" => in_value = true,
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"v" | b"t" => in_value = false,
+                b"c" => {
+                    let text = match cell_type.as_str() {
+                        "s" => value.trim().parse::<usize>().ok()
+                            .and_then(|i| shared_strings.get(i).cloned())
+                            .unwrap_or_default(),
+                        "b" => if value.trim() == "1" { "TRUE".to_string() } else { "FALSE".to_string() },
+                        _ => std::mem::take(&mut value),
+                    };
+                    let text = text.trim();
+                    if !text.is_empty() {
+                        cells.push(text.to_string());
+                    }
+                }
+                b"row" if !cells.is_empty() => {
+                    rows.push(cells.join("\t"));
+                    cells.clear();
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ if in_value => {
+                if let Some(text) = xml_event_text(&event)? {
+                    value.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Flatten CSV rows into `header: value` lines, one blank-line separated block per record
+fn csv_to_text(bytes: &[u8]) -> String {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(bytes);
+
+    let headers: Vec<String> = match reader.headers() {
+        Ok(headers) => headers.iter().map(|h| h.trim().to_string()).collect(),
+        Err(_) => return String::from_utf8_lossy(bytes).into_owned(),
+    };
+
+    let mut records = Vec::new();
+    for record in reader.records() {
+        // Skip malformed rows rather than failing the whole file
+        let Ok(record) = record else { continue };
+
+        let lines: Vec<String> = record
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| !value.trim().is_empty())
+            .map(|(i, value)| match headers.get(i).filter(|h| !h.is_empty()) {
+                Some(header) => format!("{}: {}", header, value.trim()),
+                None => format!("column {}: {}", i + 1, value.trim()),
+            })
+            .collect();
+
+        if !lines.is_empty() {
+            records.push(lines.join("\n"));
+        }
+    }
+
+    records.join("\n\n")
+}
+
+/// Strip tags, scripts and styles from HTML, keeping readable text
+fn html_to_text(html: &str) -> String {
+    const BLOCK_TAGS: &[&str] = &[
+        "p", "div", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6",
+        "section", "article", "header", "footer", "table", "ul", "ol", "blockquote", "pre",
+    ];
+
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::new();
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let Some(offset) = html[pos..].find('<') else {
+            text.push_str(&html[pos..]);
+            break;
+        };
+        text.push_str(&html[pos..pos + offset]);
+        let tag_start = pos + offset;
+
+        // Unterminated tag: keep the remainder as text
+        let Some(tag_len) = html[tag_start..].find('>') else {
+            text.push_str(&html[tag_start..]);
+            break;
+        };
+        let tag_end = tag_start + tag_len + 1;
+
+        if html[tag_start..].starts_with("<!--") {
+            pos = lower[tag_start..]
+                .find("-->")
+                .map(|i| tag_start + i + 3)
+                .unwrap_or(html.len());
+            continue;
         }
-        _ => Err(anyhow::anyhow!("Unsupported file type: {}", file_type)),
+
+        let name: String = lower[tag_start + 1..tag_end - 1]
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+
+        if name == "script" || name == "style" {
+            let closing = format!("</{}", name);
+            pos = lower[tag_end..]
+                .find(&closing)
+                .and_then(|i| lower[tag_end + i..].find('>').map(|j| tag_end + i + j + 1))
+                .unwrap_or(html.len());
+            continue;
+        }
+
+        text.push(if BLOCK_TAGS.contains(&name.as_str()) { '\n' } else { ' ' });
+        pos = tag_end;
     }
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
@@ -128,67 +1226,393 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
     chunks
 }
 
-/// Generate embeddings using local model (no API key required)
-fn get_embeddings_local(texts: &[String], show_progress: bool) -> Result<Vec<Vec<f32>>> {
+/// Special tokens (e.g. `[CLS]` and `[SEP]`) the model adds around every input
+const SPECIAL_TOKEN_ALLOWANCE: usize = 2;
+
+/// Split text into windows of `chunk_size` tokens, overlapping by `overlap` tokens, using
+/// the embedding model's tokenizer. `chunk_size` is capped so every chunk fits within the
+/// model's maximum sequence length.
+pub fn chunk_text_tokens(
+    text: &str,
+    model: EmbeddingModelChoice,
+    chunk_size: usize,
+    overlap: usize,
+) -> Result<Vec<String>> {
+    let mut tokenizer = embedding_model(model, true)?
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Embedding model lock poisoned"))?
+        .tokenizer
+        .clone();
+
+    let max_tokens = tokenizer
+        .get_truncation()
+        .map(|t| t.max_length.saturating_sub(SPECIAL_TOKEN_ALLOWANCE))
+        .unwrap_or(usize::MAX);
+    let chunk_size = chunk_size.min(max_tokens).max(1);
+    let overlap = overlap.min(chunk_size - 1);
+
+    // The model's tokenizer truncates to the sequence limit; the whole document is needed here
+    tokenizer
+        .with_truncation(None)
+        .map_err(|e| anyhow::anyhow!("Failed to configure tokenizer: {}", e))?;
+    let encoding = tokenizer
+        .encode(text, false)
+        .map_err(|e| anyhow::anyhow!("Failed to tokenize text: {}", e))?;
+    let offsets = encoding.get_offsets();
+
+    let mut chunks = Vec::new();
+    let mut i = 0;
+    while i < offsets.len() {
+        let end = (i + chunk_size).min(offsets.len());
+        // Offsets are byte ranges into the original text, so chunks keep its exact formatting
+        if let Some(chunk) = text.get(offsets[i].0..offsets[end - 1].1) {
+            let chunk = chunk.trim();
+            if !chunk.is_empty() {
+                chunks.push(chunk.to_string());
+            }
+        }
+
+        if end >= offsets.len() {
+            break;
+        }
+
+        i += chunk_size - overlap;
+    }
+
+    Ok(chunks)
+}
+
+/// Split text into sentences on `.`, `?` or `!` followed by whitespace
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+        if matches!(c, '.' | '?' | '!') && chars.peek().is_none_or(|next| next.is_whitespace()) {
+            let sentence = current.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            current.clear();
+        }
+    }
+
+    let sentence = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+
+    sentences
+}
+
+/// Greedily pack whole sentences into chunks of up to `target_size` words,
+/// carrying the last `overlap` sentences into the next chunk.
+/// A single sentence longer than `target_size` becomes its own chunk.
+pub fn chunk_text_sentences(text: &str, target_size: usize, overlap: usize) -> Vec<String> {
+    let sentences = split_sentences(text);
+    let word_counts: Vec<usize> = sentences
+        .iter()
+        .map(|s| s.split_whitespace().count())
+        .collect();
+    let mut chunks = Vec::new();
+
+    let mut i = 0;
+    while i < sentences.len() {
+        let mut end = i;
+        let mut words = 0;
+        while end < sentences.len() && (end == i || words + word_counts[end] <= target_size) {
+            words += word_counts[end];
+            end += 1;
+        }
+
+        chunks.push(sentences[i..end].join(" "));
+
+        if end >= sentences.len() {
+            break;
+        }
+
+        // Always advance by at least one sentence so large overlaps can't loop forever
+        i = end.saturating_sub(overlap).max(i + 1);
+    }
+
+    chunks
+}
+
+/// Generate unit-length embeddings using local model (no API key required), at most
+/// `batch_size` texts per model call
+fn get_embeddings_local(
+    texts: &[String],
+    model: EmbeddingModelChoice,
+    show_progress: bool,
+    batch_size: Option<usize>,
+) -> Result<Vec<Vec<f32>>> {
     if texts.is_empty() {
         return Ok(Vec::new());
     }
     
-    let model = create_embedding_model(show_progress)?;
-    
-    // Convert String to &str for the embedding function
-    let text_refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+    let model_name = model.name();
+    let model = embedding_model(model, show_progress)?
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Embedding model lock poisoned"))?;
+
+    // The model silently truncates inputs past its sequence limit, so at least say so
+    let truncated = texts
+        .iter()
+        .filter(|text| {
+            model.tokenizer
+                .encode(text.as_str(), true)
+                .is_ok_and(|encoding| !encoding.get_overflowing().is_empty())
+        })
+        .count();
+    if truncated > 0 {
+        println!(
+            "[RAG] {} of {} texts exceed {}'s maximum sequence length and will be truncated",
+            truncated, texts.len(), model_name
+        );
+    }
     
-    let embeddings = model.embed(text_refs, None)?;
+    let batch_size = batch_size.unwrap_or(DEFAULT_EMBEDDING_MODEL_BATCH).max(1);
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(batch_size) {
+        // Convert String to &str for the embedding function
+        let text_refs: Vec<&str> = batch.iter().map(|s| s.as_str()).collect();
+        embeddings.extend(model.embed(text_refs, Some(batch_size))?);
+    }
+    for embedding in &mut embeddings {
+        normalize(embedding);
+    }
     
     Ok(embeddings)
 }
 
+/// Hash of chunk content with whitespace normalized, used to spot duplicate chunks
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for word in content.split_whitespace() {
+        word.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Embed and append chunks to the bucket, skipping any whose content is already
+/// stored for the same filename. Returns the number of duplicates skipped.
 pub async fn store_chunks(
     app: &AppHandle,
     bucket_id: &str,
     filename: &str,
     chunks: &[String],
+    model: EmbeddingModelChoice,
     _api_key: &str, // No longer needed, kept for API compatibility
-) -> Result<()> {
+) -> Result<usize> {
     let bucket_path = get_bucket_path(app, bucket_id);
-    let chunks_file = bucket_path.join("chunks.json");
     
     if chunks.is_empty() {
-        return Ok(());
+        return Ok(0);
+    }
+    
+    // Held until the new chunks are saved, so parallel uploads append rather than race
+    let _guard = lock_store(&bucket_path).await?;
+
+    // Load existing chunks
+    // Corrupt stores are recovered by load_chunks; any other failure must not be
+    // papered over here, or saving would overwrite the unreadable store
+    let mut stored_chunks: Vec<Chunk> = load_chunks(&bucket_path, model)?;
+
+    if let Some(other) = stored_chunks.iter().find_map(|c| c.model.filter(|m| *m != model)) {
+        return Err(anyhow::anyhow!(
+            "Bucket holds chunks embedded with {} but {} was requested; re-embed the bucket first",
+            other.name(), model.name()
+        ));
+    }
+    if let Some(existing) = stored_chunks.first() {
+        if existing.embedding.len() != model.dimensions() {
+            return Err(anyhow::anyhow!(
+                "Bucket holds {}-dimension embeddings but {} produces {}; re-embed the bucket first",
+                existing.embedding.len(), model.name(), model.dimensions()
+            ));
+        }
+    }
+
+    let mut seen: HashSet<u64> = stored_chunks
+        .iter()
+        .filter(|c| c.filename == filename)
+        .map(|c| content_hash(&c.content))
+        .collect();
+    let new_chunks: Vec<String> = chunks
+        .iter()
+        .filter(|chunk| seen.insert(content_hash(chunk)))
+        .cloned()
+        .collect();
+    let skipped = chunks.len() - new_chunks.len();
+
+    if skipped > 0 {
+        println!("[RAG] Skipping {} duplicate chunks", skipped);
+    }
+    if new_chunks.is_empty() {
+        return Ok(skipped);
     }
     
-    println!("[RAG] Generating embeddings for {} chunks using local model...", chunks.len());
+    println!("[RAG] Generating embeddings for {} chunks using local model...", new_chunks.len());
     
-    // Get embeddings using local model (show progress on first download)
-    let embeddings = get_embeddings_local(chunks, true)?;
+    // Embed in batches so the UI can show progress on large files
+    let mut embeddings = Vec::with_capacity(new_chunks.len());
+    for batch in new_chunks.chunks(EMBEDDING_BATCH_SIZE) {
+        // Show progress on first model download
+        embeddings.extend(get_embeddings_local(batch, model, true, None)?);
+
+        let _ = app.emit("embedding-progress", EmbeddingProgress {
+            bucket_id: bucket_id.to_string(),
+            filename: filename.to_string(),
+            done_chunks: embeddings.len(),
+            total_chunks: new_chunks.len(),
+        });
+    }
     
     println!("[RAG] Generated {} embeddings", embeddings.len());
     
-    // Load existing chunks
-    let mut stored_chunks: Vec<Chunk> = if chunks_file.exists() {
-        let content = fs::read_to_string(&chunks_file)?;
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Vec::new()
-    };
-    
     // Add new chunks
-    for (chunk, embedding) in chunks.iter().zip(embeddings.iter()) {
+    for (chunk, embedding) in new_chunks.into_iter().zip(embeddings) {
         stored_chunks.push(Chunk {
-            content: chunk.clone(),
+            content: chunk,
             filename: filename.to_string(),
-            embedding: embedding.clone(),
+            embedding,
+            model: Some(model),
         });
     }
     
     // Save chunks
-    let json = serde_json::to_string_pretty(&stored_chunks)?;
-    fs::write(chunks_file, json)?;
+    save_chunks(&bucket_path, &stored_chunks)?;
     
     println!("[RAG] Stored {} total chunks in bucket", stored_chunks.len());
     
-    Ok(())
+    Ok(skipped)
+}
+
+/// Remove chunks that repeat the content of an earlier chunk from the same file.
+/// Returns the number removed and the remaining chunk count per filename.
+pub async fn dedupe_chunks(app: &AppHandle, bucket_id: &str) -> Result<(usize, HashMap<String, usize>)> {
+    let bucket_path = get_bucket_path(app, bucket_id);
+
+    if !bucket_path.exists() {
+        return Ok((0, HashMap::new()));
+    }
+
+    let model = bucket_model(app, bucket_id).await?;
+    let _guard = lock_store(&bucket_path).await?;
+    let mut chunks = load_chunks(&bucket_path, model)?;
+    let before = chunks.len();
+
+    let mut seen: HashSet<(String, u64)> = HashSet::new();
+    chunks.retain(|c| seen.insert((c.filename.clone(), content_hash(&c.content))));
+    let removed = before - chunks.len();
+
+    if removed > 0 {
+        save_chunks(&bucket_path, &chunks)?;
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for chunk in &chunks {
+        *counts.entry(chunk.filename.clone()).or_default() += 1;
+    }
+
+    println!("[RAG] Removed {} duplicate chunks from bucket {}", removed, bucket_id);
+    Ok((removed, counts))
+}
+
+/// Regenerate every stored chunk's embedding with another model, file by file,
+/// emitting the same progress events as uploads, then switch the bucket to that model.
+/// Returns the number of chunks re-embedded.
+pub async fn reembed_chunks(app: &AppHandle, bucket_id: &str, model: EmbeddingModelChoice) -> Result<usize> {
+    let bucket_path = get_bucket_path(app, bucket_id);
+
+    // Held until the bucket record points at the new model, so no upload can append
+    // old-model chunks in between and no search sees the store and record disagree
+    let _guard = lock_store(&bucket_path).await?;
+
+    if !bucket_path.exists() {
+        db::update_bucket_embedding_model(app, bucket_id, model).await?;
+        return Ok(0);
+    }
+
+    let mut chunks = load_chunks(&bucket_path, bucket_model(app, bucket_id).await?)?;
+
+    // Group chunk positions by file, keeping the store's order
+    let mut files: Vec<(String, Vec<usize>)> = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        match files.iter_mut().find(|(filename, _)| *filename == chunk.filename) {
+            Some((_, indices)) => indices.push(i),
+            None => files.push((chunk.filename.clone(), vec![i])),
+        }
+    }
+
+    println!("[RAG] Re-embedding {} chunks in bucket {} with {}", chunks.len(), bucket_id, model.name());
+
+    for (filename, indices) in &files {
+        let mut done = 0;
+        for batch in indices.chunks(EMBEDDING_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|&i| chunks[i].content.clone()).collect();
+            let embeddings = get_embeddings_local(&texts, model, true, None)?;
+
+            for (&i, embedding) in batch.iter().zip(embeddings) {
+                chunks[i].embedding = embedding;
+                chunks[i].model = Some(model);
+            }
+            done += batch.len();
+
+            let _ = app.emit("embedding-progress", EmbeddingProgress {
+                bucket_id: bucket_id.to_string(),
+                filename: filename.clone(),
+                done_chunks: done,
+                total_chunks: indices.len(),
+            });
+        }
+    }
+
+    // Only rewrite the store once every chunk has been embedded with the new model
+    save_chunks(&bucket_path, &chunks)?;
+    db::update_bucket_embedding_model(app, bucket_id, model).await?;
+
+    Ok(chunks.len())
+}
+
+/// Summary of what a bucket's chunk store actually holds
+pub struct ChunkStoreStats {
+    pub chunk_count: usize,
+    pub store_bytes: u64,
+    pub chunks_per_file: HashMap<String, usize>,
+    pub average_chunk_words: f32,
+    /// Length of the stored vectors, if any chunks exist
+    pub dimensions: Option<usize>,
+}
+
+pub async fn chunk_store_stats(app: &AppHandle, bucket_id: &str) -> Result<ChunkStoreStats> {
+    let bucket_path = get_bucket_path(app, bucket_id);
+    let model = bucket_model(app, bucket_id).await?;
+    let chunks = {
+        let _guard = lock_store(&bucket_path).await?;
+        load_chunks(&bucket_path, model)?
+    };
+
+    let store_bytes = fs::metadata(bucket_path.join(CHUNKS_FILE))
+        .map(|m| m.len())
+        .unwrap_or(0);
+
+    let mut chunks_per_file: HashMap<String, usize> = HashMap::new();
+    let mut total_words = 0;
+    for chunk in &chunks {
+        *chunks_per_file.entry(chunk.filename.clone()).or_default() += 1;
+        total_words += chunk.content.split_whitespace().count();
+    }
+
+    Ok(ChunkStoreStats {
+        chunk_count: chunks.len(),
+        store_bytes,
+        chunks_per_file,
+        average_chunk_words: total_words as f32 / chunks.len().max(1) as f32,
+        dimensions: chunks.first().map(|c| c.embedding.len()),
+    })
 }
 
 pub async fn delete_file_chunks(
@@ -197,105 +1621,524 @@ pub async fn delete_file_chunks(
     filename: &str,
 ) -> Result<()> {
     let bucket_path = get_bucket_path(app, bucket_id);
-    let chunks_file = bucket_path.join("chunks.json");
     
-    if !chunks_file.exists() {
+    if !bucket_path.exists() {
         return Ok(());
     }
     
-    let content = fs::read_to_string(&chunks_file)?;
-    let mut chunks: Vec<Chunk> = serde_json::from_str(&content)?;
+    let model = bucket_model(app, bucket_id).await?;
+    let _guard = lock_store(&bucket_path).await?;
+    let mut chunks = load_chunks(&bucket_path, model)?;
     
     chunks.retain(|c| c.filename != filename);
     
-    let json = serde_json::to_string_pretty(&chunks)?;
-    fs::write(chunks_file, json)?;
+    save_chunks(&bucket_path, &chunks)?;
     
     Ok(())
 }
 
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-    let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-    
-    if norm_a == 0.0 || norm_b == 0.0 {
-        return 0.0;
+/// Fail if any chunk was embedded by a model other than the bucket's or has a vector
+/// whose length differs from the query's, either of which would silently produce
+/// meaningless scores
+fn check_embedding_space<'a>(
+    bucket: &Bucket,
+    query_dimensions: usize,
+    chunks: impl IntoIterator<Item = &'a Chunk>,
+) -> Result<()> {
+    for chunk in chunks {
+        if let Some(model) = chunk.model.filter(|m| *m != bucket.embedding_model) {
+            return Err(anyhow::anyhow!(
+                "Bucket '{}' was embedded with {} but is set to search with {}; re-embed the bucket to search it",
+                bucket.name, model.name(), bucket.embedding_model.name()
+            ));
+        }
+        if chunk.embedding.len() != query_dimensions {
+            return Err(anyhow::anyhow!(
+                "Bucket '{}' has {}-dimension embeddings but is set to {} ({} dimensions); re-embed the bucket",
+                bucket.name, chunk.embedding.len(), bucket.embedding_model.name(), query_dimensions
+            ));
+        }
     }
-    
-    dot / (norm_a * norm_b)
+    Ok(())
+}
+
+/// Cosine similarity of two unit-length vectors
+fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
 }
 
 pub async fn search(
     app: &AppHandle,
-    bucket_id: &str,
+    bucket: &Bucket,
     query: &str,
     _api_key: &str, // No longer needed
     top_k: usize,
+    min_score: Option<f32>,
+    rerank: bool,
 ) -> Result<Vec<SearchResult>> {
-    let bucket_path = get_bucket_path(app, bucket_id);
-    let chunks_file = bucket_path.join("chunks.json");
-    
-    println!("[RAG] Looking for chunks file at: {:?}", chunks_file);
-    
-    if !chunks_file.exists() {
-        println!("[RAG] Chunks file does not exist!");
-        return Ok(Vec::new());
+    search_multi(app, std::slice::from_ref(bucket), query, top_k, min_score, rerank).await
+}
+
+/// Search several buckets at once, returning the global top-k hits across all of them
+pub async fn search_multi(
+    app: &AppHandle,
+    buckets: &[Bucket],
+    query: &str,
+    top_k: usize,
+    min_score: Option<f32>,
+    rerank: bool,
+) -> Result<Vec<SearchResult>> {
+    let min_score = min_score.unwrap_or(DEFAULT_MIN_SCORE);
+
+    // When reranking, widen the cosine pool so the reranker has candidates to promote
+    let candidates = if rerank { top_k.saturating_mul(RERANK_CANDIDATE_MULTIPLIER) } else { top_k };
+
+    // Load chunks from every bucket, remembering which bucket each came from, where
+    // each bucket's chunks sit in the combined list and its search index if it has one
+    let mut chunks: Vec<(&Bucket, Chunk)> = Vec::new();
+    let mut bucket_ranges: Vec<(&Bucket, Range<usize>, Option<Arc<index::AnnIndex>>)> = Vec::new();
+    for bucket in buckets {
+        let bucket_path = get_bucket_path(app, &bucket.id);
+        
+        println!("[RAG] Looking for chunk store in: {:?}", bucket_path);
+        
+        if !bucket_path.exists() {
+            println!("[RAG] Bucket store does not exist!");
+            continue;
+        }
+        
+        // The index is fetched, or its build started, under the store lock so it can't be
+        // built from chunks that a concurrent write is about to replace
+        let (bucket_chunks, ann_index) = {
+            let _guard = lock_store(&bucket_path).await?;
+            let bucket_chunks = load_chunks(&bucket_path, bucket.embedding_model)?;
+            // A graph search tops out at ANN_MAX_RESULTS, which caps the reranking pool but
+            // must still cover the results asked for
+            let ann_index = if bucket_chunks.len() >= index::ANN_MIN_CHUNKS && top_k <= index::ANN_MAX_RESULTS {
+                index::get_or_build(&bucket_path, bucket_chunks.len(), || {
+                    bucket_chunks.iter().map(|c| c.embedding.clone()).collect()
+                })?
+            } else {
+                None
+            };
+            (bucket_chunks, ann_index)
+        };
+        println!("[RAG] Loaded {} chunks from bucket {}", bucket_chunks.len(), bucket.id);
+        let start = chunks.len();
+        chunks.extend(bucket_chunks.into_iter().map(|c| (bucket, c)));
+        bucket_ranges.push((bucket, start..chunks.len(), ann_index));
     }
     
-    // Load chunks
-    let content = fs::read_to_string(&chunks_file)?;
-    let chunks: Vec<Chunk> = serde_json::from_str(&content)?;
-    
-    println!("[RAG] Loaded {} chunks from file", chunks.len());
-    
     if chunks.is_empty() {
         println!("[RAG] No chunks found in file");
         return Ok(Vec::new());
     }
     
     // Log first chunk info for debugging
-    if let Some(first) = chunks.first() {
+    if let Some((_, first)) = chunks.first() {
         println!("[RAG] First chunk: file={}, content_len={}, embedding_len={}", 
             first.filename, first.content.len(), first.embedding.len());
     }
     
-    println!("[RAG] Searching {} chunks for: {}...", chunks.len(), &query[..query.len().min(50)]);
+    // Cut at a char boundary; a byte index can land inside a multi-byte character
+    let query_preview = match query.char_indices().nth(50) {
+        Some((end, _)) => &query[..end],
+        None => query,
+    };
+    println!("[RAG] Searching {} chunks for: {}...", chunks.len(), query_preview);
     
-    // Get query embedding using local model (no download progress for searches)
-    let query_embeddings = get_embeddings_local(&[query.to_string()], false)?;
-    let query_embedding = query_embeddings.first()
-        .ok_or_else(|| anyhow::anyhow!("No embedding returned"))?;
+    // Embed the query once per model in use (no download progress for searches),
+    // since each bucket must be compared in the space it was embedded in
+    let mut query_embeddings: HashMap<EmbeddingModelChoice, Vec<f32>> = HashMap::new();
+    for bucket in buckets {
+        if let Entry::Vacant(entry) = query_embeddings.entry(bucket.embedding_model) {
+            let embedding = get_embeddings_local(&[query.to_string()], bucket.embedding_model, false, None)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("No embedding returned"))?;
+            println!("[RAG] Query embedding generated with {}, length: {}", bucket.embedding_model.name(), embedding.len());
+            entry.insert(embedding);
+        }
+    }
     
-    println!("[RAG] Query embedding generated, length: {}", query_embedding.len());
+    for (bucket, range, _) in &bucket_ranges {
+        check_embedding_space(
+            bucket,
+            query_embeddings[&bucket.embedding_model].len(),
+            chunks[range.clone()].iter().map(|(_, c)| c),
+        )?;
+    }
     
-    // Calculate similarities
-    let mut scores: Vec<(usize, f32)> = chunks
-        .iter()
-        .enumerate()
-        .map(|(i, chunk)| {
-            let similarity = cosine_similarity(query_embedding, &chunk.embedding);
-            (i, similarity)
-        })
-        .collect();
+    // Calculate similarities, using a bucket's HNSW index when it is large enough to
+    // have one and it has finished building, and an exact scan otherwise
+    let mut scores: Vec<(usize, f32)> = Vec::new();
+    for (bucket, range, ann_index) in &bucket_ranges {
+        let query_embedding = &query_embeddings[&bucket.embedding_model];
+
+        match ann_index {
+            Some(ann_index) => {
+                println!("[RAG] Using search index for bucket {}", bucket.id);
+                scores.extend(
+                    ann_index
+                        .search(query_embedding, candidates.min(index::ANN_MAX_RESULTS))
+                        .into_iter()
+                        .map(|(i, similarity)| (range.start + i, similarity)),
+                );
+            }
+            None => {
+                scores.extend(range.clone().map(|i| (i, dot_product(query_embedding, &chunks[i].1.embedding))));
+            }
+        }
+    }
     
     // Log top scores before filtering
     scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
     println!("[RAG] Top 3 similarity scores: {:?}", scores.iter().take(3).map(|(_, s)| s).collect::<Vec<_>>());
-    
-    // Take top k results - lowered threshold to 0.1 to be more inclusive
+
+    // Drop weak matches before taking top k so low scores don't crowd out good hits
     let results: Vec<SearchResult> = scores
         .into_iter()
-        .take(top_k)
-        .filter(|(_, score)| *score > 0.1) // Lower threshold to include more results
-        .map(|(i, score)| SearchResult {
-            content: chunks[i].content.clone(),
-            filename: chunks[i].filename.clone(),
-            score,
+        .filter(|(_, score)| *score > min_score)
+        .take(candidates)
+        .map(|(i, score)| {
+            let (bucket, chunk) = &chunks[i];
+            SearchResult {
+                content: chunk.content.clone(),
+                filename: chunk.filename.clone(),
+                bucket_id: bucket.id.clone(),
+                score,
+                rerank_score: None,
+            }
         })
         .collect();
+
+    let results = if rerank {
+        println!("[RAG] Reranking {} candidates", results.len());
+        rerank_results(query, results, top_k)?
+    } else {
+        results
+    };
     
     println!("[RAG] Returning {} relevant results", results.len());
     
     Ok(results)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(model: EmbeddingModelChoice) -> Bucket {
+        Bucket {
+            id: "bucket".to_string(),
+            name: "Notes".to_string(),
+            description: String::new(),
+            tags: Vec::new(),
+            created_at: String::new(),
+            file_count: 1,
+            chunk_size: 500,
+            chunk_overlap: 50,
+            chunk_strategy: ChunkStrategy::default(),
+            embedding_model: model,
+        }
+    }
+
+    fn chunk(dimensions: usize, model: Option<EmbeddingModelChoice>) -> Chunk {
+        Chunk {
+            content: "text".to_string(),
+            filename: "notes.txt".to_string(),
+            embedding: vec![0.0; dimensions],
+            model,
+        }
+    }
+
+    #[test]
+    fn embedding_space_check_accepts_matching_chunks() {
+        let model = EmbeddingModelChoice::BgeSmallEnV15;
+        let chunks = [chunk(384, Some(model)), chunk(384, Some(model))];
+
+        assert!(check_embedding_space(&bucket(model), 384, &chunks).is_ok());
+    }
+
+    #[test]
+    fn embedding_space_check_rejects_other_model_of_same_dimension() {
+        let chunks = [
+            chunk(384, Some(EmbeddingModelChoice::BgeSmallEnV15)),
+            chunk(384, Some(EmbeddingModelChoice::AllMiniLmL6V2)),
+        ];
+
+        let error = check_embedding_space(&bucket(EmbeddingModelChoice::BgeSmallEnV15), 384, &chunks)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("all-MiniLM-L6-v2"), "{}", error);
+    }
+
+    #[test]
+    fn embedding_space_check_rejects_mixed_dimensions() {
+        let model = EmbeddingModelChoice::BgeBaseEnV15;
+        let chunks = [chunk(768, Some(model)), chunk(384, Some(model))];
+
+        let error = check_embedding_space(&bucket(model), 768, &chunks)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("384-dimension"), "{}", error);
+    }
+
+    #[test]
+    fn unstamped_store_is_stamped_with_bucket_model() {
+        let legacy = vec![UnstampedChunk {
+            content: "text".to_string(),
+            filename: "notes.txt".to_string(),
+            embedding: vec![1.0; 384],
+        }];
+        let mut bytes = CHUNKS_MAGIC.to_vec();
+        bytes.extend_from_slice(&(CHUNKS_VERSION - 1).to_le_bytes());
+        bytes.extend(bincode::serialize(&legacy).unwrap());
+
+        let (mut chunks, upgraded) = decode_chunks(&bytes).unwrap();
+        assert!(upgraded);
+        assert!(stamp_unrecorded_chunks(&mut chunks, EmbeddingModelChoice::AllMiniLmL6V2));
+        assert_eq!(chunks[0].model, Some(EmbeddingModelChoice::AllMiniLmL6V2));
+
+        // Switching the bucket to another 384-dimension model must not go unnoticed
+        let other = bucket(EmbeddingModelChoice::BgeSmallEnV15);
+        assert!(check_embedding_space(&other, 384, &chunks).is_err());
+    }
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>() / (norm(a) * norm(b))
+    }
+
+    #[test]
+    fn normalized_dot_product_equals_cosine_similarity() {
+        let pairs = [
+            (vec![3.0, 4.0, 0.0], vec![1.0, 2.0, 2.0]),
+            (vec![0.5, -1.5, 2.0, 0.1], vec![-2.0, 0.3, 0.7, 4.0]),
+            (vec![10.0, 0.0], vec![0.0, 0.2]),
+        ];
+
+        for (mut a, mut b) in pairs {
+            let expected = cosine(&a, &b);
+            normalize(&mut a);
+            normalize(&mut b);
+            assert!((dot_product(&a, &b) - expected).abs() < 1e-6);
+        }
+    }
+
+    /// Scores a query against 5,000 synthetic 384-dimension chunks, as the search loop
+    /// does, with full cosine similarity and with the dot product of normalized vectors.
+    /// Run with `cargo test --release -- --ignored --nocapture` to see the timings.
+    #[test]
+    #[ignore = "timing benchmark"]
+    fn dot_product_search_is_faster_than_cosine() {
+        const CHUNKS: usize = 5_000;
+        const DIMENSIONS: usize = 384;
+        const ROUNDS: usize = 20;
+
+        // Cosine similarity recomputes both norms whether or not the vectors are unit length
+        let chunks = index::tests::random_unit_vectors(CHUNKS, DIMENSIONS, 0x9E37_79B9_7F4A_7C15);
+        let query = index::tests::random_unit_vectors(1, DIMENSIONS, 0xD1B5_4A32_D192_ED03).remove(0);
+
+        let start = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            let scores: Vec<f32> = chunks.iter().map(|chunk| cosine(&query, chunk)).collect();
+            std::hint::black_box(scores);
+        }
+        let cosine_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        for _ in 0..ROUNDS {
+            let scores: Vec<f32> = chunks.iter().map(|chunk| dot_product(&query, chunk)).collect();
+            std::hint::black_box(scores);
+        }
+        let dot_time = start.elapsed();
+
+        println!(
+            "[RAG] {} rounds over {} chunks: cosine {:?}, dot product {:?}",
+            ROUNDS, CHUNKS, cosine_time, dot_time
+        );
+        assert!(dot_time < cosine_time);
+    }
+
+    #[test]
+    fn normalize_leaves_zero_vector_alone() {
+        let mut zero = vec![0.0; 4];
+        normalize(&mut zero);
+        assert_eq!(zero, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn version_one_store_decodes_normalized() {
+        let legacy = vec![UnstampedChunk {
+            content: "text".to_string(),
+            filename: "notes.txt".to_string(),
+            embedding: vec![3.0, 0.0, 4.0],
+        }];
+        let mut bytes = CHUNKS_MAGIC.to_vec();
+        bytes.extend_from_slice(&UNNORMALIZED_CHUNKS_VERSION.to_le_bytes());
+        bytes.extend(bincode::serialize(&legacy).unwrap());
+
+        let (chunks, upgraded) = decode_chunks(&bytes).unwrap();
+        assert!(upgraded);
+        assert_eq!(chunks[0].embedding, vec![0.6, 0.0, 0.8]);
+
+        // The upgraded store round-trips at the current version without further changes
+        let (reloaded, upgraded) = decode_chunks(&encode_chunks(&chunks).unwrap()).unwrap();
+        assert!(!upgraded);
+        assert_eq!(reloaded[0].embedding, chunks[0].embedding);
+    }
+
+    fn temp_bucket() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("omnichat-rag-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn corrupt_legacy_store_is_backed_up_and_bucket_starts_empty() {
+        let dir = temp_bucket();
+        fs::write(dir.join(LEGACY_CHUNKS_FILE), "[{\"content\": \"cut sh").unwrap();
+
+        let first = load_chunks(&dir, EmbeddingModelChoice::AllMiniLmL6V2);
+        let backed_up = dir.join("chunks.json.corrupt").exists();
+        let migrated = dir.join(CHUNKS_FILE).exists();
+        let second = load_chunks(&dir, EmbeddingModelChoice::AllMiniLmL6V2);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(first.unwrap().is_empty());
+        assert!(backed_up);
+        assert!(migrated);
+        assert!(second.unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_store_keeps_the_chunks_that_still_decode() {
+        let model = EmbeddingModelChoice::AllMiniLmL6V2;
+        let chunks: Vec<Chunk> = (0..3)
+            .map(|i| Chunk { content: format!("chunk {}", i), ..chunk(384, Some(model)) })
+            .collect();
+        let bytes = encode_chunks(&chunks).unwrap();
+
+        // Cut the last chunk short, as an interrupted write would
+        let truncated = &bytes[..bytes.len() - 100];
+        let recovered = recover_leading_chunks(truncated);
+        assert_eq!(recovered.iter().map(|c| c.content.as_str()).collect::<Vec<_>>(), ["chunk 0", "chunk 1"]);
+
+        let dir = temp_bucket();
+        fs::write(dir.join(CHUNKS_FILE), truncated).unwrap();
+        let loaded = load_chunks(&dir, model);
+        let backed_up = dir.join("chunks.bin.corrupt").exists();
+        let reloaded = load_chunks(&dir, model);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.unwrap().len(), 2);
+        assert!(backed_up);
+        assert_eq!(reloaded.unwrap().len(), 2);
+    }
+
+    const PARAGRAPHS: &str = "The river rose overnight. Nobody in the village had seen it this high!\n\n\
+        Was the bridge still safe? The engineers thought so, but they were not certain.\n\
+        They closed it anyway.\n\n\
+        By noon the water had fallen again. Life went on.";
+
+    #[test]
+    fn split_sentences_breaks_on_terminators_followed_by_whitespace() {
+        let sentences = split_sentences("Version 1.5 shipped. Really? Yes!\nThe end");
+
+        assert_eq!(sentences, vec!["Version 1.5 shipped.", "Really?", "Yes!", "The end"]);
+    }
+
+    #[test]
+    fn sentence_chunks_never_split_a_sentence() {
+        let sentences = split_sentences(PARAGRAPHS);
+        assert_eq!(sentences.len(), 7);
+
+        for target_size in [1, 5, 12, 20, 100] {
+            for chunk in chunk_text_sentences(PARAGRAPHS, target_size, 1) {
+                // Every chunk is a run of whole sentences, in order
+                let start = sentences.iter().position(|s| chunk.starts_with(s.as_str()))
+                    .unwrap_or_else(|| panic!("chunk doesn't start on a sentence: {}", chunk));
+                let mut rest = chunk.as_str();
+                for sentence in &sentences[start..] {
+                    rest = match rest.strip_prefix(sentence.as_str()) {
+                        Some(after) => after.trim_start(),
+                        None => break,
+                    };
+                }
+                assert!(rest.is_empty(), "sentence split in chunk: {}", chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn sentence_chunks_pack_up_to_target_and_carry_overlap() {
+        let chunks = chunk_text_sentences(PARAGRAPHS, 14, 1);
+
+        assert_eq!(chunks, vec![
+            "The river rose overnight. Nobody in the village had seen it this high!",
+            "Nobody in the village had seen it this high! Was the bridge still safe?",
+            "Was the bridge still safe? The engineers thought so, but they were not certain.",
+            "The engineers thought so, but they were not certain. They closed it anyway.",
+            "They closed it anyway. By noon the water had fallen again. Life went on.",
+        ]);
+        for chunk in &chunks {
+            assert!(chunk.split_whitespace().count() <= 14, "{}", chunk);
+        }
+    }
+
+    #[test]
+    fn sentence_chunks_without_overlap_cover_text_once() {
+        let chunks = chunk_text_sentences(PARAGRAPHS, 20, 0);
+
+        assert_eq!(chunks.join(" "), split_sentences(PARAGRAPHS).join(" "));
+    }
+
+    /// Wrap paragraph XML in a minimal `document.xml`
+    fn document(body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>{}</w:body></w:document>"#,
+            body
+        )
+    }
+
+    #[test]
+    fn docx_text_renders_lists_as_indented_bullets() {
+        let xml = document(concat!(
+            "<w:p><w:r><w:t>Shopping</w:t></w:r></w:p>",
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Fruit</w:t></w:r></w:p>"#,
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Apples</w:t></w:r></w:p>"#,
+        ));
+
+        assert_eq!(docx_text(&xml).unwrap(), "Shopping\n- Fruit\n  - Apples\n");
+    }
+
+    #[test]
+    fn docx_text_renders_table_rows_as_tab_separated_cells() {
+        let xml = document(concat!(
+            "<w:tbl>",
+            "<w:tr><w:tc><w:p><w:r><w:t>Name</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Qty</w:t></w:r></w:p></w:tc></w:tr>",
+            "<w:tr><w:tc><w:p><w:r><w:t>Pears</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>3</w:t></w:r></w:p></w:tc></w:tr>",
+            "</w:tbl>",
+        ));
+
+        assert_eq!(docx_text(&xml).unwrap(), "Name\tQty\nPears\t3\n");
+    }
+
+    #[test]
+    fn docx_text_keeps_literal_tabs_but_not_tab_stops() {
+        let xml = document(concat!(
+            r#"<w:p><w:pPr><w:tabs><w:tab w:val="left" w:pos="720"/></w:tabs></w:pPr>"#,
+            "<w:r><w:t>Total</w:t><w:tab/><w:t>42</w:t></w:r></w:p>",
+        ));
+
+        assert_eq!(docx_text(&xml).unwrap(), "Total\t42\n");
+    }
+
+    #[test]
+    fn docx_text_unescapes_entities() {
+        let xml = document(r#"<w:p><w:r><w:t xml:space="preserve">if a &lt; b &amp;&amp; c &#62; d</w:t></w:r></w:p>"#);
+
+        assert_eq!(docx_text(&xml).unwrap(), "if a < b && c > d\n");
+    }
+}
@@ -149,11 +149,6 @@ export function ChatWindow() {
     }
   }, [folderPopoverOpen]);
 
-  const trimSnippet = (content: string, maxLength = 400) => {
-    if (content.length <= maxLength) return content;
-    return `${content.slice(0, maxLength)}...`;
-  };
-
   const buildContext = async (query: string) => {
     if (selectedBucketIds.length === 0) {
       return { context: undefined, sources: undefined };
@@ -175,7 +170,7 @@ export function ChatWindow() {
       const sources = results.map((result) => ({
         filename: result.filename,
         score: result.score,
-        content: trimSnippet(result.content),
+        content: result.content,
       }));
 
       return { context, sources };
@@ -332,9 +327,15 @@ export function ChatWindow() {
         setInput((prev) => (prev ? `${prev} ${cleaned}` : cleaned));
       }
     } catch (error) {
+      // Transcription errors arrive as { kind, message } objects
+      const failure = error as { kind?: string; message?: string };
+      const description =
+        failure?.kind === "cancelled"
+          ? "Transcription was cancelled."
+          : failure?.message ?? String(error);
       toast({
         title: "Transcription failed",
-        description: String(error),
+        description,
         variant: "destructive",
       });
     }
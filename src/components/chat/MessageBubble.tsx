@@ -16,6 +16,12 @@ import { MarkdownRenderer } from "@/components/preview/MarkdownRenderer";
 import { cn, getProviderIcon } from "@/lib/utils";
 import { Button } from "@/components/ui/Button";
 
+// Sources keep the full chunk; only an excerpt is shown under the reply
+const trimSnippet = (content: string, maxLength = 400) => {
+  if (content.length <= maxLength) return content;
+  return `${content.slice(0, maxLength)}...`;
+};
+
 interface MessageBubbleProps {
   message: Message;
   isLast?: boolean;
@@ -111,6 +117,11 @@ export function MessageBubble({
             <span className="text-xs text-muted-foreground/60 font-medium">
               {message.model}
             </span>
+            {message.partial && !isStreaming && (
+              <span className="text-xs text-amber-600 dark:text-amber-400 font-medium">
+                Incomplete
+              </span>
+            )}
           </div>
         )}
 
@@ -174,7 +185,7 @@ export function MessageBubble({
                       </span>
                     </div>
                     <p className="text-muted-foreground whitespace-pre-wrap leading-relaxed">
-                      {source.content}
+                      {trimSnippet(source.content)}
                     </p>
                   </div>
                 ))}
@@ -133,6 +133,7 @@ export function SettingsDialog() {
         binaryPath: whisperForm.binaryPath.trim(),
         modelPath: whisperForm.modelPath.trim(),
         language: whisperForm.language.trim() || "en",
+        threads: whisperForm.threads,
       });
       setWhisperDownloadError("");
     } catch (error) {
@@ -471,7 +472,7 @@ export function SettingsDialog() {
                         Language (optional)
                       </label>
                       <Input
-                        placeholder="en"
+                        placeholder="en, or auto to detect"
                         value={whisperForm.language}
                         onChange={(e) =>
                           setWhisperForm((prev) => ({
@@ -481,6 +482,25 @@ export function SettingsDialog() {
                         }
                       />
                     </div>
+                    <div className="space-y-2">
+                      <label className="text-xs text-muted-foreground">
+                        Threads (optional)
+                      </label>
+                      <Input
+                        type="number"
+                        min={1}
+                        placeholder="All cores"
+                        value={whisperForm.threads ?? ""}
+                        onChange={(e) =>
+                          setWhisperForm((prev) => ({
+                            ...prev,
+                            threads: e.target.value
+                              ? Math.max(1, Number(e.target.value))
+                              : undefined,
+                          }))
+                        }
+                      />
+                    </div>
                   </>
                 )}
                 <div className="flex items-center gap-2">
@@ -11,6 +11,29 @@ export interface Message {
   model: string;
   created_at: string;
   sources?: SourceReference[];
+  favorited?: boolean;
+  context_used?: string | null;
+  attachments?: { filename: string; size_bytes: number }[];
+  partial?: boolean;
+}
+
+export interface Attachment {
+  filename: string;
+  content: string;
+}
+
+export interface TagCount {
+  tag: string;
+  count: number;
+}
+
+export interface ConversationPage {
+  conversations: Conversation[];
+  total_count: number;
+}
+
+export interface FavoriteMessage extends Message {
+  conversation_title: string;
 }
 
 export interface Conversation {
@@ -21,6 +44,14 @@ export interface Conversation {
   pinned?: boolean;
   tags?: string[];
   folder?: string | null;
+  archived?: boolean;
+  deleted_at?: string | null;
+  locked_model?: ModelLock | null;
+}
+
+export interface ModelLock {
+  provider: string;
+  model: string;
 }
 
 export interface ConversationSearchResult {
@@ -31,6 +62,7 @@ export interface ConversationSearchResult {
   pinned: boolean;
   tags: string[];
   folder?: string | null;
+  archived?: boolean;
 }
 
 export interface SourceReference {
@@ -78,31 +110,50 @@ interface ChatState {
   selectedProvider: Provider;
   selectedModel: string;
   selectedBucketIds: string[];
+  autoTrim: boolean;
 
   // Actions
   initializeDatabase: () => Promise<void>;
   loadConversations: () => Promise<void>;
+  getConversation: (conversationId: string) => Promise<Conversation | null>;
+  getConversationsPage: (
+    offset: number,
+    limit: number,
+    folder?: string | null,
+    tag?: string | null
+  ) => Promise<ConversationPage | null>;
   loadMessages: (conversationId: string) => Promise<void>;
   createConversation: (title?: string) => Promise<string>;
   deleteConversation: (id: string) => Promise<void>;
   setConversationPinned: (id: string, pinned: boolean) => Promise<void>;
+  setConversationArchived: (id: string, archived: boolean) => Promise<void>;
+  setConversationLockedModel: (id: string, lockedModel: ModelLock | null) => Promise<void>;
   updateConversationTags: (id: string, tags: string[]) => Promise<void>;
   updateConversationFolder: (id: string, folder?: string | null) => Promise<void>;
+  renameFolder: (oldName: string, newName: string) => Promise<number>;
+  getAllTags: () => Promise<TagCount[]>;
+  renameTag: (oldTag: string, newTag: string) => Promise<number>;
+  deleteTag: (tag: string) => Promise<number>;
+  deleteFolder: (name: string, reassignTo?: string | null) => Promise<number>;
   updateConversationTitle: (id: string, title: string) => Promise<void>;
   cloneConversation: (id: string, title: string) => Promise<string>;
   updateMessageContent: (messageId: string, content: string) => Promise<void>;
+  toggleMessageFavorite: (messageId: string) => Promise<void>;
+  getFavoriteMessages: () => Promise<FavoriteMessage[]>;
   selectConversation: (id: string) => Promise<void>;
   sendMessage: (
     content: string,
     apiKey: string,
     context?: string,
-    sources?: SourceReference[]
+    sources?: SourceReference[],
+    attachments?: Attachment[]
   ) => Promise<void>;
   sendMessageStream: (
     content: string,
     apiKey: string,
     context?: string,
-    sources?: SourceReference[]
+    sources?: SourceReference[],
+    attachments?: Attachment[]
   ) => Promise<void>;
   setupStreamListeners: () => Promise<UnlistenFn[]>;
   stopStreaming: () => void;
@@ -122,12 +173,35 @@ interface ChatState {
   ) => Promise<void>;
   searchConversations: (query: string) => Promise<ConversationSearchResult[]>;
   exportConversation: (conversationId: string, filePath: string) => Promise<void>;
+  exportConversationWithSources: (conversationId: string, filePath: string) => Promise<void>;
+  exportAllMarkdown: (dirPath: string) => Promise<number>;
   setSelectedProvider: (provider: Provider) => void;
   setSelectedModel: (model: string) => void;
   setSelectedBucketIds: (ids: string[]) => void;
+  setAutoTrim: (autoTrim: boolean) => void;
   clearError: () => void;
 }
 
+// Structured error returned by commands that call a provider
+interface ProviderCommandError {
+  kind: "auth_failed" | "rate_limited" | "invalid_request" | "network" | "empty_response" | "other";
+  message: string;
+  retry_after: number | null;
+}
+
+// Payload of the stream-error event
+interface StreamError extends ProviderCommandError {
+  message_id: string;
+  conversation_id: string;
+}
+
+const formatError = (error: unknown): string => {
+  if (error && typeof error === "object" && "message" in error) {
+    return (error as ProviderCommandError).message;
+  }
+  return String(error);
+};
+
 // Load persisted model selection from localStorage
 const getPersistedSelection = (): { provider: Provider; model: string } => {
   try {
@@ -158,6 +232,7 @@ export const useChatStore = create<ChatState>((set, get) => ({
   selectedProvider: persistedSelection.provider,
   selectedModel: persistedSelection.model,
   selectedBucketIds: [],
+  autoTrim: false,
 
   initializeDatabase: async () => {
     try {
@@ -176,6 +251,34 @@ export const useChatStore = create<ChatState>((set, get) => ({
     }
   },
 
+  getConversation: async (conversationId: string) => {
+    try {
+      return await invoke<Conversation>("get_conversation", { conversationId });
+    } catch (error) {
+      set({ error: `Failed to load conversation: ${error}` });
+      return null;
+    }
+  },
+
+  getConversationsPage: async (
+    offset: number,
+    limit: number,
+    folder?: string | null,
+    tag?: string | null
+  ) => {
+    try {
+      return await invoke<ConversationPage>("get_conversations_page", {
+        offset,
+        limit,
+        folder: folder ?? null,
+        tag: tag ?? null,
+      });
+    } catch (error) {
+      set({ error: `Failed to load conversations: ${error}` });
+      return null;
+    }
+  },
+
   loadMessages: async (conversationId: string) => {
     try {
       const messages = await invoke<Message[]>("get_messages", {
@@ -235,6 +338,36 @@ export const useChatStore = create<ChatState>((set, get) => ({
     }
   },
 
+  setConversationArchived: async (id: string, archived: boolean) => {
+    set((state) => ({
+      conversations: archived
+        ? state.conversations.filter((conversation) => conversation.id !== id)
+        : state.conversations,
+    }));
+
+    try {
+      await invoke("update_conversation_archived", { conversationId: id, archived });
+      await get().loadConversations();
+    } catch (error) {
+      set({ error: `Failed to update conversation archive: ${error}` });
+    }
+  },
+
+  setConversationLockedModel: async (id: string, lockedModel: ModelLock | null) => {
+    set((state) => ({
+      conversations: state.conversations.map((conversation) =>
+        conversation.id === id ? { ...conversation, locked_model: lockedModel } : conversation
+      ),
+    }));
+
+    try {
+      await invoke("update_conversation_locked_model", { conversationId: id, lockedModel });
+    } catch (error) {
+      set({ error: `Failed to update conversation model lock: ${error}` });
+      await get().loadConversations();
+    }
+  },
+
   updateConversationTags: async (id: string, tags: string[]) => {
     set((state) => ({
       conversations: state.conversations.map((conversation) =>
@@ -267,6 +400,62 @@ export const useChatStore = create<ChatState>((set, get) => ({
     }
   },
 
+  renameFolder: async (oldName: string, newName: string) => {
+    try {
+      const moved = await invoke<number>("rename_folder", { oldName, newName });
+      await get().loadConversations();
+      return moved;
+    } catch (error) {
+      set({ error: `Failed to rename folder: ${error}` });
+      return 0;
+    }
+  },
+
+  getAllTags: async () => {
+    try {
+      return await invoke<TagCount[]>("get_all_tags");
+    } catch (error) {
+      set({ error: `Failed to load tags: ${error}` });
+      return [];
+    }
+  },
+
+  renameTag: async (oldTag: string, newTag: string) => {
+    try {
+      const changed = await invoke<number>("rename_tag", { old: oldTag, new: newTag });
+      await get().loadConversations();
+      return changed;
+    } catch (error) {
+      set({ error: `Failed to rename tag: ${error}` });
+      return 0;
+    }
+  },
+
+  deleteTag: async (tag: string) => {
+    try {
+      const changed = await invoke<number>("delete_tag", { tag });
+      await get().loadConversations();
+      return changed;
+    } catch (error) {
+      set({ error: `Failed to delete tag: ${error}` });
+      return 0;
+    }
+  },
+
+  deleteFolder: async (name: string, reassignTo?: string | null) => {
+    try {
+      const moved = await invoke<number>("delete_folder", {
+        name,
+        reassignTo: reassignTo?.trim() ? reassignTo : null,
+      });
+      await get().loadConversations();
+      return moved;
+    } catch (error) {
+      set({ error: `Failed to delete folder: ${error}` });
+      return 0;
+    }
+  },
+
   updateConversationTitle: async (id: string, title: string) => {
     set((state) => ({
       conversations: state.conversations.map((conversation) =>
@@ -320,6 +509,28 @@ export const useChatStore = create<ChatState>((set, get) => ({
     }
   },
 
+  toggleMessageFavorite: async (messageId: string) => {
+    try {
+      const favorited = await invoke<boolean>("toggle_message_favorite", { messageId });
+      set((state) => ({
+        messages: state.messages.map((message) =>
+          message.id === messageId ? { ...message, favorited } : message
+        ),
+      }));
+    } catch (error) {
+      set({ error: `Failed to update favorite: ${error}` });
+    }
+  },
+
+  getFavoriteMessages: async () => {
+    try {
+      return await invoke<FavoriteMessage[]>("get_favorite_messages");
+    } catch (error) {
+      set({ error: `Failed to load favorites: ${error}` });
+      return [];
+    }
+  },
+
   selectConversation: async (id: string) => {
     set({ currentConversationId: id });
     await get().loadMessages(id);
@@ -329,7 +540,8 @@ export const useChatStore = create<ChatState>((set, get) => ({
     content: string,
     apiKey: string,
     context?: string,
-    sources?: SourceReference[]
+    sources?: SourceReference[],
+    attachments: Attachment[] = []
   ) => {
     const { currentConversationId, selectedProvider, selectedModel } = get();
 
@@ -367,6 +579,8 @@ export const useChatStore = create<ChatState>((set, get) => ({
             api_key: apiKey,
             context,
             sources,
+            attachments,
+            auto_trim: get().autoTrim,
           },
         }
       );
@@ -386,7 +600,7 @@ export const useChatStore = create<ChatState>((set, get) => ({
       set((state) => ({
         messages: state.messages.filter((m) => !m.id.startsWith("temp-")),
         isLoading: false,
-        error: `Failed to send message: ${error}`,
+        error: `Failed to send message: ${formatError(error)}`,
       }));
     }
   },
@@ -395,7 +609,8 @@ export const useChatStore = create<ChatState>((set, get) => ({
     content: string,
     apiKey: string,
     context?: string,
-    sources?: SourceReference[]
+    sources?: SourceReference[],
+    attachments: Attachment[] = []
   ) => {
     const { currentConversationId, selectedProvider, selectedModel, isStreaming, isLoading } = get();
 
@@ -439,6 +654,8 @@ export const useChatStore = create<ChatState>((set, get) => ({
           api_key: apiKey,
           context,
           sources,
+          attachments,
+          auto_trim: get().autoTrim,
         },
       });
 
@@ -487,7 +704,7 @@ export const useChatStore = create<ChatState>((set, get) => ({
         messages: state.messages.filter((m) => !m.id.startsWith("temp-")),
         isLoading: false,
         isStreaming: false,
-        error: `Failed to send message: ${error}`,
+        error: `Failed to send message: ${formatError(error)}`,
       }));
     }
   },
@@ -526,13 +743,12 @@ export const useChatStore = create<ChatState>((set, get) => ({
       }
     });
 
-    const unlisten2 = await listen<StreamingChunk>("stream-error", (event) => {
-      const chunk = event.payload;
+    const unlisten2 = await listen<StreamError>("stream-error", (event) => {
       set({
         isStreaming: false,
         streamingMessageId: null,
         streamingContent: "",
-        error: chunk.delta,
+        error: `Error: ${formatError(event.payload)}`,
       });
     });
 
@@ -589,7 +805,7 @@ export const useChatStore = create<ChatState>((set, get) => ({
     } catch (error) {
       set({
         isLoading: false,
-        error: `Failed to regenerate response: ${error}`,
+        error: `Failed to regenerate response: ${formatError(error)}`,
       });
     }
   },
@@ -631,7 +847,7 @@ export const useChatStore = create<ChatState>((set, get) => ({
     } catch (error) {
       set({
         isLoading: false,
-        error: `Failed to compare response: ${error}`,
+        error: `Failed to compare response: ${formatError(error)}`,
       });
     }
   },
@@ -658,6 +874,24 @@ export const useChatStore = create<ChatState>((set, get) => ({
     }
   },
 
+  exportConversationWithSources: async (conversationId: string, filePath: string) => {
+    try {
+      await invoke("export_conversation_with_sources", { conversationId, filePath });
+    } catch (error) {
+      set({ error: `Failed to export conversation: ${error}` });
+      throw error;
+    }
+  },
+
+  exportAllMarkdown: async (dirPath: string) => {
+    try {
+      return await invoke<number>("export_all_markdown", { dirPath });
+    } catch (error) {
+      set({ error: `Failed to export conversations: ${error}` });
+      throw error;
+    }
+  },
+
   setSelectedProvider: (provider: Provider) => {
     set({ selectedProvider: provider });
     // Persist selection
@@ -676,6 +910,10 @@ export const useChatStore = create<ChatState>((set, get) => ({
     set({ selectedBucketIds: ids });
   },
 
+  setAutoTrim: (autoTrim: boolean) => {
+    set({ autoTrim });
+  },
+
   clearError: () => {
     set({ error: null });
   },
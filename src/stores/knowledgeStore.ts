@@ -6,8 +6,10 @@ export interface Bucket {
   id: string;
   name: string;
   description: string;
+  tags?: string[];
   created_at: string;
   file_count: number;
+  embedding_model?: string;
 }
 
 export interface BucketFile {
@@ -18,11 +20,14 @@ export interface BucketFile {
   file_size: number;
   chunk_count: number;
   created_at: string;
+  duplicate_chunks?: number;
+  text_source?: "native" | "ocr";
 }
 
 export interface SearchResult {
   content: string;
   filename: string;
+  bucket_id: string;
   score: number;
 }
 
@@ -38,9 +43,13 @@ interface KnowledgeState {
   loadBuckets: () => Promise<void>;
   createBucket: (name: string, description: string) => Promise<void>;
   deleteBucket: (id: string) => Promise<void>;
+  updateBucket: (id: string, name: string, description: string) => Promise<void>;
+  updateBucketTags: (id: string, tags: string[]) => Promise<void>;
+  getBucketsByTag: (tag: string) => Promise<Bucket[]>;
   selectBucket: (id: string | null) => Promise<void>;
   loadBucketFiles: (bucketId: string) => Promise<void>;
-  uploadFile: (bucketId: string) => Promise<void>;
+  uploadFile: (bucketId: string, ocr?: boolean) => Promise<void>;
+  ingestUrl: (bucketId: string, url: string) => Promise<void>;
   deleteFile: (bucketId: string, fileId: string, filename: string) => Promise<void>;
   searchBucket: (bucketId: string, query: string) => Promise<SearchResult[]>;
   searchMultipleBuckets: (bucketIds: string[], query: string) => Promise<SearchResult[]>;
@@ -90,6 +99,40 @@ export const useKnowledgeStore = create<KnowledgeState>((set, get) => ({
     }
   },
 
+  updateBucket: async (id: string, name: string, description: string) => {
+    try {
+      await invoke("update_bucket", { bucketId: id, name, description });
+      set((state) => ({
+        buckets: state.buckets.map((b) =>
+          b.id === id ? { ...b, name: name.trim(), description: description.trim() } : b
+        ),
+      }));
+    } catch (error) {
+      set({ error: `Failed to update bucket: ${error}` });
+    }
+  },
+
+  updateBucketTags: async (id: string, tags: string[]) => {
+    set((state) => ({
+      buckets: state.buckets.map((b) => (b.id === id ? { ...b, tags } : b)),
+    }));
+
+    try {
+      await invoke("update_bucket_tags", { bucketId: id, tags });
+    } catch (error) {
+      set({ error: `Failed to update bucket tags: ${error}` });
+    }
+  },
+
+  getBucketsByTag: async (tag: string) => {
+    try {
+      return await invoke<Bucket[]>("get_buckets_by_tag", { tag });
+    } catch (error) {
+      set({ error: `Failed to load buckets: ${error}` });
+      return [];
+    }
+  },
+
   selectBucket: async (id: string | null) => {
     set({ selectedBucketId: id, bucketFiles: [] });
     if (id) {
@@ -107,14 +150,14 @@ export const useKnowledgeStore = create<KnowledgeState>((set, get) => ({
     }
   },
 
-  uploadFile: async (bucketId: string) => {
+  uploadFile: async (bucketId: string, ocr = false) => {
     try {
       const result = await open({
         multiple: false,
         filters: [
           {
             name: "Documents",
-            extensions: ["pdf", "docx", "doc", "txt", "md"],
+            extensions: ["pdf", "docx", "doc", "pptx", "xlsx", "txt", "md", "csv", "html", "htm"],
           },
         ],
       });
@@ -130,6 +173,7 @@ export const useKnowledgeStore = create<KnowledgeState>((set, get) => ({
         bucketId,
         filePath: result as string,
         apiKey: "", // Not used anymore, local embeddings
+        ocr,
       });
 
       console.log("File uploaded successfully:", file);
@@ -150,6 +194,28 @@ export const useKnowledgeStore = create<KnowledgeState>((set, get) => ({
     }
   },
 
+  ingestUrl: async (bucketId: string, url: string) => {
+    try {
+      set({ isUploading: true, error: null });
+
+      const file = await invoke<BucketFile>("ingest_url", { bucketId, url });
+
+      set((state) => ({
+        bucketFiles: [file, ...state.bucketFiles],
+        buckets: state.buckets.map((b) =>
+          b.id === bucketId ? { ...b, file_count: b.file_count + 1 } : b
+        ),
+        isUploading: false,
+        error: null,
+      }));
+    } catch (error) {
+      console.error("URL ingest error:", error);
+      const errorMessage = error instanceof Error ? error.message : String(error);
+      set({ error: `Failed to add URL: ${errorMessage}`, isUploading: false });
+      throw error;
+    }
+  },
+
   deleteFile: async (bucketId: string, fileId: string, filename: string) => {
     try {
       await invoke("delete_file", { bucketId, fileId, filename });
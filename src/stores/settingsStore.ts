@@ -13,6 +13,36 @@ interface WhisperConfig {
   binaryPath: string;
   modelPath: string;
   language: string;
+  translate?: boolean;
+  threads?: number;
+}
+
+export interface ProviderStatus {
+  provider: string;
+  has_key: boolean;
+  reachable: boolean | null;
+  error: string | null;
+}
+
+export interface WhisperModelStatus {
+  model_id: string;
+  present: boolean;
+  path: string;
+  size_bytes: number;
+  valid: boolean;
+}
+
+export interface CustomProviderConfig {
+  base_url: string;
+  auth_header_name?: string | null;
+  auth_header_value?: string | null;
+  models: { id: string; name?: string | null; max_tokens?: number | null }[];
+}
+
+export interface AzureProviderConfig {
+  endpoint: string;
+  deployments: string[];
+  api_version?: string | null;
 }
 
 interface SettingsState {
@@ -27,6 +57,18 @@ interface SettingsState {
   loadWhisperConfig: () => Promise<void>;
   setWhisperConfig: (config: WhisperConfig) => Promise<void>;
   downloadWhisperModel: (modelId: string) => Promise<string>;
+  getWhisperModelStatus: (modelId: string) => Promise<WhisperModelStatus>;
+  listWhisperModels: () => Promise<WhisperModelStatus[]>;
+  deleteWhisperModel: (modelId: string, force?: boolean) => Promise<boolean>;
+  verifyWhisperModel: (modelId: string) => Promise<boolean>;
+  getWhisperModelsDiskUsage: () => Promise<number>;
+  getProviderStatus: (check?: boolean) => Promise<ProviderStatus[]>;
+  getCustomProviderConfig: () => Promise<CustomProviderConfig | null>;
+  setCustomProviderConfig: (config: CustomProviderConfig | null) => Promise<CustomProviderConfig | null>;
+  getAzureProviderConfig: () => Promise<AzureProviderConfig | null>;
+  setAzureProviderConfig: (config: AzureProviderConfig | null) => Promise<AzureProviderConfig | null>;
+  getStoreContextUsed: () => Promise<boolean>;
+  setStoreContextUsed: (enabled: boolean) => Promise<void>;
 }
 
 export const useSettingsStore = create<SettingsState>((set, get) => ({
@@ -108,6 +150,8 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
         binaryPath: config.binaryPath,
         modelPath: config.modelPath,
         language: config.language,
+        translate: config.translate ?? false,
+        threads: config.threads ?? null,
       });
       set({ whisperConfig: config });
     } catch (error) {
@@ -125,4 +169,57 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
       throw error;
     }
   },
+
+  getWhisperModelStatus: async (modelId: string) => {
+    return invoke<WhisperModelStatus>("get_whisper_model_status", { modelId });
+  },
+
+  listWhisperModels: async () => {
+    return invoke<WhisperModelStatus[]>("list_whisper_models");
+  },
+
+  deleteWhisperModel: async (modelId: string, force = false) => {
+    const removed = await invoke<boolean>("delete_whisper_model", { modelId, force });
+    if (force) {
+      // Deleting the configured model clears the model path
+      await get().loadWhisperConfig();
+    }
+    return removed;
+  },
+
+  verifyWhisperModel: async (modelId: string) => {
+    return invoke<boolean>("verify_whisper_model", { modelId });
+  },
+
+  getWhisperModelsDiskUsage: async () => {
+    return invoke<number>("get_whisper_models_disk_usage");
+  },
+
+  getProviderStatus: async (check = false) => {
+    return invoke<ProviderStatus[]>("get_provider_status", { check });
+  },
+
+  getCustomProviderConfig: async () => {
+    return invoke<CustomProviderConfig | null>("get_custom_provider_config");
+  },
+
+  setCustomProviderConfig: async (config: CustomProviderConfig | null) => {
+    return invoke<CustomProviderConfig | null>("set_custom_provider_config", { config });
+  },
+
+  getAzureProviderConfig: async () => {
+    return invoke<AzureProviderConfig | null>("get_azure_provider_config");
+  },
+
+  setAzureProviderConfig: async (config: AzureProviderConfig | null) => {
+    return invoke<AzureProviderConfig | null>("set_azure_provider_config", { config });
+  },
+
+  getStoreContextUsed: async () => {
+    return invoke<boolean>("get_store_context_used");
+  },
+
+  setStoreContextUsed: async (enabled: boolean) => {
+    await invoke("set_store_context_used", { enabled });
+  },
 }));